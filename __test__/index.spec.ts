@@ -1,5 +1,25 @@
-import { reSampleBuffers, reSampleInt16Buffer, DataType } from "../index.js";
+import {
+  reSampleBuffers,
+  reSampleEncodedBuffer,
+  reSampleBufferToFlac,
+  reSampleInt16Buffer,
+  reSampleRawBuffer,
+  reSampleContainerBuffer,
+  OutputContainer,
+  probeAudio,
+  rescaleSamplePositions,
+  reSampleAudioFile,
+  reSampleAudioFiles,
+  reSampleDirectory,
+  interleaveAudioFiles,
+  concatAudioFiles,
+  WritePolicy,
+  FileErrorCode,
+  RangeUnit,
+  DataType,
+} from "../index.js";
 import fs, { unlinkSync } from "fs";
+import { tmpdir } from "os";
 import { readFile, writeFile } from "fs/promises";
 import axios from "axios";
 import { resolve } from "path";
@@ -226,6 +246,339 @@ describe("NAPI -  Rubato Module", () => {
   });
 });
 
+describe("NAPI - Native Ogg Vorbis decoding", () => {
+  Object.entries(files_to_resamples)
+    .filter(([_, data]) => data.format === "ogg")
+    .forEach(([_, data]) => {
+      const { channels: channelsStr, sampleRateOutput, expectedSize } = data;
+      test(`OGG ${channelsStr} decoded natively -> ${sampleRateOutput}`, async () => {
+        const input = await readFile(OUT_DIR_FILE(getBaseName(data)));
+        const { buffer, channels } = reSampleEncodedBuffer({
+          inputBuffer: input,
+          sampleRateOutput,
+          typeOfBinData: DataType.F32,
+        });
+        expect(channels).toBe(channelsStr === "mono" ? 1 : 2);
+        // Same output size as the f32 resampling of the sox conversion
+        expect(buffer.length).toBeLessThan(expectedSize + expectedSize * 0.1);
+        expect(buffer.length).toBeGreaterThan(expectedSize - expectedSize * 0.1);
+      }, 60000);
+    });
+});
+
+describe("NAPI - Raw format descriptors", () => {
+  test("s16le:2:44100 -> f32le:2:22050 keeps half the frames", () => {
+    const inputBuffer = Buffer.alloc(44100 * 2 * 2);
+    for (let i = 0; i < 44100; i++) {
+      const value = Math.round(Math.sin(i / 20) * 10000);
+      inputBuffer.writeInt16LE(value, i * 4);
+      inputBuffer.writeInt16LE(value, i * 4 + 2);
+    }
+    const buffer = reSampleRawBuffer({
+      inputBuffer,
+      inputFormat: "s16le:2:44100",
+      outputFormat: "f32le:2:22050",
+    });
+    expect(buffer.length).toBeGreaterThan(22050 * 8 * 0.99);
+    expect(buffer.length).toBeLessThan(22050 * 8 * 1.01);
+  });
+
+  test("Invalid descriptors and channel changes are rejected", () => {
+    const inputBuffer = Buffer.alloc(1024);
+    expect(() =>
+      reSampleRawBuffer({
+        inputBuffer,
+        inputFormat: "s16:2:44100",
+        outputFormat: "f32le:2:22050",
+      })
+    ).toThrow();
+    expect(() =>
+      reSampleRawBuffer({
+        inputBuffer,
+        inputFormat: "s16le:2:44100",
+        outputFormat: "f32le:1:22050",
+      })
+    ).toThrow();
+  });
+});
+
+describe("NAPI - Probing", () => {
+  Object.entries(files_to_resamples).forEach(([_, data]) => {
+    const { channels: channelsStr, format, sampleRateInput } = data;
+    test(`${format} ${channelsStr} is probed from its magic bytes`, async () => {
+      const input = await readFile(OUT_DIR_FILE(getBaseName(data)));
+      const probe = probeAudio(input);
+      expect(probe.container).toBe(format);
+      expect(probe.channels).toBe(channelsStr === "mono" ? 1 : 2);
+      expect(probe.sampleRate).toBe(sampleRateInput);
+      expect(probe.duration).toBeGreaterThan(0);
+    });
+  });
+
+  test("Unknown bytes are raw", () => {
+    expect(probeAudio(Buffer.alloc(256, 1))).toEqual({ container: "raw" });
+  });
+});
+
+describe("NAPI - Container buffers", () => {
+  test("Raw samples are encoded as an in-memory WAV and back", () => {
+    const inputBuffer = Buffer.alloc(48000 * 2);
+    for (let i = 0; i < 48000; i++) {
+      inputBuffer.writeInt16LE(Math.round(Math.sin(i / 10) * 8000), i * 2);
+    }
+    const wav = reSampleContainerBuffer({
+      inputBuffer,
+      inputFormat: "s16le:1:48000",
+      sampleRateOutput: 16000,
+      outputContainer: OutputContainer.Wav,
+      typeOfBinData: DataType.I16,
+    });
+    expect(wav.buffer.subarray(0, 4).toString()).toBe("RIFF");
+    expect(probeAudio(wav.buffer)).toMatchObject({
+      container: "wav",
+      sampleRate: 16000,
+      channels: 1,
+      frames: 16000,
+    });
+
+    const flac = reSampleContainerBuffer({
+      inputBuffer: wav.buffer,
+      sampleRateOutput: 8000,
+      outputContainer: OutputContainer.Flac,
+    });
+    expect(flac.sampleRateInput).toBe(16000);
+    expect(probeAudio(flac.buffer)).toMatchObject({ container: "flac", frames: 8000 });
+  });
+});
+
+describe("NAPI - Ranges", () => {
+  test("A range is the same part of the whole resample", () => {
+    const samples = Float32Array.from({ length: 44100 }, (_, i) => Math.sin(i / 10) / 2);
+    const args = { inputBuffer: Buffer.from(samples.buffer), inputFormat: "f32le:1:44100", outputFormat: "f32le:1:16000" };
+    const floats = (buffer: Buffer) => new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4);
+    const whole = floats(reSampleRawBuffer(args));
+    const part = floats(reSampleRawBuffer({ ...args, start: 11025, end: 22050, rangeUnit: RangeUnit.Frames }));
+    expect(part.length).toBe(4000);
+    part.forEach((sample, i) => expect(sample).toBeCloseTo(whole[4000 + i], 5));
+    expect(() => reSampleRawBuffer({ ...args, start: 0.5, end: 0.25 })).toThrow();
+  });
+
+  test("The raw buffer functions take a range too", () => {
+    const samples = Float32Array.from({ length: 44100 }, (_, i) => Math.sin(i / 10) / 2);
+    const args = {
+      argsAudioToReSample: { channels: 1, sampleRateInput: 44100, sampleRateOutput: 16000 },
+      inputBuffer: Buffer.from(samples.buffer),
+    };
+    const floats = (buffer: Buffer) => new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4);
+    const whole = floats(reSampleBuffers(args));
+    const part = floats(reSampleBuffers({ ...args, start: 0.25, end: 0.5 }));
+    expect(part.length).toBe(4000);
+    part.forEach((sample, i) => expect(sample).toBeCloseTo(whole[4000 + i], 5));
+
+    const int16 = reSampleInt16Buffer({
+      argsAudioToReSample: args.argsAudioToReSample,
+      inputInt16Buffer: Buffer.from(Int16Array.from(samples, (sample) => sample * 32767).buffer),
+      start: 11025,
+      end: 22050,
+      rangeUnit: RangeUnit.Frames,
+    });
+    expect(int16.length).toBe(8000);
+    const flac = reSampleBufferToFlac({ ...args, typeOfBinData: DataType.F32, start: 0.25, end: 0.5 });
+    expect(probeAudio(flac)).toMatchObject({ container: "flac", frames: 4000 });
+  });
+});
+
+describe("NAPI - Sample positions", () => {
+  test("Markers are rescaled to the output rate", () => {
+    expect(
+      rescaleSamplePositions({
+        positions: [0, 3, 44100, 88200],
+        sampleRateInput: 44100,
+        sampleRateOutput: 16000,
+      })
+    ).toEqual([0, 1, 16000, 32000]);
+    expect(() =>
+      rescaleSamplePositions({
+        positions: [-1],
+        sampleRateInput: 44100,
+        sampleRateOutput: 16000,
+      })
+    ).toThrow();
+  });
+});
+
+describe("NAPI - Output files", () => {
+  test("Write policies and missing directories", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const inputRawPath = resolve(dir, "input.raw");
+    fs.writeFileSync(inputRawPath, Buffer.alloc(4 * 8000));
+    const outputPath = resolve(dir, "nested/output.raw");
+    const args = { inputRawPath, outputPath, inputFormat: "f32le:1:8000", outputFormat: "f32le:1:16000" };
+
+    expect(() => reSampleAudioFile(args)).toThrow(
+      expect.objectContaining({ code: FileErrorCode.OutputDirectoryMissing })
+    );
+    expect(reSampleAudioFile({ ...args, createOutputDirectory: true }).skipped).toBe(false);
+    expect(fs.readdirSync(resolve(dir, "nested"))).toEqual(["output.raw"]);
+    expect(() => reSampleAudioFile({ ...args, writePolicy: WritePolicy.ErrorIfExists })).toThrow(
+      expect.objectContaining({ code: FileErrorCode.OutputExists })
+    );
+    expect(reSampleAudioFile({ ...args, writePolicy: WritePolicy.SkipIfUpToDate }).skipped).toBe(true);
+    fs.rmSync(dir, { recursive: true });
+  });
+
+  test("Segments of an output", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const inputRawPath = resolve(dir, "input.raw");
+    fs.writeFileSync(inputRawPath, Buffer.alloc(4 * 20000));
+    const outputPath = resolve(dir, "part-{segment}.raw");
+    const { segments } = reSampleAudioFile({
+      inputRawPath,
+      outputPath,
+      inputFormat: "f32le:1:8000",
+      outputFormat: "f32le:1:16000",
+      segmentDuration: 1,
+    });
+    expect(segments?.map(({ start, frames }) => [start, frames])).toEqual([
+      [0, 16000],
+      [16000, 16000],
+      [32000, 8000],
+    ]);
+    expect(segments?.[2].path).toBe(resolve(dir, "part-002.raw"));
+    expect(fs.statSync(resolve(dir, "part-002.raw")).size).toBe(4 * 8000);
+
+    // Every segment is checked against the write policy, and none is written when one exists
+    const args = { inputRawPath, outputPath, inputFormat: "f32le:1:8000", outputFormat: "f32le:1:16000", segmentDuration: 1 };
+    fs.unlinkSync(resolve(dir, "part-000.raw"));
+    expect(() => reSampleAudioFile({ ...args, writePolicy: WritePolicy.ErrorIfExists })).toThrow(
+      expect.objectContaining({ code: FileErrorCode.OutputExists })
+    );
+    expect(fs.existsSync(resolve(dir, "part-000.raw"))).toBe(false);
+    expect(reSampleAudioFile({ ...args, writePolicy: WritePolicy.SkipIfUpToDate }).skipped).toBe(false);
+    expect(reSampleAudioFile({ ...args, writePolicy: WritePolicy.SkipIfUpToDate }).skipped).toBe(true);
+    fs.rmSync(dir, { recursive: true });
+  });
+
+  test("A file per channel", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const inputRawPath = resolve(dir, "call.raw");
+    // Silent left channel, constant right channel
+    const samples = Float32Array.from({ length: 2 * 8000 }, (_, i) => (i % 2) * 0.5);
+    fs.writeFileSync(inputRawPath, Buffer.from(samples.buffer));
+    const { channelPaths } = reSampleAudioFile({
+      inputRawPath,
+      outputPath: resolve(dir, "call_{channel}.raw"),
+      inputFormat: "f32le:2:8000",
+      outputFormat: "f32le:1:16000",
+      splitChannels: true,
+    });
+    expect(channelPaths).toEqual([resolve(dir, "call_0.raw"), resolve(dir, "call_1.raw")]);
+    const [left, right] = channelPaths!.map((path) => {
+      const buffer = fs.readFileSync(path);
+      return new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4);
+    });
+    expect(left.length).toBe(16000);
+    expect(left.every((sample) => sample === 0)).toBe(true);
+    expect(right[8000]).toBeCloseTo(0.5, 3);
+    fs.rmSync(dir, { recursive: true });
+  });
+
+  test("A batch of files", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const jobs = [1, 2, 3].map((seconds) => {
+      const inputRawPath = resolve(dir, `input-${seconds}.raw`);
+      fs.writeFileSync(inputRawPath, Buffer.alloc(4 * 8000 * seconds));
+      return { inputRawPath, outputPath: resolve(dir, `output-${seconds}.raw`), inputFormat: "f32le:1:8000", outputFormat: "f32le:1:16000" };
+    });
+    const missing = { ...jobs[0], inputRawPath: resolve(dir, "missing.raw") };
+    const results = reSampleAudioFiles({ jobs: [...jobs, missing], threads: 2 });
+    expect(results.map(({ success }) => success)).toEqual([true, true, true, false]);
+    expect(results[3].errorCode).toBe(FileErrorCode.GenericFailure);
+    expect(results[3].error).toContain("missing.raw");
+    results.forEach(({ durationMs }) => expect(durationMs).toBeGreaterThanOrEqual(0));
+    expect(fs.statSync(resolve(dir, "output-3.raw")).size).toBe(4 * 16000 * 3);
+    fs.rmSync(dir, { recursive: true });
+  });
+
+  test("A directory mirrored and converted again incrementally", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const inputDirectory = resolve(dir, "calls");
+    fs.mkdirSync(resolve(inputDirectory, "2024"), { recursive: true });
+    fs.writeFileSync(resolve(inputDirectory, "a.raw"), Buffer.alloc(4 * 8000));
+    fs.writeFileSync(resolve(inputDirectory, "2024/b.raw"), Buffer.alloc(4 * 8000));
+    fs.writeFileSync(resolve(inputDirectory, "notes.txt"), "not audio");
+    const args = {
+      inputDirectory,
+      outputDirectory: resolve(dir, "calls-16k"),
+      include: ["**/*.raw"],
+      inputFormat: "f32le:1:8000",
+      outputFormat: "f32le:1:16000",
+    };
+
+    const first = reSampleDirectory(args);
+    expect([first.converted, first.skipped, first.failed]).toEqual([2, 0, 0]);
+    expect(first.files.map(({ outputPath }) => outputPath)).toEqual([
+      resolve(dir, "calls-16k/2024/b.raw"),
+      resolve(dir, "calls-16k/a.raw"),
+    ]);
+    expect(fs.statSync(resolve(dir, "calls-16k/2024/b.raw")).size).toBe(4 * 16000);
+    const second = reSampleDirectory(args);
+    expect([second.converted, second.skipped, second.failed]).toEqual([0, 2, 0]);
+    fs.rmSync(dir, { recursive: true });
+  });
+
+  test("Mono inputs of different rates interleaved", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const telephony = resolve(dir, "leg-8k.raw");
+    const webrtc = resolve(dir, "leg-48k.raw");
+    fs.writeFileSync(telephony, Buffer.from(new Float32Array(8000).fill(0.25).buffer));
+    fs.writeFileSync(webrtc, Buffer.from(new Float32Array(24000).fill(-0.25).buffer));
+    const outputPath = resolve(dir, "call.raw");
+    const interleaved = interleaveAudioFiles({
+      inputs: [
+        { path: telephony, inputFormat: "f32le:1:8000" },
+        { path: webrtc, inputFormat: "f32le:1:48000" },
+      ],
+      outputPath,
+      outputFormat: "f32le:2:16000",
+    });
+    expect(interleaved).toEqual({ channels: 2, frames: 16000 });
+    const buffer = fs.readFileSync(outputPath);
+    const samples = new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4);
+    expect(samples.length).toBe(2 * 16000);
+    expect(samples[2 * 4000]).toBeCloseTo(0.25, 3);
+    expect(samples[2 * 4000 + 1]).toBeCloseTo(-0.25, 3);
+    // The 0.5 s leg is padded with silence up to the end of the 1 s one
+    expect(samples[2 * 12000 + 1]).toBe(0);
+    fs.rmSync(dir, { recursive: true });
+  });
+
+  test("Clips of different rates concatenated", () => {
+    const dir = fs.mkdtempSync(resolve(tmpdir(), "resampler-"));
+    const greeting = resolve(dir, "greeting.raw");
+    const message = resolve(dir, "message.raw");
+    fs.writeFileSync(greeting, Buffer.from(new Float32Array(8000).fill(0.25).buffer));
+    fs.writeFileSync(message, Buffer.from(new Float32Array(2 * 44100).fill(-0.25).buffer));
+    const outputPath = resolve(dir, "playlist.raw");
+    const concatenated = concatAudioFiles({
+      inputs: [
+        { path: greeting, inputFormat: "f32le:1:8000" },
+        { path: message, inputFormat: "f32le:2:44100" },
+      ],
+      outputPath,
+      outputFormat: "f32le:1:16000",
+      silence: 0.5,
+    });
+    expect(concatenated).toEqual({ channels: 1, frames: 40000, starts: [0, 24000] });
+    const buffer = fs.readFileSync(outputPath);
+    const samples = new Float32Array(buffer.buffer, buffer.byteOffset, buffer.length / 4);
+    expect(samples.length).toBe(40000);
+    expect(samples[20000]).toBe(0);
+    expect(samples[32000]).toBeCloseTo(-0.25, 3);
+    fs.rmSync(dir, { recursive: true });
+  });
+});
+
 /**
  * Will download the entry fiel for test, will not re-dl it if already present
  * @param url link to .wav/ogg file to download
@@ -15,20 +15,422 @@ export const enum DataType {
   I16 = 0,
   F32 = 1
 }
+/**
+ * Container of the resampled file, raw samples of `type_of_bin_data` unless `output_path` ends with
+ * `.flac`, `.opus`, `.aif`, `.aiff`, `.aifc`, `.wav`, `.rf64`, `.w64`, `.au` or `.snd`. FLAC and
+ * AIFF keep 16 bits for `DataType.I16` and 24 bits for `DataType.F32`, AIFF-C keeps little-endian 16
+ * bits (`sowt`) or 32-bit floats (`fl32`), the WAV and AU containers keep `type_of_bin_data`. A WAV
+ * file becomes RF64 when its samples exceed 4 GB and keeps the metadata chunks (`LIST`, `bext`,
+ * `iXML`, `cue `, `smpl`...) of a WAV input, their sample positions rescaled
+*/
+export const enum OutputContainer {
+  Raw = 0,
+  Flac = 1,
+  /** Needs an output sample rate of 48000, 24000, 16000, 12000 or 8000 and the `opus` feature */
+  Opus = 2,
+  Aiff = 3,
+  Aifc = 4,
+  Wav = 5,
+  Rf64 = 6,
+  Wave64 = 7,
+  Au = 8
+}
+/**
+ * `input_raw_path` can also be a `.flac`, an Ogg Vorbis `.ogg`, an `.mp3`, an Ogg Opus `.opus`, an
+ * AIFF `.aif` / `.aiff` / `.aifc`, a WAV `.wav` / `.rf64` / `.w64`, an AU `.au` / `.snd` or a CAF
+ * `.caf` file, its sample rate and channels are then taken from the file header instead of
+ * `args_audio_to_re_sample`. Opus is always decoded at 48 kHz.
+ *
+ * Raw samples are described either by `args_audio_to_re_sample` and `type_of_bin_data` or by the
+ * `input_format` and `output_format` descriptors, like `s16le:2:44100`. An `input_format` of `auto`
+ * finds the container from the first bytes of the file instead of its extension
+*/
 export interface ArgsAudioFile {
-  argsAudioToReSample: ArgsAudioToReSample
+  argsAudioToReSample?: ArgsAudioToReSample
   inputRawPath: string
   outputPath: string
-  typeOfBinData: DataType
+  typeOfBinData?: DataType
+  /** Raw input as `<format>:<channels>:<rate>`, like `s16le:2:44100`, `f32be:1:48000` or `mulaw:1:8000` */
+  inputFormat?: string
+  /** Raw output as `<format>:<channels>:<rate>`, the rate being the one to resample to */
+  outputFormat?: string
+  outputContainer?: OutputContainer
+  /** FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default */
+  compressionLevel?: number
+  /** How the output is flushed to the disk before and after it replaces `output_path`, `File` by default */
+  outputSync?: OutputSync
+  /** What happens when `output_path` already exists, `Overwrite` by default */
+  writePolicy?: WritePolicy
+  /** Creates the missing directories of `output_path` instead of throwing `OutputDirectoryMissing` */
+  createOutputDirectory?: boolean
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
+  /**
+   * Splits the output into files of this many seconds, named after `output_path` with its
+   * `{segment}` replaced by `000`, `001`..., or with `_000`, `_001`... before its extension
+   */
+  segmentDuration?: number
+  /** Seconds at the end of each segment repeated at the start of the next one */
+  segmentOverlap?: number
+  /** Moves each cut between segments to the closest zero crossing, at most 10 ms away */
+  segmentAtZeroCrossing?: boolean
+  /**
+   * Writes each channel to its own mono file, named after `output_path` with its `{channel}`
+   * replaced by `0`, `1`..., or with `_0`, `_1`... before its extension
+   */
+  splitChannels?: boolean
+}
+/**
+ * Unit of the `start` and `end` of the part of an input to resample. The part is resampled with
+ * enough of the audio around it to be the same as the matching part of the resampled whole input,
+ * the uncompressed inputs (raw, WAV, AIFF, AU, CAF LPCM) seeking straight to it
+ */
+export const enum RangeUnit {
+  Seconds = 0,
+  Frames = 1
+}
+/**
+ * The output is written to a hidden temporary file next to `output_path` and renamed over it once
+ * complete, so `output_path` never holds partial audio. `OutputSync` chooses what is flushed to the
+ * disk around the rename: nothing (`None`, fastest, the rename may then reach the disk before the
+ * samples), the samples before the rename (`File`) or also the rename itself (`Directory`)
+ */
+export const enum OutputSync {
+  None = 0,
+  File = 1,
+  Directory = 2
+}
+/**
+ * What happens when `output_path` already exists: it is replaced (`Overwrite`), the call throws an
+ * `OutputExists` error (`ErrorIfExists`), or nothing is resampled when it was modified after the
+ * input (`SkipIfUpToDate`)
+ */
+export const enum WritePolicy {
+  Overwrite = 0,
+  ErrorIfExists = 1,
+  SkipIfUpToDate = 2
+}
+/**
+ * `code` of the errors thrown by `reSampleAudioFile`, `interleaveAudioFiles` and `concatAudioFiles`,
+ * and of the failed jobs of `reSampleAudioFiles`
+ */
+export const enum FileErrorCode {
+  /** `output_path` exists and the write policy is `ErrorIfExists` */
+  OutputExists = 'OutputExists',
+  /** The directory of `output_path` does not exist and `create_output_directory` is not set */
+  OutputDirectoryMissing = 'OutputDirectoryMissing',
+  /** The directory of `output_path` cannot be created, or the output cannot be written there */
+  OutputNotWritable = 'OutputNotWritable',
+  /** Any other error, about the input, the formats or the resampling */
+  GenericFailure = 'GenericFailure'
+}
+export interface ReSampledAudioFile {
+  /** Sample rate of the input, found in the header of encoded files */
+  sampleRateInput: number
+  /** Channels of the input, kept in the output */
+  channels: number
+  /** The output was up to date and left untouched, with `WritePolicy.SkipIfUpToDate` */
+  skipped: boolean
+  /** The files written with `segment_duration`, in order */
+  segments?: Array<AudioSegment>
+  /** The mono file of each channel written with `split_channels` */
+  channelPaths?: Array<string>
+}
+export interface AudioSegment {
+  path: string
+  /** First frame of the segment in the whole output */
+  start: number
+  frames: number
+}
+/** Errors about `output_path` have the `code` of a `FileErrorCode`, the other ones `GenericFailure` */
+export function reSampleAudioFile(args: ArgsAudioFile): ReSampledAudioFile
+export interface ArgsAudioFileBatch {
+  jobs: Array<ArgsAudioFile>
+  /** Worker threads running the jobs, one per CPU by default */
+  threads?: number
+}
+export interface AudioFileJobResult {
+  success: boolean
+  /** What `reSampleAudioFile` returns for the job, when it succeeded */
+  result?: ReSampledAudioFile
+  /** `code` of the error of a failed job */
+  errorCode?: FileErrorCode
+  /** Message of the error of a failed job */
+  error?: string
+  /** Milliseconds spent on the job */
+  durationMs: number
+}
+/**
+ * Runs `reSampleAudioFile` for every job across native worker threads and returns the result of
+ * each job, in order. A failed job does not stop the other ones
+ */
+export function reSampleAudioFiles(args: ArgsAudioFileBatch): Array<AudioFileJobResult>
+export interface ArgsAudioDirectory {
+  inputDirectory: string
+  /** Directory of the outputs, mirroring the paths of the inputs under `input_directory` */
+  outputDirectory: string
+  /**
+   * Globs selecting the inputs by their path relative to `input_directory`, like `**/*.wav`,
+   * `*` not crossing `/`
+   */
+  include?: Array<string>
+  /**
+   * Extensions selecting the inputs, like `wav` or `.flac`, ignoring case. Without `include` nor
+   * `extensions`, every file of a known container is selected, or every file with `input_format`
+   */
+  extensions?: Array<string>
+  /** Extension replacing the one of the inputs in the output paths, like `flac` */
+  outputExtension?: string
+  argsAudioToReSample?: ArgsAudioToReSample
+  typeOfBinData?: DataType
+  /** Raw inputs as `<format>:<channels>:<rate>`, or `auto` to find the container of each input from its magic bytes */
+  inputFormat?: string
+  /** Raw outputs as `<format>:<channels>:<rate>`, the rate being the one to resample to */
+  outputFormat?: string
+  outputContainer?: OutputContainer
+  /** FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default */
+  compressionLevel?: number
+  /** How each output is flushed to the disk before and after it replaces its path, `File` by default */
+  outputSync?: OutputSync
+  /** What happens to the outputs that already exist, `SkipIfUpToDate` by default */
+  writePolicy?: WritePolicy
+  /** Worker threads converting the files, one per CPU by default */
+  threads?: number
+}
+export interface DirectoryFileResult {
+  inputPath: string
+  outputPath: string
+  result: AudioFileJobResult
+}
+export interface ReSampledDirectory {
+  /** Every selected input, sorted by path, and the entries that could not be listed as failed ones */
+  files: Array<DirectoryFileResult>
+  converted: number
+  /** Outputs left untouched because they were up to date */
+  skipped: number
+  failed: number
+}
+/**
+ * Resamples the files of a directory selected by globs or extensions to the same paths under an
+ * output directory, across native worker threads, skipping by default the outputs modified after
+ * their input. A failed file does not stop the other ones
+ */
+export function reSampleDirectory(args: ArgsAudioDirectory): ReSampledDirectory
+export interface AudioFileInput {
+  path: string
+  /**
+   * Raw input as `<format>:<channels>:<rate>`, or `auto` to find the container from the magic
+   * bytes, the container of the extension by default
+   */
+  inputFormat?: string
+}
+export interface ArgsInterleaveAudioFiles {
+  /** Inputs of the channels of the output, in order, each one adding its channels */
+  inputs: Array<AudioFileInput>
+  outputPath: string
+  /** Sample rate every input is resampled to, the one of `output_format` by default */
+  sampleRateOutput?: number
+  typeOfBinData?: DataType
+  /** Raw output as `<format>:<channels>:<rate>`, with the channels of all the inputs */
+  outputFormat?: string
+  outputContainer?: OutputContainer
+  /** FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default */
+  compressionLevel?: number
+  /** How the output is flushed to the disk before and after it replaces `output_path`, `File` by default */
+  outputSync?: OutputSync
+  /** Creates the missing directories of `output_path` instead of throwing `OutputDirectoryMissing` */
+  createOutputDirectory?: boolean
+}
+export interface InterleavedAudioFile {
+  channels: number
+  /** Frames of the output, the ones of the longest input once resampled */
+  frames: number
 }
-export function reSampleAudioFile(args: ArgsAudioFile): void
+/**
+ * Resamples inputs of any rate, like the mono legs of a call, to a common rate and writes them as
+ * the channels of one output, the shorter ones padded with silence. The inputs are streamed side by
+ * side, errors have the `code` of a `FileErrorCode`
+ */
+export function interleaveAudioFiles(args: ArgsInterleaveAudioFiles): InterleavedAudioFile
+export interface ArgsConcatAudioFiles {
+  /** Inputs to join, in order */
+  inputs: Array<AudioFileInput>
+  outputPath: string
+  /** Sample rate every input is resampled to, the one of `output_format` by default */
+  sampleRateOutput?: number
+  /** Channels of the output, the ones of `output_format` or else of the first input by default */
+  channels?: number
+  /** Seconds of silence between two inputs */
+  silence?: number
+  /** Seconds over which an input fades out while the next one fades in, instead of `silence` */
+  crossfade?: number
+  typeOfBinData?: DataType
+  /** Raw output as `<format>:<channels>:<rate>` */
+  outputFormat?: string
+  outputContainer?: OutputContainer
+  /** FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default */
+  compressionLevel?: number
+  /** How the output is flushed to the disk before and after it replaces `output_path`, `File` by default */
+  outputSync?: OutputSync
+  /** Creates the missing directories of `output_path` instead of throwing `OutputDirectoryMissing` */
+  createOutputDirectory?: boolean
+}
+export interface ConcatenatedAudioFile {
+  channels: number
+  frames: number
+  /** Output frame where each input starts */
+  starts: Array<number>
+}
+/**
+ * Resamples inputs of any rate and channels, like the clips of a playlist, to a common rate and
+ * channel layout and writes them one after the other to one output, with silence or a crossfade
+ * between them. Mono inputs are copied to every channel and inputs down to mono are averaged.
+ * Errors have the `code` of a `FileErrorCode`
+ */
+export function concatAudioFiles(args: ArgsConcatAudioFiles): ConcatenatedAudioFile
 export interface ArgsAudioBuffer {
   argsAudioToReSample: ArgsAudioToReSample
   inputBuffer: Buffer
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
 }
 export function reSampleBuffers(args: ArgsAudioBuffer): Buffer
+export interface ArgsEncodedAudioBuffer {
+  /** A whole FLAC, Ogg Vorbis, Ogg Opus, MP3, WAV / RF64 / Wave64, AIFF / AIFF-C, AU or CAF file */
+  inputBuffer: Buffer
+  sampleRateOutput?: number
+  typeOfBinData?: DataType
+  /** Raw output as `<format>:<channels>:<rate>`, instead of `sample_rate_output` and `type_of_bin_data` */
+  outputFormat?: string
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
+}
+export interface ReSampledEncodedAudio {
+  /** Interleaved raw samples of `output_format` or `type_of_bin_data` */
+  buffer: Buffer
+  /** Sample rate found in the header of the input */
+  sampleRateInput: number
+  /** Channels found in the header of the input, kept in the output */
+  channels: number
+}
+/**
+ * Decodes a file held in memory, of any container found from its first bytes, and returns its
+ * resampled raw samples
+ */
+export function reSampleEncodedBuffer(args: ArgsEncodedAudioBuffer): ReSampledEncodedAudio
+export interface ArgsAudioBufferToFlac {
+  argsAudioToReSample: ArgsAudioToReSample
+  inputBuffer: Buffer
+  typeOfBinData: DataType
+  /** From 0 (fastest) to 8 (smallest), 5 by default */
+  compressionLevel?: number
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
+}
+/** Resamples raw samples of `type_of_bin_data` and returns them as a FLAC file */
+export function reSampleBufferToFlac(args: ArgsAudioBufferToFlac): Buffer
+export interface ArgsRawAudioBuffer {
+  inputBuffer: Buffer
+  /** `<format>:<channels>:<rate>` of the input, like `s16le:2:44100`, or `auto` for an encoded file */
+  inputFormat: string
+  /** `<format>:<channels>:<rate>` of the output, with the channels of the input */
+  outputFormat: string
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
+}
+/**
+ * Resamples raw samples described by format descriptors, like `s16le:2:44100` to `f32le:2:16000`.
+ * With an `input_format` of `auto` the input is any file found by `probe_audio`
+ */
+export function reSampleRawBuffer(args: ArgsRawAudioBuffer): Buffer
+export interface ArgsContainerBuffer {
+  /** A whole file of a container found by `probe_audio`, or raw samples of `input_format` */
+  inputBuffer: Buffer
+  /** `auto` (the default) or a raw format descriptor like `s16le:2:44100` */
+  inputFormat?: string
+  sampleRateOutput: number
+  outputContainer: OutputContainer
+  /** Sample format of the output, as in `ArgsAudioFile`, `DataType.F32` by default */
+  typeOfBinData?: DataType
+  /** FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default */
+  compressionLevel?: number
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
+}
+export interface ReSampledContainer {
+  /** The output file, or interleaved samples of `type_of_bin_data` for `OutputContainer.Raw` */
+  buffer: Buffer
+  /** Sample rate of the input, found in its header */
+  sampleRateInput: number
+  /** Channels of the input, kept in the output */
+  channels: number
+}
+/**
+ * Decodes a file held in memory, resamples it and encodes it in `output_container` without any file
+ * on disk. A WAV output keeps the metadata chunks of a WAV input, like `re_sample_audio_file`
+ */
+export function reSampleContainerBuffer(args: ArgsContainerBuffer): ReSampledContainer
+export interface ProbedAudio {
+  /** `flac`, `ogg`, `mp3`, `aiff`, `wav`, `au`, `caf`, or `raw` when no container is recognized */
+  container: string
+  /** Like `pcm_s16le`, `flac`, `vorbis`, `mp3`, `opus` or `adpcm_ima_qt`, missing for raw input */
+  codec?: string
+  sampleRate?: number
+  channels?: number
+  /** `s16le`, `mulaw`... for PCM, `s16` / `s24` for lossless codecs and `f32` for lossy ones */
+  sampleFormat?: string
+  /** In seconds */
+  duration?: number
+  frames?: number
+}
+/**
+ * Finds the container of a file or an in-memory file from its magic bytes, whatever its extension,
+ * and describes its stream. Input without a known container is reported as `raw`
+ */
+export function probeAudio(input: Buffer | string): ProbedAudio
+export interface ArgsSamplePositions {
+  /** Frame indices at the input sample rate, like markers or region bounds */
+  positions: Array<number>
+  sampleRateInput: number
+  sampleRateOutput: number
+}
+/**
+ * Rescales frame indices of the input to the frames of the same audio in the resampled output, the
+ * resampler delay being already compensated in every output of this module
+ */
+export function rescaleSamplePositions(args: ArgsSamplePositions): Array<number>
 export interface ArgsAudioInt16Buffer {
   argsAudioToReSample: ArgsAudioToReSample
   inputInt16Buffer: Buffer
+  /** Start of the part of the input to resample, in seconds or in input frames with `range_unit` */
+  start?: number
+  /** End of the part of the input to resample, excluded, the end of the input by default */
+  end?: number
+  /** Unit of `start` and `end`, `Seconds` by default */
+  rangeUnit?: RangeUnit
 }
 export function reSampleInt16Buffer(args: ArgsAudioInt16Buffer): Buffer
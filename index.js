@@ -254,9 +254,24 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { DataType, reSampleAudioFile, reSampleBuffers, reSampleInt16Buffer } = nativeBinding
+const { DataType, OutputContainer, RangeUnit, OutputSync, WritePolicy, FileErrorCode, reSampleAudioFile, reSampleAudioFiles, reSampleDirectory, interleaveAudioFiles, concatAudioFiles, reSampleBuffers, reSampleEncodedBuffer, reSampleBufferToFlac, reSampleRawBuffer, reSampleContainerBuffer, probeAudio, rescaleSamplePositions, reSampleInt16Buffer } = nativeBinding
 
 module.exports.DataType = DataType
+module.exports.OutputContainer = OutputContainer
+module.exports.RangeUnit = RangeUnit
+module.exports.OutputSync = OutputSync
+module.exports.WritePolicy = WritePolicy
+module.exports.FileErrorCode = FileErrorCode
 module.exports.reSampleAudioFile = reSampleAudioFile
+module.exports.reSampleAudioFiles = reSampleAudioFiles
+module.exports.reSampleDirectory = reSampleDirectory
+module.exports.interleaveAudioFiles = interleaveAudioFiles
+module.exports.concatAudioFiles = concatAudioFiles
 module.exports.reSampleBuffers = reSampleBuffers
+module.exports.reSampleEncodedBuffer = reSampleEncodedBuffer
+module.exports.reSampleBufferToFlac = reSampleBufferToFlac
+module.exports.reSampleRawBuffer = reSampleRawBuffer
+module.exports.reSampleContainerBuffer = reSampleContainerBuffer
+module.exports.probeAudio = probeAudio
+module.exports.rescaleSamplePositions = rescaleSamplePositions
 module.exports.reSampleInt16Buffer = reSampleInt16Buffer
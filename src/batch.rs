@@ -0,0 +1,101 @@
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/**
+ Result of a job, the panic message when it panicked, and the time it took.
+*/
+pub type JobResult<R> = (Result<R, String>, Duration);
+
+/**
+ Runs `run` on every job across `threads` worker threads, each one taking the next job once done
+ with the previous one, and returns the results in the order of the jobs with the time each one
+ took. A job that panics gets the panic message as its error instead of stopping the other ones.
+*/
+pub fn run_jobs<J: Send, R: Send>(
+  jobs: Vec<J>,
+  threads: usize,
+  run: impl Fn(J) -> R + Sync,
+) -> Vec<JobResult<R>> {
+  let jobs: Vec<Mutex<Option<J>>> = jobs.into_iter().map(|job| Mutex::new(Some(job))).collect();
+  let results: Vec<Mutex<Option<JobResult<R>>>> = jobs.iter().map(|_| Mutex::new(None)).collect();
+  let next = AtomicUsize::new(0);
+  let threads = threads.clamp(1, std::cmp::max(jobs.len(), 1));
+  std::thread::scope(|scope| {
+    for _ in 0..threads {
+      scope.spawn(|| loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(job) = jobs.get(index) else {
+          break;
+        };
+        let job = job.lock().unwrap().take().unwrap();
+        let job_time = Instant::now();
+        let result = catch_unwind(AssertUnwindSafe(|| run(job))).map_err(panic_message);
+        *results[index].lock().unwrap() = Some((result, job_time.elapsed()));
+      });
+    }
+  });
+  results
+    .into_iter()
+    .map(|result| result.into_inner().unwrap().unwrap())
+    .collect()
+}
+
+/**
+ Worker threads used when none are asked for: one per CPU.
+*/
+pub fn default_threads() -> usize {
+  std::thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+  let message = payload
+    .downcast_ref::<&str>()
+    .map(|message| message.to_string())
+    .or_else(|| payload.downcast_ref::<String>().cloned())
+    .unwrap_or_default();
+  format!("Job panicked : {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_results_keep_the_order_of_the_jobs() {
+    let jobs: Vec<u64> = (0..20).collect();
+    let results = run_jobs(jobs, 4, |job| {
+      // The first jobs finish last
+      std::thread::sleep(Duration::from_millis(20 - job));
+      job * 2
+    });
+    let results: Vec<Result<u64, String>> = results.into_iter().map(|(result, _)| result).collect();
+    let expected: Vec<Result<u64, String>> = (0..20).map(|job| Ok(job * 2)).collect();
+    assert_eq!(results, expected);
+    let results = run_jobs(vec![1, 2], 0, |job| job);
+    assert_eq!(results[0].0, Ok(1));
+    assert_eq!(results[1].0, Ok(2));
+    assert!(run_jobs(Vec::<u8>::new(), 8, |job| job).is_empty());
+  }
+
+  #[test]
+  fn test_a_panicking_job_does_not_stop_the_others() {
+    let results = run_jobs(vec![1, 0, 4], 2, |job| {
+      if job == 0 {
+        std::thread::sleep(Duration::from_millis(20));
+        panic!("division by zero");
+      }
+      8 / job
+    });
+    assert_eq!(results[0].0, Ok(8));
+    assert_eq!(
+      results[1].0,
+      Err("Job panicked : division by zero".to_string())
+    );
+    // Timed up to the panic
+    assert!(results[1].1 >= Duration::from_millis(20));
+    assert_eq!(results[2].0, Ok(2));
+  }
+}
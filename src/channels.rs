@@ -0,0 +1,241 @@
+use crate::codecs::{AudioSink, AudioSource};
+
+/**
+ Writes each channel of the interleaved samples written to it to its own sink, as mono samples.
+*/
+pub struct ChannelSplitSink {
+  sinks: Vec<Box<dyn AudioSink>>,
+  mono: Vec<f32>,
+}
+
+impl ChannelSplitSink {
+  pub fn new(sinks: Vec<Box<dyn AudioSink>>) -> Self {
+    ChannelSplitSink {
+      sinks,
+      mono: Vec::new(),
+    }
+  }
+}
+
+impl AudioSink for ChannelSplitSink {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    let channels = self.sinks.len();
+    for (channel, sink) in self.sinks.iter_mut().enumerate() {
+      self.mono.clear();
+      self
+        .mono
+        .extend(samples.iter().skip(channel).step_by(channels));
+      sink.write_samples(&self.mono)?;
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    self.sinks.iter_mut().try_for_each(|sink| sink.finish())
+  }
+}
+
+/**
+ A block of planar frames with `channels` channels: mono is copied to every channel, several
+ channels down to mono are averaged, and otherwise channels are kept by position, the missing ones
+ being silent.
+*/
+pub fn remix_channels(block: Vec<Vec<f32>>, channels: usize) -> Vec<Vec<f32>> {
+  let len = block.first().map_or(0, Vec::len);
+  match block.len() {
+    input if input == channels => block,
+    1 => vec![block[0].clone(); channels],
+    input if channels == 1 => vec![(0..len)
+      .map(|frame| block.iter().map(|channel| channel[frame]).sum::<f32>() / input as f32)
+      .collect()],
+    _ => {
+      let mut block = block;
+      block.resize(channels, vec![0.0; len]);
+      block
+    }
+  }
+}
+
+/**
+ Checks that sources read side by side or one after the other have the same sample rate.
+*/
+pub fn check_sample_rates(sources: &[Box<dyn AudioSource>]) -> Result<(), String> {
+  let sample_rates: Vec<u32> = sources
+    .iter()
+    .map(|source| source.spec().sample_rate)
+    .collect();
+  if sample_rates.windows(2).any(|pair| pair[0] != pair[1]) {
+    return Err(format!(
+      "Cannot join sources of different sample rates {:?}",
+      sample_rates
+    ));
+  }
+  Ok(())
+}
+
+/**
+ Streams sources of the same sample rate into an `AudioSink` as one output holding the channels of
+ each source in turn, the shorter ones padded with silence up to the end of the longest one, and
+ finishes it. Blocks are read from the sources with the fewest pending frames, so only about a block
+ of each one is held in memory. Returns the number of output frames.
+*/
+pub fn interleave_sources(
+  sources: &mut [Box<dyn AudioSource>],
+  sink: &mut dyn AudioSink,
+) -> Result<u64, String> {
+  check_sample_rates(sources)?;
+  // Planar frames read from each source and not written yet
+  let mut pending: Vec<Vec<Vec<f32>>> = sources
+    .iter()
+    .map(|source| vec![Vec::new(); source.spec().channels])
+    .collect();
+  let mut ended = vec![false; sources.len()];
+  let mut samples = Vec::new();
+  let mut nbr_frames = 0;
+  loop {
+    for ((source, frames), ended) in sources.iter_mut().zip(&mut pending).zip(&mut ended) {
+      while !*ended && frames[0].is_empty() {
+        match source.read_block()? {
+          Some(block) => {
+            for (frames, channel) in frames.iter_mut().zip(block) {
+              frames.extend(channel);
+            }
+          }
+          None => *ended = true,
+        }
+      }
+    }
+    let all_ended = ended.iter().all(|&ended| ended);
+    let lengths = pending.iter().map(|frames| frames[0].len());
+    // The frames every source has, or all the remaining ones once every source is read
+    let len = if all_ended {
+      lengths.max()
+    } else {
+      lengths
+        .zip(&ended)
+        .filter(|(_, &ended)| !ended)
+        .map(|(len, _)| len)
+        .min()
+    }
+    .unwrap_or(0);
+    samples.clear();
+    for frame in 0..len {
+      for channel in pending.iter().flatten() {
+        samples.push(channel.get(frame).copied().unwrap_or(0.0));
+      }
+    }
+    if len > 0 {
+      sink.write_samples(&samples)?;
+    }
+    for channel in pending.iter_mut().flatten() {
+      channel.drain(..std::cmp::min(len, channel.len()));
+    }
+    nbr_frames += len as u64;
+    if all_ended {
+      break;
+    }
+  }
+  sink.finish()?;
+  Ok(nbr_frames)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  struct SharedSink(Rc<RefCell<Vec<f32>>>);
+
+  impl AudioSink for SharedSink {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+      self.0.borrow_mut().extend_from_slice(samples);
+      Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn test_remix_channels() {
+    assert_eq!(
+      remix_channels(vec![vec![0.5, 1.0]], 2),
+      [[0.5, 1.0], [0.5, 1.0]]
+    );
+    assert_eq!(
+      remix_channels(vec![vec![0.5, 1.0], vec![-0.5, 0.0]], 1),
+      [[0.0, 0.5]]
+    );
+    assert_eq!(
+      remix_channels(vec![vec![0.5], vec![1.0]], 3),
+      [[0.5], [1.0], [0.0]]
+    );
+    assert_eq!(
+      remix_channels(vec![vec![0.5], vec![1.0], vec![0.25]], 2),
+      [[0.5], [1.0]]
+    );
+  }
+
+  struct BlockSource(Vec<Vec<Vec<f32>>>, usize);
+
+  impl AudioSource for BlockSource {
+    fn spec(&self) -> crate::codecs::AudioSpec {
+      crate::codecs::AudioSpec {
+        sample_rate: 16000,
+        channels: self.1,
+        bits_per_sample: 32,
+      }
+    }
+
+    fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+      Ok((!self.0.is_empty()).then(|| self.0.remove(0)))
+    }
+
+    fn codec(&self) -> String {
+      "pcm_f32le".to_string()
+    }
+
+    fn sample_format(&self) -> String {
+      "f32le".to_string()
+    }
+  }
+
+  #[test]
+  fn test_sources_are_interleaved_and_padded() {
+    let mut sources: Vec<Box<dyn AudioSource>> = vec![
+      Box::new(BlockSource(
+        vec![vec![vec![1.0, 2.0]], vec![], vec![vec![3.0]]],
+        1,
+      )),
+      Box::new(BlockSource(
+        vec![vec![vec![-1.0, -2.0, -3.0, -4.0], vec![5.0, 6.0, 7.0, 8.0]]],
+        2,
+      )),
+    ];
+    let mut output = Vec::new();
+    assert_eq!(interleave_sources(&mut sources, &mut output), Ok(4));
+    assert_eq!(
+      output,
+      [1.0, -1.0, 5.0, 2.0, -2.0, 6.0, 3.0, -3.0, 7.0, 0.0, -4.0, 8.0]
+    );
+  }
+
+  #[test]
+  fn test_channels_are_split() {
+    let outputs: Vec<Rc<RefCell<Vec<f32>>>> = (0..3).map(|_| Rc::default()).collect();
+    let mut sink = ChannelSplitSink::new(
+      outputs
+        .iter()
+        .map(|output| Box::new(SharedSink(output.clone())) as Box<dyn AudioSink>)
+        .collect(),
+    );
+    sink.write_samples(&[0.0, 1.0, 2.0, 0.5, 1.5, 2.5]).unwrap();
+    sink.write_samples(&[0.25, 1.25, 2.25]).unwrap();
+    sink.finish().unwrap();
+    assert_eq!(*outputs[0].borrow(), [0.0, 0.5, 0.25]);
+    assert_eq!(*outputs[1].borrow(), [1.0, 1.5, 1.25]);
+    assert_eq!(*outputs[2].borrow(), [2.0, 2.5, 2.25]);
+  }
+}
@@ -0,0 +1,337 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use log::debug;
+
+use super::pcm::{PcmHeader, PcmSource, SampleFormat};
+
+/**
+ Decodes the 80-bit IEEE 754 extended float holding the sample rate of the COMM chunk.
+*/
+pub fn read_extended(bytes: &[u8; 10]) -> f64 {
+  let exponent = (((bytes[0] & 0x7F) as i32) << 8) | bytes[1] as i32;
+  let mantissa = u64::from_be_bytes(bytes[2..].try_into().unwrap());
+  if exponent == 0 && mantissa == 0 {
+    return 0.0;
+  }
+  let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+  if bytes[0] & 0x80 != 0 {
+    -value
+  } else {
+    value
+  }
+}
+
+/**
+ Encodes a positive value as an 80-bit IEEE 754 extended float.
+*/
+pub fn write_extended(value: f64) -> [u8; 10] {
+  let mut bytes = [0u8; 10];
+  if value <= 0.0 {
+    return bytes;
+  }
+  let exponent = value.log2().floor() as i32;
+  let mantissa = (value * 2f64.powi(63 - exponent)) as u64;
+  bytes[..2].copy_from_slice(&((exponent + 16383) as u16).to_be_bytes());
+  bytes[2..].copy_from_slice(&mantissa.to_be_bytes());
+  bytes
+}
+
+/**
+ Sample format of an AIFF-C compression type, `None` for compressed payloads.
+*/
+fn compression_format(compression: &[u8], bits: u32) -> Option<SampleFormat> {
+  match compression {
+    b"NONE" | b"twos" => Some(SampleFormat::Int {
+      bits,
+      big_endian: true,
+    }),
+    b"sowt" => Some(SampleFormat::Int {
+      bits,
+      big_endian: false,
+    }),
+    b"in24" => Some(SampleFormat::Int {
+      bits: 24,
+      big_endian: true,
+    }),
+    b"in32" => Some(SampleFormat::Int {
+      bits: 32,
+      big_endian: true,
+    }),
+    b"fl32" | b"FL32" => Some(SampleFormat::Float {
+      bits: 32,
+      big_endian: true,
+    }),
+    b"fl64" | b"FL64" => Some(SampleFormat::Float {
+      bits: 64,
+      big_endian: true,
+    }),
+    _ => None,
+  }
+}
+
+/**
+ Parses the chunks of an AIFF or AIFF-C file and returns a source positioned at the samples of its
+ SSND chunk.
+*/
+pub fn aiff_source<R: Read + Seek>(mut reader: R) -> Result<PcmSource<R>, String> {
+  let read_error = |err: std::io::Error| format!("Invalid AIFF file : {}", err);
+  let mut form = [0u8; 12];
+  reader.read_exact(&mut form).map_err(read_error)?;
+  if &form[..4] != b"FORM" || (&form[8..] != b"AIFF" && &form[8..] != b"AIFC") {
+    return Err("Not an AIFF file".to_string());
+  }
+  let is_aifc = &form[8..] == b"AIFC";
+
+  let mut comm = None;
+  let mut ssnd = None;
+  loop {
+    let mut id = [0u8; 4];
+    match reader.read_exact(&mut id) {
+      Ok(()) => {}
+      Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+      Err(err) => return Err(read_error(err)),
+    }
+    let size = reader.read_u32::<BigEndian>().map_err(read_error)? as u64;
+    let start = reader.stream_position().map_err(read_error)?;
+    match &id {
+      b"COMM" => {
+        let channels = reader.read_u16::<BigEndian>().map_err(read_error)? as usize;
+        let nbr_frames = reader.read_u32::<BigEndian>().map_err(read_error)? as u64;
+        let bits = reader.read_u16::<BigEndian>().map_err(read_error)? as u32;
+        let mut rate = [0u8; 10];
+        reader.read_exact(&mut rate).map_err(read_error)?;
+        let mut compression = *b"NONE";
+        if is_aifc {
+          reader.read_exact(&mut compression).map_err(read_error)?;
+        }
+        let format = compression_format(&compression, bits).ok_or_else(|| {
+          format!(
+            "Unsupported AIFF-C compression {}",
+            String::from_utf8_lossy(&compression)
+          )
+        })?;
+        if let SampleFormat::Int { bits: 0 | 33.., .. } = format {
+          return Err(format!("Unsupported AIFF sample size of {} bits", bits));
+        }
+        comm = Some((channels, nbr_frames, read_extended(&rate), format));
+      }
+      b"SSND" => {
+        let offset = reader.read_u32::<BigEndian>().map_err(read_error)? as u64;
+        ssnd = Some((start + 8 + offset, size.saturating_sub(8 + offset)));
+      }
+      _ => debug!("Skipping AIFF chunk {}", String::from_utf8_lossy(&id)),
+    }
+    // Chunks are padded to an even size
+    reader
+      .seek(SeekFrom::Start(start + size + (size & 1)))
+      .map_err(read_error)?;
+  }
+
+  let (channels, nbr_frames, sample_rate, format) =
+    comm.ok_or_else(|| "AIFF file has no COMM chunk".to_string())?;
+  let (data_start, data_len) = ssnd.ok_or_else(|| "AIFF file has no SSND chunk".to_string())?;
+  let frame_len = (format.bytes_per_sample() * channels) as u64;
+  let data_len = std::cmp::min(data_len, nbr_frames * frame_len);
+  reader
+    .seek(SeekFrom::Start(data_start))
+    .map_err(read_error)?;
+  PcmSource::new(
+    reader,
+    sample_rate.round() as u32,
+    channels,
+    format,
+    Some(data_len),
+  )
+}
+
+/**
+ Header of an AIFF file, or of an AIFF-C file when the samples are little-endian or floats.
+*/
+pub struct AiffHeader {
+  sample_rate: u32,
+  channels: usize,
+  format: SampleFormat,
+  compression: Option<(&'static [u8; 4], &'static str)>,
+}
+
+impl AiffHeader {
+  pub fn new(sample_rate: u32, channels: usize, format: SampleFormat) -> Result<Self, String> {
+    let compression = match format {
+      SampleFormat::Int {
+        big_endian: true, ..
+      } => None,
+      SampleFormat::Int {
+        big_endian: false, ..
+      } => Some((b"sowt", "")),
+      SampleFormat::Float {
+        bits: 32,
+        big_endian: true,
+      } => Some((b"fl32", "32-bit floating point")),
+      SampleFormat::Float {
+        bits: 64,
+        big_endian: true,
+      } => Some((b"fl64", "64-bit floating point")),
+      _ => return Err(format!("AIFF cannot hold samples of {:?}", format)),
+    };
+    Ok(AiffHeader {
+      sample_rate,
+      channels,
+      format,
+      compression,
+    })
+  }
+}
+
+impl PcmHeader for AiffHeader {
+  fn header(&self, nbr_frames: u64) -> Result<Vec<u8>, String> {
+    let data_len = nbr_frames * (self.format.bytes_per_sample() * self.channels) as u64;
+
+    let mut comm = Vec::new();
+    comm.extend_from_slice(&(self.channels as u16).to_be_bytes());
+    comm.extend_from_slice(&(nbr_frames as u32).to_be_bytes());
+    comm.extend_from_slice(&(self.format.bits_per_sample() as u16).to_be_bytes());
+    comm.extend_from_slice(&write_extended(self.sample_rate as f64));
+    let mut chunks = Vec::new();
+    if let Some((compression, name)) = self.compression {
+      comm.extend_from_slice(compression);
+      // Pascal string padded to an even length
+      comm.push(name.len() as u8);
+      comm.extend_from_slice(name.as_bytes());
+      if name.len() % 2 == 0 {
+        comm.push(0);
+      }
+      chunks.extend_from_slice(b"FVER");
+      chunks.extend_from_slice(&4u32.to_be_bytes());
+      chunks.extend_from_slice(&0xA280_5140u32.to_be_bytes());
+    }
+    chunks.extend_from_slice(b"COMM");
+    chunks.extend_from_slice(&(comm.len() as u32).to_be_bytes());
+    chunks.extend_from_slice(&comm);
+    chunks.extend_from_slice(b"SSND");
+    chunks.extend_from_slice(&((8 + data_len) as u32).to_be_bytes());
+    chunks.extend_from_slice(&[0; 8]); // offset and block size
+
+    let form_len = 4 + chunks.len() as u64 + data_len + (data_len & 1);
+    if form_len > u32::MAX as u64 {
+      return Err("AIFF files cannot hold more than 4 GB of samples".to_string());
+    }
+    let mut header = b"FORM".to_vec();
+    header.extend_from_slice(&(form_len as u32).to_be_bytes());
+    header.extend_from_slice(if self.compression.is_some() {
+      b"AIFC"
+    } else {
+      b"AIFF"
+    });
+    header.extend_from_slice(&chunks);
+    Ok(header)
+  }
+
+  fn trailer(&self, nbr_frames: u64) -> Vec<u8> {
+    let data_len = nbr_frames * (self.format.bytes_per_sample() * self.channels) as u64;
+    vec![0; (data_len & 1) as usize]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::pcm::PcmWriter;
+  use crate::codecs::{AudioSink, AudioSource};
+  use std::io::Cursor;
+
+  #[test]
+  fn test_extended_sample_rates() {
+    for rate in [8000.0, 22050.0, 44100.0, 48000.0, 96000.0, 192000.0] {
+      assert_eq!(read_extended(&write_extended(rate)), rate);
+    }
+    // 44100 Hz as written by Apple tools
+    let bytes = [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0];
+    assert_eq!(read_extended(&bytes), 44100.0);
+    assert_eq!(write_extended(44100.0), bytes);
+  }
+
+  fn round_trip(format: SampleFormat, channels: usize) -> (Vec<u8>, Vec<Vec<f32>>) {
+    let samples: Vec<f32> = (0..301 * channels)
+      .map(|i| ((i as f32) * 0.01).sin() * 0.5)
+      .collect();
+    let header = AiffHeader::new(22050, channels, format).unwrap();
+    let mut writer = PcmWriter::new(Cursor::new(Vec::new()), header, format, channels).unwrap();
+    writer.write_samples(&samples).unwrap();
+    writer.finish().unwrap();
+    let file = writer.into_inner().into_inner();
+
+    let mut source = aiff_source(Cursor::new(file.clone())).unwrap();
+    assert_eq!(source.spec().sample_rate, 22050);
+    assert_eq!(source.spec().channels, channels);
+    let mut planar = vec![Vec::new(); channels];
+    while let Some(block) = source.read_block().unwrap() {
+      for (all, chan) in planar.iter_mut().zip(block) {
+        all.extend(chan);
+      }
+    }
+    // One quantization step of the integer formats
+    let tolerance = 2f32.powi(1 - format.bits_per_sample().min(16) as i32);
+    for (i, &sample) in samples.iter().enumerate() {
+      assert!((planar[i % channels][i / channels] - sample).abs() < tolerance);
+    }
+    (file, planar)
+  }
+
+  #[test]
+  fn test_aiff_round_trip() {
+    let (file, planar) = round_trip(
+      SampleFormat::Int {
+        bits: 16,
+        big_endian: true,
+      },
+      2,
+    );
+    assert_eq!(&file[8..12], b"AIFF");
+    assert_eq!(planar[0].len(), 301);
+    // 8-bit mono has an odd SSND chunk followed by a pad byte
+    let (file, _) = round_trip(
+      SampleFormat::Int {
+        bits: 8,
+        big_endian: true,
+      },
+      1,
+    );
+    assert_eq!(file.len() % 2, 0);
+    assert_eq!(
+      u32::from_be_bytes(file[4..8].try_into().unwrap()) as usize,
+      file.len() - 8
+    );
+  }
+
+  #[test]
+  fn test_aifc_round_trip() {
+    let (file, _) = round_trip(
+      SampleFormat::Int {
+        bits: 16,
+        big_endian: false,
+      },
+      2,
+    );
+    assert_eq!(&file[8..12], b"AIFC");
+    assert!(file.windows(4).any(|id| id == b"sowt"));
+    let (file, _) = round_trip(
+      SampleFormat::Float {
+        bits: 32,
+        big_endian: true,
+      },
+      1,
+    );
+    assert!(file.windows(4).any(|id| id == b"fl32"));
+  }
+
+  #[test]
+  fn test_aiff_rejects_compressed_payload() {
+    let mut file = b"FORM\0\0\0\x2EAIFC".to_vec();
+    file.extend_from_slice(b"COMM\0\0\0\x16\0\x01\0\0\0\0\0\x10");
+    file.extend_from_slice(&write_extended(8000.0));
+    file.extend_from_slice(b"ulaw");
+    assert!(aiff_source(Cursor::new(file)).is_err());
+    assert!(aiff_source(Cursor::new(b"RIFF\0\0\0\0WAVE".to_vec())).is_err());
+  }
+}
@@ -0,0 +1,179 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use super::pcm::{PcmHeader, PcmSource, SampleFormat};
+
+/**
+ Size of the written header, the 24 bytes of fields followed by an empty annotation.
+*/
+const AU_HEADER_LEN: u32 = 32;
+
+/**
+ Sample format of an AU encoding, `None` for the compressed ones (G.721, G.723, ...).
+*/
+fn encoding_format(encoding: u32) -> Option<SampleFormat> {
+  let int = |bits| SampleFormat::Int {
+    bits,
+    big_endian: true,
+  };
+  let float = |bits| SampleFormat::Float {
+    bits,
+    big_endian: true,
+  };
+  match encoding {
+    1 => Some(SampleFormat::MuLaw),
+    2 => Some(int(8)),
+    3 => Some(int(16)),
+    4 => Some(int(24)),
+    5 => Some(int(32)),
+    6 => Some(float(32)),
+    7 => Some(float(64)),
+    27 => Some(SampleFormat::ALaw),
+    _ => None,
+  }
+}
+
+fn format_encoding(format: SampleFormat) -> Option<u32> {
+  (1..=27).find(|&encoding| encoding_format(encoding) == Some(format))
+}
+
+/**
+ Tells if an in-memory file is a Sun AU / NeXT .snd file.
+*/
+pub fn is_au(data: &[u8]) -> bool {
+  data.starts_with(b".snd")
+}
+
+/**
+ Parses the header of an AU file and returns a source positioned at its samples.
+*/
+pub fn au_source<R: Read + Seek>(mut reader: R) -> Result<PcmSource<R>, String> {
+  let read_error = |err: std::io::Error| format!("Invalid AU file : {}", err);
+  let mut magic = [0u8; 4];
+  reader.read_exact(&mut magic).map_err(read_error)?;
+  if !is_au(&magic) {
+    return Err("Not an AU file".to_string());
+  }
+  let mut fields = [0u32; 5];
+  reader
+    .read_u32_into::<BigEndian>(&mut fields)
+    .map_err(read_error)?;
+  let [data_offset, data_len, encoding, sample_rate, channels] = fields;
+  let format =
+    encoding_format(encoding).ok_or_else(|| format!("Unsupported AU encoding {}", encoding))?;
+  reader
+    .seek(SeekFrom::Start(data_offset as u64))
+    .map_err(read_error)?;
+  // An unknown size means the samples run to the end of the file
+  let data_len = if data_len == u32::MAX {
+    None
+  } else {
+    Some(data_len as u64)
+  };
+  PcmSource::new(reader, sample_rate, channels as usize, format, data_len)
+}
+
+/**
+ Header of an AU file, big-endian integers and floats, mu-law or A-law samples.
+*/
+pub struct AuHeader {
+  sample_rate: u32,
+  channels: usize,
+  format: SampleFormat,
+  encoding: u32,
+}
+
+impl AuHeader {
+  pub fn new(sample_rate: u32, channels: usize, format: SampleFormat) -> Result<Self, String> {
+    let encoding =
+      format_encoding(format).ok_or_else(|| format!("AU cannot hold samples of {:?}", format))?;
+    Ok(AuHeader {
+      sample_rate,
+      channels,
+      format,
+      encoding,
+    })
+  }
+}
+
+impl PcmHeader for AuHeader {
+  fn header(&self, nbr_frames: u64) -> Result<Vec<u8>, String> {
+    let data_len = nbr_frames * (self.format.bytes_per_sample() * self.channels) as u64;
+    let mut header = b".snd".to_vec();
+    for field in [
+      AU_HEADER_LEN,
+      // Larger samples are still readable, up to the end of the file
+      u32::try_from(data_len).unwrap_or(u32::MAX),
+      self.encoding,
+      self.sample_rate,
+      self.channels as u32,
+    ] {
+      header.extend_from_slice(&field.to_be_bytes());
+    }
+    header.resize(AU_HEADER_LEN as usize, 0);
+    Ok(header)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::pcm::PcmWriter;
+  use crate::codecs::{AudioSink, AudioSource};
+  use std::io::Cursor;
+
+  #[test]
+  fn test_au_round_trip() {
+    let samples: Vec<f32> = (0..400).map(|i| ((i as f32) * 0.05).sin() * 0.7).collect();
+    for (format, tolerance) in [
+      (
+        SampleFormat::Int {
+          bits: 16,
+          big_endian: true,
+        },
+        1e-4,
+      ),
+      (
+        SampleFormat::Float {
+          bits: 32,
+          big_endian: true,
+        },
+        1e-7,
+      ),
+      (SampleFormat::MuLaw, 0.05),
+    ] {
+      let header = AuHeader::new(8000, 2, format).unwrap();
+      let mut writer = PcmWriter::new(Cursor::new(Vec::new()), header, format, 2).unwrap();
+      writer.write_samples(&samples).unwrap();
+      writer.finish().unwrap();
+      let file = writer.into_inner().into_inner();
+      assert!(is_au(&file));
+      assert_eq!(file.len(), 32 + 400 * format.bytes_per_sample());
+
+      let mut source = au_source(Cursor::new(file)).unwrap();
+      assert_eq!(source.spec().sample_rate, 8000);
+      assert_eq!(source.spec().channels, 2);
+      let block = source.read_block().unwrap().unwrap();
+      assert_eq!(block[0].len(), 200);
+      assert!((block[1][10] - samples[21]).abs() < tolerance);
+      assert_eq!(source.read_block().unwrap(), None);
+    }
+  }
+
+  #[test]
+  fn test_au_unknown_size_and_encoding() {
+    let mut file = b".snd".to_vec();
+    for field in [28, u32::MAX, 1, 8000, 1, 0] {
+      file.extend_from_slice(&field.to_be_bytes());
+    }
+    file.extend_from_slice(&[0xFF, 0x80, 0x00]);
+    let mut source = au_source(Cursor::new(file.clone())).unwrap();
+    assert_eq!(source.read_block().unwrap().unwrap()[0].len(), 3);
+
+    // G.721 ADPCM
+    file[15] = 23;
+    assert!(au_source(Cursor::new(file)).is_err());
+    assert!(AuHeader::new(8000, 1, SampleFormat::UInt8).is_err());
+  }
+}
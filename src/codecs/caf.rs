@@ -0,0 +1,402 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use log::debug;
+
+use super::pcm::{PcmSource, SampleFormat};
+use super::{AudioSource, AudioSpec};
+
+const IMA4_PACKET_FRAMES: usize = 64;
+const IMA4_PACKET_LEN: usize = 34;
+
+/**
+ Packets of IMA4 decoded by each `read_block`, 4096 frames.
+*/
+const IMA4_BLOCK_PACKETS: usize = 64;
+
+const IMA_STEPS: [i32; 89] = [
+  7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73,
+  80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494,
+  544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499,
+  2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487,
+  12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+const IMA_INDEX_STEPS: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+/**
+ Tells if an in-memory file is a Core Audio Format file.
+*/
+pub fn is_caf(data: &[u8]) -> bool {
+  data.starts_with(b"caff")
+}
+
+/**
+ Decodes the 64 frames of one channel of an Apple IMA4 packet, a 16-bit header holding the initial
+ predictor (top 9 bits) and step index (low 7 bits) followed by 32 bytes of nibbles, low one first.
+*/
+fn decode_ima4_packet(packet: &[u8], output: &mut Vec<f32>) {
+  let header = u16::from_be_bytes([packet[0], packet[1]]);
+  let mut predictor = (header & 0xFF80) as i16 as i32;
+  let mut index = std::cmp::min((header & 0x7F) as i32, 88);
+  for byte in &packet[2..IMA4_PACKET_LEN] {
+    for nibble in [byte & 0x0F, byte >> 4] {
+      let step = IMA_STEPS[index as usize];
+      let mut diff = step >> 3;
+      if nibble & 1 != 0 {
+        diff += step >> 2;
+      }
+      if nibble & 2 != 0 {
+        diff += step >> 1;
+      }
+      if nibble & 4 != 0 {
+        diff += step;
+      }
+      if nibble & 8 != 0 {
+        predictor -= diff;
+      } else {
+        predictor += diff;
+      }
+      predictor = predictor.clamp(i16::MIN as i32, i16::MAX as i32);
+      index = (index + IMA_INDEX_STEPS[nibble as usize]).clamp(0, 88);
+      output.push(predictor as f32 / i16::MAX as f32);
+    }
+  }
+}
+
+/**
+ Streams the frames of the IMA4 packets of a CAF data chunk, the priming frames announced by the
+ `pakt` chunk being dropped and the output cut at its number of valid frames.
+*/
+pub struct Ima4Source<R: Read> {
+  reader: R,
+  spec: AudioSpec,
+  remaining_packets: u64,
+  frames_to_skip: usize,
+  frames_left: u64,
+  nbr_frames: Option<u64>,
+  packets: Vec<u8>,
+}
+
+impl<R: Read> AudioSource for Ima4Source<R> {
+  fn spec(&self) -> AudioSpec {
+    self.spec
+  }
+
+  fn codec(&self) -> String {
+    "adpcm_ima_qt".to_string()
+  }
+
+  fn sample_format(&self) -> String {
+    "s16".to_string()
+  }
+
+  fn nbr_frames(&self) -> Option<u64> {
+    self.nbr_frames
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    let channels = self.spec.channels;
+    loop {
+      let nbr_packets = std::cmp::min(IMA4_BLOCK_PACKETS as u64, self.remaining_packets) as usize;
+      if nbr_packets == 0 || self.frames_left == 0 {
+        return Ok(None);
+      }
+      let packet_len = IMA4_PACKET_LEN * channels;
+      self.packets.resize(nbr_packets * packet_len, 0);
+      let mut filled = 0;
+      while filled < self.packets.len() {
+        match self.reader.read(&mut self.packets[filled..]) {
+          Ok(0) => break,
+          Ok(read) => filled += read,
+          Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+          Err(err) => return Err(format!("Cannot read IMA4 packets : {}", err)),
+        }
+      }
+      // A data chunk running to the end of the file stops at its last whole packet
+      if filled < self.packets.len() {
+        self.packets.truncate(filled - filled % packet_len);
+        self.remaining_packets = 0;
+      } else {
+        self.remaining_packets -= nbr_packets as u64;
+      }
+      if self.packets.is_empty() {
+        return Ok(None);
+      }
+
+      let mut block = vec![Vec::with_capacity(nbr_packets * IMA4_PACKET_FRAMES); channels];
+      // Each packet holds the frames of every channel one after the other
+      for packet in self.packets.chunks_exact(packet_len) {
+        for (channel, data) in block.iter_mut().zip(packet.chunks_exact(IMA4_PACKET_LEN)) {
+          decode_ima4_packet(data, channel);
+        }
+      }
+
+      let skipped = std::cmp::min(self.frames_to_skip, block[0].len());
+      self.frames_to_skip -= skipped;
+      let kept = std::cmp::min((block[0].len() - skipped) as u64, self.frames_left) as usize;
+      self.frames_left -= kept as u64;
+      if kept > 0 {
+        return Ok(Some(
+          block
+            .into_iter()
+            .map(|channel| channel[skipped..skipped + kept].to_vec())
+            .collect(),
+        ));
+      }
+    }
+  }
+}
+
+/**
+ Audio description of the `desc` chunk.
+*/
+struct CafDescription {
+  sample_rate: f64,
+  format_id: [u8; 4],
+  format_flags: u32,
+  bytes_per_packet: u32,
+  channels: u32,
+  bits_per_channel: u32,
+}
+
+/**
+ Parses the chunks of a CAF file and returns a source positioned at the samples of its data chunk,
+ for LPCM, mu-law, A-law or IMA4 payloads.
+*/
+pub fn caf_source<R: Read + Seek + 'static>(mut reader: R) -> Result<Box<dyn AudioSource>, String> {
+  let read_error = |err: std::io::Error| format!("Invalid CAF file : {}", err);
+  let mut header = [0u8; 8];
+  reader.read_exact(&mut header).map_err(read_error)?;
+  if !is_caf(&header) {
+    return Err("Not a CAF file".to_string());
+  }
+
+  let mut desc = None;
+  let mut data = None;
+  // Number of valid frames and priming frames of the pakt chunk
+  let mut packet_table = None;
+  loop {
+    let mut id = [0u8; 4];
+    match reader.read_exact(&mut id) {
+      Ok(()) => {}
+      Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+      Err(err) => return Err(read_error(err)),
+    }
+    let size = reader.read_i64::<BigEndian>().map_err(read_error)?;
+    let start = reader.stream_position().map_err(read_error)?;
+    match &id {
+      b"desc" => {
+        let sample_rate = reader.read_f64::<BigEndian>().map_err(read_error)?;
+        let mut format_id = [0u8; 4];
+        reader.read_exact(&mut format_id).map_err(read_error)?;
+        let mut fields = [0u32; 5];
+        reader
+          .read_u32_into::<BigEndian>(&mut fields)
+          .map_err(read_error)?;
+        let [format_flags, bytes_per_packet, _frames_per_packet, channels, bits_per_channel] =
+          fields;
+        desc = Some(CafDescription {
+          sample_rate,
+          format_id,
+          format_flags,
+          bytes_per_packet,
+          channels,
+          bits_per_channel,
+        });
+      }
+      b"pakt" => {
+        let _nbr_packets = reader.read_i64::<BigEndian>().map_err(read_error)?;
+        let nbr_valid_frames = reader.read_i64::<BigEndian>().map_err(read_error)?;
+        let priming_frames = reader.read_i32::<BigEndian>().map_err(read_error)?;
+        packet_table = Some((nbr_valid_frames as u64, priming_frames.max(0) as usize));
+      }
+      b"data" => {
+        // The data chunk starts with an edit count, a size of -1 runs to the end of the file
+        let data_len = if size < 0 {
+          None
+        } else {
+          Some((size as u64).saturating_sub(4))
+        };
+        data = Some((start + 4, data_len));
+        if data_len.is_none() {
+          break;
+        }
+      }
+      _ => debug!("Skipping CAF chunk {}", String::from_utf8_lossy(&id)),
+    }
+    reader
+      .seek(SeekFrom::Start(start + size.max(0) as u64))
+      .map_err(read_error)?;
+  }
+
+  let desc = desc.ok_or_else(|| "CAF file has no desc chunk".to_string())?;
+  let (data_start, data_len) = data.ok_or_else(|| "CAF file has no data chunk".to_string())?;
+  let channels = desc.channels as usize;
+  let sample_rate = desc.sample_rate.round() as u32;
+  if channels == 0 || sample_rate == 0 {
+    return Err(format!(
+      "Invalid CAF stream of {} channel(s) at {} Hz",
+      channels, desc.sample_rate
+    ));
+  }
+  reader
+    .seek(SeekFrom::Start(data_start))
+    .map_err(read_error)?;
+
+  let format = match &desc.format_id {
+    b"lpcm" => {
+      let big_endian = desc.format_flags & 2 == 0;
+      let bits = desc.bits_per_channel;
+      let container_bits = desc.bytes_per_packet / desc.channels * 8;
+      if desc.format_flags & 1 != 0 && (bits == 32 || bits == 64) {
+        SampleFormat::Float { bits, big_endian }
+      } else if desc.format_flags & 1 == 0
+        && bits > 0
+        && bits <= container_bits
+        && container_bits <= 32
+      {
+        SampleFormat::Int {
+          bits: container_bits,
+          big_endian,
+        }
+      } else {
+        return Err(format!("Unsupported CAF LPCM of {} bits", bits));
+      }
+    }
+    b"ulaw" => SampleFormat::MuLaw,
+    b"alaw" => SampleFormat::ALaw,
+    b"ima4" => {
+      let nbr_packets = data_len.unwrap_or(u64::MAX) / (IMA4_PACKET_LEN * channels) as u64;
+      let (frames_left, frames_to_skip) = packet_table.unwrap_or((u64::MAX, 0));
+      debug!(
+        "CAF IMA4 stream of {:?} channel(s) at {:?} Hz, {:?} priming frames",
+        channels, sample_rate, frames_to_skip
+      );
+      return Ok(Box::new(Ima4Source {
+        reader,
+        spec: AudioSpec {
+          sample_rate,
+          channels,
+          bits_per_sample: 16,
+        },
+        remaining_packets: nbr_packets,
+        frames_to_skip,
+        frames_left,
+        nbr_frames: packet_table.map(|(valid_frames, _)| valid_frames),
+        packets: Vec::new(),
+      }));
+    }
+    format_id => {
+      return Err(format!(
+        "Unsupported CAF format {}",
+        String::from_utf8_lossy(format_id)
+      ))
+    }
+  };
+  Ok(Box::new(PcmSource::new(
+    reader,
+    sample_rate,
+    channels,
+    format,
+    data_len,
+  )?))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  fn caf(format_id: &[u8; 4], flags: u32, bytes_per_packet: u32, bits: u32) -> Vec<u8> {
+    let mut file = b"caff\0\x01\0\0desc".to_vec();
+    file.extend_from_slice(&32i64.to_be_bytes());
+    file.extend_from_slice(&44100f64.to_be_bytes());
+    file.extend_from_slice(format_id);
+    let frames_per_packet: u32 = if format_id == b"ima4" { 64 } else { 1 };
+    for field in [flags, bytes_per_packet, frames_per_packet, 2, bits] {
+      file.extend_from_slice(&field.to_be_bytes());
+    }
+    file
+  }
+
+  fn read_all(file: Vec<u8>) -> Vec<Vec<f32>> {
+    let mut source = caf_source(Cursor::new(file)).unwrap();
+    assert_eq!(source.spec().sample_rate, 44100);
+    assert_eq!(source.spec().channels, 2);
+    let mut planar = vec![Vec::new(); 2];
+    while let Some(block) = source.read_block().unwrap() {
+      for (all, chan) in planar.iter_mut().zip(block) {
+        all.extend(chan);
+      }
+    }
+    planar
+  }
+
+  #[test]
+  fn test_caf_lpcm() {
+    // Little-endian 16 bits, with a free chunk before the samples
+    let mut file = caf(b"lpcm", 2, 4, 16);
+    file.extend_from_slice(b"free");
+    file.extend_from_slice(&3i64.to_be_bytes());
+    file.extend_from_slice(&[0; 3]);
+    file.extend_from_slice(b"data");
+    file.extend_from_slice(&(-1i64).to_be_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&[0xFF, 0x7F, 0x01, 0x80, 0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(read_all(file), vec![vec![1.0, 0.0], vec![-1.0, 0.0]]);
+
+    let mut file = caf(b"lpcm", 1, 8, 32);
+    file.extend_from_slice(b"data");
+    file.extend_from_slice(&12i64.to_be_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&0.5f32.to_be_bytes());
+    file.extend_from_slice(&(-0.25f32).to_be_bytes());
+    assert_eq!(read_all(file), vec![vec![0.5], vec![-0.25]]);
+  }
+
+  #[test]
+  fn test_caf_ima4() {
+    let mut file = caf(b"ima4", 0, 68, 0);
+    file.extend_from_slice(b"pakt");
+    file.extend_from_slice(&24i64.to_be_bytes());
+    file.extend_from_slice(&2i64.to_be_bytes()); // packets
+    file.extend_from_slice(&100i64.to_be_bytes()); // valid frames
+    file.extend_from_slice(&10i32.to_be_bytes()); // priming frames
+    file.extend_from_slice(&18i32.to_be_bytes()); // remainder frames
+    file.extend_from_slice(b"data");
+    file.extend_from_slice(&(4 + 2 * 68i64).to_be_bytes());
+    file.extend_from_slice(&[0; 4]);
+    for _packet in 0..2 {
+      // Left channel starts at 4096 with the step index 0, right channel is silent
+      file.extend_from_slice(&[0x10, 0x00, 0xF7]);
+      file.extend_from_slice(&[0; 31]);
+      file.extend_from_slice(&[0; 34]);
+    }
+    let planar = read_all(file);
+    assert_eq!(planar[0].len(), 100);
+    assert_eq!(planar[1].len(), 100);
+    assert!(planar[1].iter().all(|&sample| sample == 0.0));
+
+    // Nibble 7 with a step of 7 adds 11, nibble 15 with a step of 16 removes 30
+    let mut first_packet = Vec::new();
+    let mut packet = vec![0x10, 0x00, 0xF7];
+    packet.resize(IMA4_PACKET_LEN, 0);
+    decode_ima4_packet(&packet, &mut first_packet);
+    assert_eq!(first_packet.len(), 64);
+    assert_eq!(first_packet[0], 4107.0 / 32767.0);
+    assert_eq!(first_packet[1], 4077.0 / 32767.0);
+    assert_eq!(planar[0][0], first_packet[10]);
+    assert_eq!(planar[0][54], first_packet[0]);
+  }
+
+  #[test]
+  fn test_caf_rejects_other_formats() {
+    let mut file = caf(b"aac ", 0, 0, 0);
+    file.extend_from_slice(b"data");
+    file.extend_from_slice(&4i64.to_be_bytes());
+    file.extend_from_slice(&[0; 4]);
+    assert!(caf_source(Cursor::new(file)).is_err());
+    assert!(caf_source(Cursor::new(b"RIFF\0\0\0\0".to_vec())).is_err());
+  }
+}
@@ -0,0 +1,907 @@
+use log::debug;
+use md5::{Digest, Md5};
+use std::io::{Seek, SeekFrom, Write};
+
+use symphonia::default::codecs::FlacDecoder;
+use symphonia::default::formats::FlacReader;
+
+use super::packets::PacketSource;
+use super::{AudioSink, AudioSpec};
+
+/**
+ Streams the frames of a FLAC file, the format being taken from its STREAMINFO block.
+*/
+pub type FlacSource = PacketSource<FlacReader, FlacDecoder>;
+
+/**
+ Encoder settings derived from a compression level, from 0 (fastest) to 8 (smallest), like the
+ levels of the reference `flac` tool.
+*/
+#[derive(Clone, Copy, Debug)]
+struct FlacPreset {
+  block_size: usize,
+  stereo_decorrelation: bool,
+  max_lpc_order: usize,
+  max_partition_order: u32,
+}
+
+pub const DEFAULT_COMPRESSION_LEVEL: u8 = 5;
+
+fn flac_preset(compression_level: u8) -> Result<FlacPreset, String> {
+  let (block_size, stereo_decorrelation, max_lpc_order, max_partition_order) =
+    match compression_level {
+      0 => (1152, false, 0, 3),
+      1 => (1152, true, 0, 3),
+      2 => (1152, true, 0, 4),
+      3 => (4096, false, 6, 4),
+      4 => (4096, true, 8, 4),
+      5 => (4096, true, 8, 5),
+      6 => (4096, true, 8, 6),
+      7 => (4096, true, 12, 6),
+      8 => (4096, true, 12, 8),
+      _ => {
+        return Err(format!(
+          "FLAC compression level must be between 0 and 8, got {}",
+          compression_level
+        ))
+      }
+    };
+  Ok(FlacPreset {
+    block_size,
+    stereo_decorrelation,
+    max_lpc_order,
+    max_partition_order,
+  })
+}
+
+// Big endian bit packer, FLAC fields are not byte aligned
+#[derive(Default)]
+struct BitWriter {
+  bytes: Vec<u8>,
+  acc: u64,
+  nbr_bits: u32,
+}
+
+impl BitWriter {
+  fn write(&mut self, value: u64, bits: u32) {
+    debug_assert!(bits <= 32);
+    if bits == 0 {
+      return;
+    }
+    self.acc = (self.acc << bits) | (value & ((1u64 << bits) - 1));
+    self.nbr_bits += bits;
+    while self.nbr_bits >= 8 {
+      self.nbr_bits -= 8;
+      self.bytes.push((self.acc >> self.nbr_bits) as u8);
+    }
+  }
+
+  fn write_signed(&mut self, value: i64, bits: u32) {
+    self.write(value as u64, bits);
+  }
+
+  fn write_unary(&mut self, zeros: u64) {
+    let mut zeros = zeros;
+    while zeros >= 32 {
+      self.write(0, 32);
+      zeros -= 32;
+    }
+    self.write(1, zeros as u32 + 1);
+  }
+
+  fn append(&mut self, other: &BitWriter) {
+    for &byte in &other.bytes {
+      self.write(byte as u64, 8);
+    }
+    self.write(other.acc, other.nbr_bits);
+  }
+
+  fn len_bits(&self) -> usize {
+    self.bytes.len() * 8 + self.nbr_bits as usize
+  }
+
+  fn align(&mut self) {
+    if self.nbr_bits > 0 {
+      self.write(0, 8 - self.nbr_bits);
+    }
+  }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+  data.iter().fold(0u8, |crc, &byte| {
+    (0..8).fold(crc ^ byte, |crc, _| {
+      if crc & 0x80 != 0 {
+        (crc << 1) ^ 0x07
+      } else {
+        crc << 1
+      }
+    })
+  })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+  data.iter().fold(0u16, |crc, &byte| {
+    (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+      if crc & 0x8000 != 0 {
+        (crc << 1) ^ 0x8005
+      } else {
+        crc << 1
+      }
+    })
+  })
+}
+
+// Frame numbers are coded like UTF-8 code points, extended up to 36 bits
+fn write_utf8_number(bits: &mut BitWriter, number: u64) {
+  if number < 0x80 {
+    bits.write(number, 8);
+    return;
+  }
+  let nbr_bytes = match number {
+    0..=0x7FF => 2,
+    0x800..=0xFFFF => 3,
+    0x1_0000..=0x1F_FFFF => 4,
+    0x20_0000..=0x3FF_FFFF => 5,
+    0x400_0000..=0x7FFF_FFFF => 6,
+    _ => 7,
+  };
+  let prefix = (0xFF00u64 >> nbr_bytes) & 0xFF;
+  let first_bits = 7u32.saturating_sub(nbr_bytes);
+  bits.write(prefix >> first_bits, 8 - first_bits);
+  bits.write(number >> (6 * (nbr_bytes - 1)), first_bits);
+  for byte in (0..nbr_bytes - 1).rev() {
+    bits.write(0x80 | ((number >> (6 * byte)) & 0x3F), 8);
+  }
+}
+
+fn sample_rate_code(sample_rate: u32) -> u64 {
+  match sample_rate {
+    88200 => 0b0001,
+    176400 => 0b0010,
+    192000 => 0b0011,
+    8000 => 0b0100,
+    16000 => 0b0101,
+    22050 => 0b0110,
+    24000 => 0b0111,
+    32000 => 0b1000,
+    44100 => 0b1001,
+    48000 => 0b1010,
+    96000 => 0b1011,
+    // Taken from the STREAMINFO block
+    _ => 0b0000,
+  }
+}
+
+fn sample_size_code(bits_per_sample: u32) -> u64 {
+  match bits_per_sample {
+    8 => 0b001,
+    12 => 0b010,
+    16 => 0b100,
+    20 => 0b101,
+    24 => 0b110,
+    _ => 0b000,
+  }
+}
+
+fn zigzag(residual: i64) -> u64 {
+  ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+// Best Rice parameter of a partition, checking the neighbours of the mean based estimation
+fn rice_parameter(folded: &[u64], max_parameter: u32) -> (u32, u64) {
+  let cost = |parameter: u32| -> u64 {
+    folded.len() as u64 * (parameter as u64 + 1)
+      + folded.iter().map(|u| u >> parameter).sum::<u64>()
+  };
+  let sum: u64 = folded.iter().sum();
+  let mean = sum / std::cmp::max(folded.len() as u64, 1);
+  let estimation = std::cmp::min(64 - mean.leading_zeros(), max_parameter);
+  (estimation.saturating_sub(1)..=std::cmp::min(estimation + 1, max_parameter))
+    .map(|parameter| (parameter, cost(parameter)))
+    .min_by_key(|&(_, bits)| bits)
+    .unwrap_or((0, cost(0)))
+}
+
+// Rice parameter and number of residuals of each partition
+type RicePartitions = Vec<(u32, usize)>;
+
+// Residual section of a subframe, with the partition order giving the fewest bits
+fn write_residual(
+  bits: &mut BitWriter,
+  residual: &[i64],
+  block_size: usize,
+  predictor_order: usize,
+  max_partition_order: u32,
+) {
+  let folded: Vec<u64> = residual.iter().map(|&r| zigzag(r)).collect();
+  let mut best: Option<(u64, u32, RicePartitions)> = None;
+  for partition_order in 0..=max_partition_order {
+    let partition_size = block_size >> partition_order;
+    if !block_size.is_multiple_of(1 << partition_order) || partition_size <= predictor_order {
+      break;
+    }
+    let mut total = 0u64;
+    let mut partitions = Vec::with_capacity(1 << partition_order);
+    let mut start = 0;
+    for partition in 0..(1usize << partition_order) {
+      let len = if partition == 0 {
+        partition_size - predictor_order
+      } else {
+        partition_size
+      };
+      let (parameter, cost) = rice_parameter(&folded[start..start + len], 30);
+      total += cost + 5;
+      partitions.push((parameter, len));
+      start += len;
+    }
+    if best.as_ref().is_none_or(|(bits, _, _)| total < *bits) {
+      best = Some((total, partition_order, partitions));
+    }
+  }
+  let (_, partition_order, partitions) = best.expect("a partition order of 0 is always valid");
+
+  let extended = partitions.iter().any(|&(parameter, _)| parameter > 14);
+  bits.write(extended as u64, 2);
+  bits.write(partition_order as u64, 4);
+  let mut start = 0;
+  for (parameter, len) in partitions {
+    bits.write(parameter as u64, if extended { 5 } else { 4 });
+    for &u in &folded[start..start + len] {
+      bits.write_unary(u >> parameter);
+      bits.write(u & ((1u64 << parameter) - 1), parameter);
+    }
+    start += len;
+  }
+}
+
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+  (order..samples.len())
+    .map(|i| match order {
+      0 => samples[i],
+      1 => samples[i] - samples[i - 1],
+      2 => samples[i] - 2 * samples[i - 1] + samples[i - 2],
+      3 => samples[i] - 3 * samples[i - 1] + 3 * samples[i - 2] - samples[i - 3],
+      _ => {
+        samples[i] - 4 * samples[i - 1] + 6 * samples[i - 2] - 4 * samples[i - 3] + samples[i - 4]
+      }
+    })
+    .collect()
+}
+
+// Linear prediction coefficients of every order up to `max_order`, through Levinson-Durbin
+fn lpc_coefficients(samples: &[i64], max_order: usize) -> Vec<Vec<f64>> {
+  let len = samples.len();
+  // Tukey(0.5) window
+  let windowed: Vec<f64> = samples
+    .iter()
+    .enumerate()
+    .map(|(i, &sample)| {
+      let taper = (len as f64 * 0.25).max(1.0);
+      let position = std::cmp::min(i, len - 1 - i) as f64;
+      let weight = if position < taper {
+        0.5 - 0.5 * (std::f64::consts::PI * position / taper).cos()
+      } else {
+        1.0
+      };
+      sample as f64 * weight
+    })
+    .collect();
+  let autoc: Vec<f64> = (0..=max_order)
+    .map(|lag| {
+      windowed[lag..]
+        .iter()
+        .zip(windowed.iter())
+        .map(|(a, b)| a * b)
+        .sum()
+    })
+    .collect();
+
+  let mut coefficients = Vec::with_capacity(max_order);
+  let mut lpc = vec![0.0; max_order + 1];
+  let mut error = autoc[0];
+  for order in 1..=max_order {
+    if error <= 0.0 {
+      break;
+    }
+    let mut acc = autoc[order];
+    for j in 1..order {
+      acc -= lpc[j] * autoc[order - j];
+    }
+    let reflection = acc / error;
+    let previous = lpc.clone();
+    lpc[order] = reflection;
+    for j in 1..order {
+      lpc[j] = previous[j] - reflection * previous[order - j];
+    }
+    error *= 1.0 - reflection * reflection;
+    coefficients.push(lpc[1..=order].to_vec());
+  }
+  coefficients
+}
+
+// Coefficients quantized on `precision` bits with their shift, as stored in the subframe
+fn quantize_lpc(coefficients: &[f64], precision: u32) -> Option<(Vec<i64>, u32)> {
+  let cmax = coefficients.iter().fold(0.0f64, |max, c| max.max(c.abs()));
+  if cmax <= 0.0 || !cmax.is_finite() {
+    return None;
+  }
+  let log2cmax = cmax.log2().floor() as i32 + 1;
+  let shift = std::cmp::min(precision as i32 - 1 - log2cmax, 15);
+  if shift < 0 {
+    return None;
+  }
+  let qmax = (1i64 << (precision - 1)) - 1;
+  let qmin = -(1i64 << (precision - 1));
+  let mut error = 0.0;
+  let quantized = coefficients
+    .iter()
+    .map(|c| {
+      error += c * (1i64 << shift) as f64;
+      let q = (error.round() as i64).clamp(qmin, qmax);
+      error -= q as f64;
+      q
+    })
+    .collect();
+  Some((quantized, shift as u32))
+}
+
+fn lpc_residual(samples: &[i64], quantized: &[i64], shift: u32) -> Vec<i64> {
+  let order = quantized.len();
+  (order..samples.len())
+    .map(|i| {
+      let prediction: i64 = quantized
+        .iter()
+        .enumerate()
+        .map(|(j, q)| q * samples[i - j - 1])
+        .sum();
+      samples[i] - (prediction >> shift)
+    })
+    .collect()
+}
+
+fn lpc_precision(block_size: usize) -> u32 {
+  match block_size {
+    0..=192 => 7,
+    193..=384 => 8,
+    385..=576 => 9,
+    577..=1152 => 10,
+    1153..=2304 => 11,
+    2305..=4608 => 12,
+    _ => 13,
+  }
+}
+
+// Smallest subframe among constant, verbatim, fixed and LPC predictions
+fn encode_subframe(samples: &[i64], bits_per_sample: u32, preset: &FlacPreset) -> BitWriter {
+  let block_size = samples.len();
+  if samples.iter().all(|&sample| sample == samples[0]) {
+    let mut bits = BitWriter::default();
+    bits.write(0b0000_0000, 8);
+    bits.write_signed(samples[0], bits_per_sample);
+    return bits;
+  }
+
+  let mut best = BitWriter::default();
+  best.write(0b0000_0010, 8);
+  for &sample in samples {
+    best.write_signed(sample, bits_per_sample);
+  }
+
+  for order in 0..=std::cmp::min(4, block_size.saturating_sub(1)) {
+    let mut bits = BitWriter::default();
+    bits.write(0b0001_0000 | (order as u64) << 1, 8);
+    for &sample in &samples[..order] {
+      bits.write_signed(sample, bits_per_sample);
+    }
+    write_residual(
+      &mut bits,
+      &fixed_residual(samples, order),
+      block_size,
+      order,
+      preset.max_partition_order,
+    );
+    if bits.len_bits() < best.len_bits() {
+      best = bits;
+    }
+  }
+
+  let max_lpc_order = std::cmp::min(preset.max_lpc_order, block_size.saturating_sub(1));
+  let precision = lpc_precision(block_size);
+  for coefficients in lpc_coefficients(samples, max_lpc_order) {
+    let order = coefficients.len();
+    let Some((quantized, shift)) = quantize_lpc(&coefficients, precision) else {
+      continue;
+    };
+    let residual = lpc_residual(samples, &quantized, shift);
+    // The decoders keep the residual on 32 bits
+    if residual
+      .iter()
+      .any(|&r| r > i32::MAX as i64 || r < i32::MIN as i64)
+    {
+      continue;
+    }
+    let mut bits = BitWriter::default();
+    bits.write(0b0100_0000 | ((order as u64 - 1) << 1), 8);
+    for &sample in &samples[..order] {
+      bits.write_signed(sample, bits_per_sample);
+    }
+    bits.write(precision as u64 - 1, 4);
+    bits.write(shift as u64, 5);
+    for &q in &quantized {
+      bits.write_signed(q, precision);
+    }
+    write_residual(
+      &mut bits,
+      &residual,
+      block_size,
+      order,
+      preset.max_partition_order,
+    );
+    if bits.len_bits() < best.len_bits() {
+      best = bits;
+    }
+  }
+  best
+}
+
+/**
+ Writes a FLAC stream from interleaved f32 samples into any seekable writer, the STREAMINFO block
+ being completed with the total number of samples and the MD5 signature once `finish` is called.
+
+ # Example
+
+ ```
+ let spec = AudioSpec { sample_rate: 16000, channels: 1, bits_per_sample: 16 };
+ let mut writer = FlacWriter::new(File::create("out.flac")?, spec, DEFAULT_COMPRESSION_LEVEL)?;
+ writer.write_samples(&samples)?;
+ writer.finish()?;
+ ```
+*/
+pub struct FlacWriter<W: Write + Seek> {
+  writer: W,
+  spec: AudioSpec,
+  preset: FlacPreset,
+  pending: Vec<i64>,
+  md5: Md5,
+  stream_start: u64,
+  nbr_frames: u64,
+  frame_number: u64,
+  min_frame_size: u32,
+  max_frame_size: u32,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+  pub fn new(mut writer: W, spec: AudioSpec, compression_level: u8) -> Result<Self, String> {
+    let preset = flac_preset(compression_level)?;
+    if spec.channels == 0 || spec.channels > 8 {
+      return Err(format!(
+        "FLAC supports 1 to 8 channels, got {}",
+        spec.channels
+      ));
+    }
+    if !(4..=24).contains(&spec.bits_per_sample) {
+      return Err(format!(
+        "FLAC encoding supports 4 to 24 bits per sample, got {}",
+        spec.bits_per_sample
+      ));
+    }
+    if spec.sample_rate == 0 || spec.sample_rate >= 1 << 20 {
+      return Err(format!("Invalid FLAC sample rate {}", spec.sample_rate));
+    }
+    let stream_start = writer.stream_position().map_err(|err| err.to_string())?;
+    let mut flac_writer = FlacWriter {
+      writer,
+      spec,
+      preset,
+      pending: Vec::new(),
+      md5: Md5::new(),
+      stream_start,
+      nbr_frames: 0,
+      frame_number: 0,
+      min_frame_size: 0,
+      max_frame_size: 0,
+    };
+    // Placeholder, rewritten by `finish` when sizes and signature are known
+    let header = flac_writer.header();
+    flac_writer
+      .writer
+      .write_all(&header)
+      .map_err(|err| format!("Cannot write FLAC header : {}", err))?;
+    Ok(flac_writer)
+  }
+
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+
+  fn header(&self) -> Vec<u8> {
+    let block_size = self.preset.block_size as u64;
+    let mut bits = BitWriter::default();
+    bits.write(u32::from_be_bytes(*b"fLaC") as u64, 32);
+    // Last metadata block, STREAMINFO, 34 bytes
+    bits.write(1, 1);
+    bits.write(0, 7);
+    bits.write(34, 24);
+    bits.write(block_size, 16);
+    bits.write(block_size, 16);
+    bits.write(self.min_frame_size as u64, 24);
+    bits.write(self.max_frame_size as u64, 24);
+    bits.write(self.spec.sample_rate as u64, 20);
+    bits.write(self.spec.channels as u64 - 1, 3);
+    bits.write(self.spec.bits_per_sample as u64 - 1, 5);
+    bits.write(self.nbr_frames >> 32, 4);
+    bits.write(self.nbr_frames & 0xFFFF_FFFF, 32);
+    for byte in self.md5.clone().finalize() {
+      bits.write(byte as u64, 8);
+    }
+    bits.bytes
+  }
+
+  fn encode_frame(&mut self, nbr_frames: usize) -> Result<(), String> {
+    let channels = self.spec.channels;
+    let bps = self.spec.bits_per_sample;
+    let planar: Vec<Vec<i64>> = (0..channels)
+      .map(|chan| {
+        self.pending[..nbr_frames * channels]
+          .iter()
+          .skip(chan)
+          .step_by(channels)
+          .copied()
+          .collect()
+      })
+      .collect();
+    self.pending.drain(..nbr_frames * channels);
+
+    // Stereo can be coded as left/side, side/right or mid/side, the side taking one more bit
+    let (channel_assignment, subframes) = if channels == 2 && self.preset.stereo_decorrelation {
+      let side: Vec<i64> = planar[0]
+        .iter()
+        .zip(&planar[1])
+        .map(|(l, r)| l - r)
+        .collect();
+      let mid: Vec<i64> = planar[0]
+        .iter()
+        .zip(&planar[1])
+        .map(|(l, r)| (l + r) >> 1)
+        .collect();
+      let left = encode_subframe(&planar[0], bps, &self.preset);
+      let right = encode_subframe(&planar[1], bps, &self.preset);
+      let side = encode_subframe(&side, bps + 1, &self.preset);
+      let mid = encode_subframe(&mid, bps, &self.preset);
+      [
+        (0b0001, left.len_bits() + right.len_bits()),
+        (0b1000, left.len_bits() + side.len_bits()),
+        (0b1001, side.len_bits() + right.len_bits()),
+        (0b1010, mid.len_bits() + side.len_bits()),
+      ]
+      .into_iter()
+      .min_by_key(|&(_, len)| len)
+      .map(|(assignment, _)| match assignment {
+        0b1000 => (assignment, vec![left, side]),
+        0b1001 => (assignment, vec![side, right]),
+        0b1010 => (assignment, vec![mid, side]),
+        _ => (assignment, vec![left, right]),
+      })
+      .expect("four stereo modes")
+    } else {
+      (
+        channels as u64 - 1,
+        planar
+          .iter()
+          .map(|samples| encode_subframe(samples, bps, &self.preset))
+          .collect(),
+      )
+    };
+
+    let mut frame = BitWriter::default();
+    frame.write(0b11_1111_1111_1110, 14);
+    frame.write(0, 1);
+    frame.write(0, 1); // fixed block size
+    frame.write(0b0111, 4); // block size on 16 bits at the end of the header
+    frame.write(sample_rate_code(self.spec.sample_rate), 4);
+    frame.write(channel_assignment, 4);
+    frame.write(sample_size_code(bps), 3);
+    frame.write(0, 1);
+    write_utf8_number(&mut frame, self.frame_number);
+    frame.write(nbr_frames as u64 - 1, 16);
+    let header_crc = crc8(&frame.bytes);
+    frame.write(header_crc as u64, 8);
+    for subframe in &subframes {
+      frame.append(subframe);
+    }
+    frame.align();
+    let frame_crc = crc16(&frame.bytes);
+    frame.write(frame_crc as u64, 16);
+
+    let frame_size = frame.bytes.len() as u32;
+    self.min_frame_size = if self.frame_number == 0 {
+      frame_size
+    } else {
+      std::cmp::min(self.min_frame_size, frame_size)
+    };
+    self.max_frame_size = std::cmp::max(self.max_frame_size, frame_size);
+    self.frame_number += 1;
+    self
+      .writer
+      .write_all(&frame.bytes)
+      .map_err(|err| format!("Cannot write FLAC frame : {}", err))
+  }
+}
+
+impl<W: Write + Seek> AudioSink for FlacWriter<W> {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    let bps = self.spec.bits_per_sample;
+    let max = ((1i64 << (bps - 1)) - 1) as f64;
+    let bytes_per_sample = bps.div_ceil(8) as usize;
+    let mut signature = Vec::with_capacity(samples.len() * bytes_per_sample);
+    for &sample in samples {
+      let value = (sample.clamp(-1.0, 1.0) as f64 * max) as i64;
+      signature.extend_from_slice(&value.to_le_bytes()[..bytes_per_sample]);
+      self.pending.push(value);
+    }
+    self.md5.update(&signature);
+    self.nbr_frames += (samples.len() / self.spec.channels) as u64;
+
+    let block_len = self.preset.block_size * self.spec.channels;
+    while self.pending.len() >= block_len {
+      self.encode_frame(self.preset.block_size)?;
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    let remaining = self.pending.len() / self.spec.channels;
+    if remaining > 0 {
+      self.encode_frame(remaining)?;
+    }
+    debug!(
+      "FLAC stream of {:?} samples in {:?} frames",
+      self.nbr_frames, self.frame_number
+    );
+    let header = self.header();
+    let end = self
+      .writer
+      .stream_position()
+      .map_err(|err| err.to_string())?;
+    self
+      .writer
+      .seek(SeekFrom::Start(self.stream_start))
+      .and_then(|_| self.writer.write_all(&header))
+      .and_then(|_| self.writer.seek(SeekFrom::Start(end)))
+      .and_then(|_| self.writer.flush())
+      .map_err(|err| format!("Cannot complete FLAC STREAMINFO : {}", err))?;
+    Ok(())
+  }
+}
+
+/**
+ Encodes interleaved f32 samples as a complete in-memory FLAC stream.
+*/
+pub fn encode_flac(
+  samples: &[f32],
+  spec: AudioSpec,
+  compression_level: u8,
+) -> Result<Vec<u8>, String> {
+  let mut writer = FlacWriter::new(std::io::Cursor::new(Vec::new()), spec, compression_level)?;
+  writer.write_samples(samples)?;
+  writer.finish()?;
+  Ok(writer.into_inner().into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::AudioSource;
+  use std::io::Cursor;
+
+  // Single frame 16 bits stream with verbatim subframes
+  fn verbatim_flac(sample_rate: u32, samples: &[Vec<i16>]) -> Vec<u8> {
+    let channels = samples.len() as u64;
+    let block_size = samples[0].len() as u64;
+    let mut stream = b"fLaC".to_vec();
+    stream.extend_from_slice(&[0x80, 0, 0, 34]);
+    stream.extend_from_slice(&(block_size as u16).to_be_bytes());
+    stream.extend_from_slice(&(block_size as u16).to_be_bytes());
+    stream.extend_from_slice(&[0; 6]);
+    let packed: u64 =
+      ((sample_rate as u64) << 44) | ((channels - 1) << 41) | (15 << 36) | block_size;
+    stream.extend_from_slice(&packed.to_be_bytes());
+    stream.extend_from_slice(&[0; 16]);
+
+    let mut frame = vec![0xFF, 0xF8, 0x70, (((channels - 1) as u8) << 4) | 0x08, 0x00];
+    frame.extend_from_slice(&((block_size - 1) as u16).to_be_bytes());
+    frame.push(crc8(&frame));
+    for channel in samples {
+      frame.push(0x02); // verbatim
+      for sample in channel {
+        frame.extend_from_slice(&sample.to_be_bytes());
+      }
+    }
+    frame.extend_from_slice(&crc16(&frame).to_be_bytes());
+    stream.extend_from_slice(&frame);
+    stream
+  }
+
+  #[test]
+  fn test_flac_source_reads_streaminfo_and_frames() {
+    let samples = vec![
+      vec![
+        0, 16384, -16384, 32767, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+      ],
+      vec![
+        -1, -2, -3, -4, -5, -6, -7, -8, -9, -10, -11, -12, -13, -14, -15, -32768,
+      ],
+    ];
+    let flac = verbatim_flac(22050, &samples);
+    let mut source = FlacSource::new(Box::new(Cursor::new(flac))).unwrap();
+
+    assert_eq!(
+      source.spec(),
+      AudioSpec {
+        sample_rate: 22050,
+        channels: 2,
+        bits_per_sample: 16,
+      }
+    );
+    let block = source.read_block().unwrap().unwrap();
+    assert_eq!(block.len(), 2);
+    assert_eq!(block[0].len(), 16);
+    assert_eq!(block[0][1], 0.5);
+    assert_eq!(block[0][2], -0.5);
+    assert_eq!(block[1][15], -1.0);
+    assert!(source.read_block().unwrap().is_none());
+  }
+
+  #[test]
+  fn test_flac_source_rejects_other_data() {
+    let data = vec![0u8; 64];
+    assert!(FlacSource::new(Box::new(Cursor::new(data))).is_err());
+  }
+
+  fn noisy_sine(frames: usize, channels: usize) -> Vec<f32> {
+    let mut seed = 42u32;
+    (0..frames * channels)
+      .map(|i| {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        let noise = (seed >> 16) as f32 / 65536.0 - 0.5;
+        ((i / channels) as f32 * 0.03).sin() * 0.6 + noise * 0.05
+      })
+      .collect()
+  }
+
+  fn decode_all(flac: Vec<u8>) -> (AudioSpec, Vec<f32>) {
+    let mut source = FlacSource::new(Box::new(Cursor::new(flac))).unwrap();
+    let mut samples = Vec::new();
+    while let Some(block) = source.read_block().unwrap() {
+      crate::helpers::interleave_frames(&block, &mut samples);
+    }
+    (source.spec(), samples)
+  }
+
+  #[test]
+  fn test_encode_flac_round_trip_every_level() {
+    let samples = noisy_sine(10000, 2);
+    let spec = AudioSpec {
+      sample_rate: 44100,
+      channels: 2,
+      bits_per_sample: 16,
+    };
+    for level in 0..=8 {
+      let flac = encode_flac(&samples, spec, level).unwrap();
+      assert!(
+        flac.len() < samples.len() * 2,
+        "level {} does not compress",
+        level
+      );
+      let (decoded_spec, decoded) = decode_all(flac);
+      assert_eq!(decoded_spec, spec);
+      assert_eq!(decoded.len(), samples.len());
+      for (decoded, sample) in decoded.iter().zip(samples.iter()) {
+        let expected = (*sample as f64 * i16::MAX as f64) as i64;
+        assert_eq!((*decoded as f64 * 32768.0).round() as i64, expected);
+      }
+    }
+  }
+
+  #[test]
+  fn test_encode_flac_streaminfo() {
+    let samples = noisy_sine(5000, 1);
+    let spec = AudioSpec {
+      sample_rate: 16000,
+      channels: 1,
+      bits_per_sample: 24,
+    };
+    let flac = encode_flac(&samples, spec, DEFAULT_COMPRESSION_LEVEL).unwrap();
+
+    let mut expected_md5 = Md5::new();
+    for sample in &samples {
+      let value = (*sample as f64 * 8388607.0) as i64;
+      expected_md5.update(&value.to_le_bytes()[..3]);
+    }
+    let total_samples = u64::from_be_bytes(flac[18..26].try_into().unwrap()) & 0xF_FFFF_FFFF;
+    assert_eq!(total_samples, 5000);
+    assert_eq!(&flac[26..42], expected_md5.finalize().as_slice());
+
+    let (decoded_spec, decoded) = decode_all(flac);
+    assert_eq!(decoded_spec, spec);
+    assert_eq!(decoded.len(), 5000);
+  }
+
+  #[test]
+  fn test_write_utf8_number() {
+    let coded = |number: u64| {
+      let mut bits = BitWriter::default();
+      write_utf8_number(&mut bits, number);
+      bits.bytes
+    };
+    assert_eq!(coded(0x7F), [0x7F]);
+    assert_eq!(coded(256), [0xC4, 0x80]);
+    assert_eq!(coded(0x800), [0xE0, 0xA0, 0x80]);
+    assert_eq!(coded(0x1_0000), [0xF0, 0x90, 0x80, 0x80]);
+    assert_eq!(
+      coded(0xF_FFFF_FFFF),
+      [0xFE, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF, 0xBF]
+    );
+  }
+
+  #[test]
+  fn test_encode_flac_round_trip_past_128_frames() {
+    // 200 frames of 1152 samples, the frame numbers from 128 on taking 2 bytes
+    let samples = noisy_sine(230400, 1);
+    let spec = AudioSpec {
+      sample_rate: 16000,
+      channels: 1,
+      bits_per_sample: 16,
+    };
+    let flac = encode_flac(&samples, spec, 0).unwrap();
+    let streaminfo_md5 = flac[26..42].to_vec();
+
+    let (_, decoded) = decode_all(flac);
+    assert_eq!(decoded.len(), samples.len());
+    let mut decoded_md5 = Md5::new();
+    for sample in &decoded {
+      let value = (*sample as f64 * 32768.0).round() as i16;
+      decoded_md5.update(value.to_le_bytes());
+    }
+    assert_eq!(decoded_md5.finalize().as_slice(), streaminfo_md5);
+  }
+
+  #[test]
+  fn test_corrupted_flac_fails_to_decode() {
+    let samples = noisy_sine(20000, 1);
+    let spec = AudioSpec {
+      sample_rate: 16000,
+      channels: 1,
+      bits_per_sample: 16,
+    };
+    let flac = encode_flac(&samples, spec, 0).unwrap();
+    let read_all = |flac: Vec<u8>| {
+      let mut source = FlacSource::new(Box::new(Cursor::new(flac))).unwrap();
+      let mut nbr_samples = 0;
+      while let Some(block) = source.read_block()? {
+        nbr_samples += block[0].len();
+      }
+      Ok::<_, String>(nbr_samples)
+    };
+    assert_eq!(read_all(flac.clone()), Ok(20000));
+    // A flipped bit in the middle of a frame breaks its CRC
+    let mut flipped = flac.clone();
+    let middle = flipped.len() / 2;
+    flipped[middle] ^= 0x10;
+    let err = read_all(flipped).unwrap_err();
+    assert!(err.contains("missing frames"), "{}", err);
+    // So is a stream cut before the frames announced by its STREAMINFO
+    let err = read_all(flac[..flac.len() * 3 / 4].to_vec()).unwrap_err();
+    assert!(err.contains("truncated"), "{}", err);
+  }
+
+  #[test]
+  fn test_encode_flac_rejects_unknown_level() {
+    let spec = AudioSpec {
+      sample_rate: 16000,
+      channels: 1,
+      bits_per_sample: 16,
+    };
+    assert!(encode_flac(&[0.0; 16], spec, 9).is_err());
+  }
+}
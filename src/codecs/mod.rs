@@ -0,0 +1,362 @@
+pub mod aiff;
+pub mod au;
+pub mod caf;
+pub mod flac;
+pub mod mp3;
+pub mod opus;
+pub mod packets;
+pub mod pcm;
+pub mod raw;
+pub mod vorbis;
+pub mod wav;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use symphonia::core::io::MediaSource;
+
+use self::aiff::aiff_source;
+use self::au::{au_source, is_au};
+use self::caf::{caf_source, is_caf};
+use self::flac::FlacSource;
+use self::mp3::{is_mp3_frame_header, Mp3Source};
+use self::opus::{is_ogg_opus, opus_source};
+use self::vorbis::VorbisSource;
+use self::wav::{is_wav, wav_source};
+
+/**
+ Bytes needed by `InputFormat::from_magic`, the Ogg Opus identification header ending at byte 36 and
+ the second header of an MP3 without ID3 tag at most 1441 bytes after the first one.
+*/
+pub const MAGIC_LEN: usize = 2048;
+
+/**
+ Sample format of a decoded stream, as announced by its container header.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioSpec {
+  pub sample_rate: u32,
+  pub channels: usize,
+  pub bits_per_sample: u32,
+}
+
+/**
+ A decoder handing out planar f32 frames, block by block, so they can be streamed into the resampler
+ without holding the whole file in memory.
+*/
+pub trait AudioSource {
+  fn spec(&self) -> AudioSpec;
+
+  /**
+   Returns the next block of frames, one vector per channel, or `None` once the stream is exhausted.
+  */
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String>;
+
+  /**
+   Codec of the stream, like `pcm_s16le`, `flac` or `mp3`.
+  */
+  fn codec(&self) -> String;
+
+  /**
+   Format of the stored samples, the raw descriptor names (`s16le`, `mulaw`, ...) for PCM, the bit
+   depth for lossless codecs (`s16`, `s24`) and `f32` for the lossy ones.
+  */
+  fn sample_format(&self) -> String;
+
+  /**
+   Number of frames announced by the header, `None` when it has to be found by decoding the stream.
+  */
+  fn nbr_frames(&self) -> Option<u64> {
+    None
+  }
+
+  /**
+   Moves the stream to an input frame, past the end meaning the end, and returns `false` when it
+   cannot seek and has to be decoded up to the frame instead. Only uncompressed streams seek.
+  */
+  fn seek_frame(&mut self, _frame: u64) -> Result<bool, String> {
+    Ok(false)
+  }
+}
+
+/**
+ An encoder receiving interleaved f32 samples, in the same layout as the raw outputs, that completes
+ its container once `finish` is called.
+*/
+pub trait AudioSink {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String>;
+
+  fn finish(&mut self) -> Result<(), String>;
+}
+
+/**
+ Encoded inputs that can be decoded in front of the resampler.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+  Flac,
+  OggVorbis,
+  Mp3,
+  /// Decoded at 48 kHz, only with the `opus` feature
+  OggOpus,
+  /// AIFF or AIFF-C with uncompressed samples
+  Aiff,
+  /// RIFF WAVE, RF64 / BW64 or Wave64 with uncompressed samples
+  Wav,
+  /// Sun AU / NeXT .snd with linear, float, mu-law or A-law samples
+  Au,
+  /// Core Audio Format with LPCM, mu-law, A-law or IMA4 samples
+  Caf,
+}
+
+impl InputFormat {
+  /**
+   Format of a file from its extension, `None` meaning raw samples.
+  */
+  pub fn from_path(path: &str) -> Option<Self> {
+    if has_extension(path, "flac") {
+      Some(InputFormat::Flac)
+    } else if has_extension(path, "ogg") || has_extension(path, "oga") {
+      Some(InputFormat::OggVorbis)
+    } else if has_extension(path, "mp3") {
+      Some(InputFormat::Mp3)
+    } else if has_extension(path, "opus") {
+      Some(InputFormat::OggOpus)
+    } else if ["aif", "aiff", "aifc"]
+      .iter()
+      .any(|extension| has_extension(path, extension))
+    {
+      Some(InputFormat::Aiff)
+    } else if ["wav", "w64", "rf64", "bwf"]
+      .iter()
+      .any(|extension| has_extension(path, extension))
+    {
+      Some(InputFormat::Wav)
+    } else if has_extension(path, "au") || has_extension(path, "snd") {
+      Some(InputFormat::Au)
+    } else if has_extension(path, "caf") {
+      Some(InputFormat::Caf)
+    } else {
+      None
+    }
+  }
+
+  /**
+   Format of an in-memory file from its first bytes.
+  */
+  pub fn from_magic(data: &[u8]) -> Option<Self> {
+    if data.starts_with(b"fLaC") {
+      Some(InputFormat::Flac)
+    } else if is_ogg_opus(data) {
+      Some(InputFormat::OggOpus)
+    } else if data.starts_with(b"OggS") {
+      Some(InputFormat::OggVorbis)
+    } else if data.starts_with(b"FORM")
+      && (data.get(8..12) == Some(b"AIFF") || data.get(8..12) == Some(b"AIFC"))
+    {
+      Some(InputFormat::Aiff)
+    } else if is_wav(data) {
+      Some(InputFormat::Wav)
+    } else if is_au(data) {
+      Some(InputFormat::Au)
+    } else if is_caf(data) {
+      Some(InputFormat::Caf)
+    } else if data.starts_with(b"ID3") || is_mp3_frame_header(data) {
+      Some(InputFormat::Mp3)
+    } else {
+      None
+    }
+  }
+
+  /**
+   Format of a file from its first bytes, whatever its extension.
+  */
+  pub fn sniff(path: &str) -> Result<Option<Self>, String> {
+    let file = File::open(path).map_err(|err| format!("Can't open file {} : {}", path, err))?;
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    file
+      .take(MAGIC_LEN as u64)
+      .read_to_end(&mut magic)
+      .map_err(|err| format!("Can't read file {} : {}", path, err))?;
+    Ok(Self::from_magic(&magic))
+  }
+
+  /**
+   Name of the container, Ogg Vorbis and Ogg Opus both being `ogg`.
+  */
+  pub fn container(self) -> &'static str {
+    match self {
+      InputFormat::Flac => "flac",
+      InputFormat::OggVorbis | InputFormat::OggOpus => "ogg",
+      InputFormat::Mp3 => "mp3",
+      InputFormat::Aiff => "aiff",
+      InputFormat::Wav => "wav",
+      InputFormat::Au => "au",
+      InputFormat::Caf => "caf",
+    }
+  }
+
+  pub fn decoder(self, source: Box<dyn MediaSource>) -> Result<Box<dyn AudioSource>, String> {
+    Ok(match self {
+      InputFormat::Flac => Box::new(FlacSource::new(source)?),
+      InputFormat::OggVorbis => Box::new(VorbisSource::new(source)?),
+      InputFormat::Mp3 => Box::new(Mp3Source::new(source)?),
+      InputFormat::OggOpus => opus_source(source)?,
+      InputFormat::Aiff => Box::new(aiff_source(source)?),
+      InputFormat::Wav => Box::new(wav_source(source)?),
+      InputFormat::Au => Box::new(au_source(source)?),
+      InputFormat::Caf => caf_source(source)?,
+    })
+  }
+
+  pub fn open(self, path: &str) -> Result<Box<dyn AudioSource>, String> {
+    let file = File::open(path).map_err(|err| format!("Can't open file {} : {}", path, err))?;
+    self.decoder(Box::new(file))
+  }
+}
+
+/**
+ A file held in memory and shared with the sink writing it, so its bytes can be taken once the sink
+ is finished.
+*/
+#[derive(Clone, Default)]
+pub struct MemoryFile(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl MemoryFile {
+  pub fn take(&self) -> Vec<u8> {
+    std::mem::take(self.0.borrow_mut().get_mut())
+  }
+}
+
+impl Write for MemoryFile {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Seek for MemoryFile {
+  fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+    self.0.borrow_mut().seek(pos)
+  }
+}
+
+/**
+ Tells if a path ends with the given extension, whatever its case.
+*/
+pub fn has_extension(path: &str, extension: &str) -> bool {
+  Path::new(path)
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_has_extension() {
+    assert!(has_extension("/tmp/voice.flac", "flac"));
+    assert!(has_extension("/tmp/VOICE.FLAC", "flac"));
+    assert!(!has_extension("/tmp/voice.raw", "flac"));
+    assert!(!has_extension("/tmp/flac", "flac"));
+  }
+
+  #[test]
+  fn test_input_format_detection() {
+    assert_eq!(InputFormat::from_path("a.flac"), Some(InputFormat::Flac));
+    assert_eq!(
+      InputFormat::from_path("a.OGG"),
+      Some(InputFormat::OggVorbis)
+    );
+    assert_eq!(InputFormat::from_path("a.raw"), None);
+    assert_eq!(
+      InputFormat::from_magic(b"fLaC\0\0"),
+      Some(InputFormat::Flac)
+    );
+    assert_eq!(
+      InputFormat::from_magic(b"OggS\0\x02"),
+      Some(InputFormat::OggVorbis)
+    );
+    assert_eq!(InputFormat::from_path("a.mp3"), Some(InputFormat::Mp3));
+    assert_eq!(InputFormat::from_magic(b"ID3\x04"), Some(InputFormat::Mp3));
+    assert_eq!(
+      InputFormat::from_magic(&[0xFF, 0xFB, 0x90, 0xC0]),
+      Some(InputFormat::Mp3)
+    );
+    assert_eq!(InputFormat::from_magic(&[0, 1, 2, 3]), None);
+    assert_eq!(InputFormat::from_path("a.opus"), Some(InputFormat::OggOpus));
+    let mut opus = b"OggS".to_vec();
+    opus.resize(28, 0);
+    opus.extend_from_slice(b"OpusHead");
+    assert_eq!(InputFormat::from_magic(&opus), Some(InputFormat::OggOpus));
+    assert_eq!(InputFormat::from_path("a.aif"), Some(InputFormat::Aiff));
+    assert_eq!(InputFormat::from_path("a.AIFC"), Some(InputFormat::Aiff));
+    assert_eq!(
+      InputFormat::from_magic(b"FORM\0\0\0\0AIFC"),
+      Some(InputFormat::Aiff)
+    );
+    assert_eq!(InputFormat::from_path("a.w64"), Some(InputFormat::Wav));
+    assert_eq!(InputFormat::from_path("a.snd"), Some(InputFormat::Au));
+    assert_eq!(InputFormat::from_path("memo.caf"), Some(InputFormat::Caf));
+    assert_eq!(
+      InputFormat::from_magic(b"caff\0\x01"),
+      Some(InputFormat::Caf)
+    );
+    assert_eq!(
+      InputFormat::from_magic(b".snd\0\0\0\x18"),
+      Some(InputFormat::Au)
+    );
+    assert_eq!(
+      InputFormat::from_magic(b"RF64\xFF\xFF\xFF\xFFWAVE"),
+      Some(InputFormat::Wav)
+    );
+  }
+
+  #[test]
+  fn test_memory_file_is_shared_with_its_sink() {
+    use self::pcm::{PcmWriter, SampleFormat};
+    use self::wav::{WavHeader, WavLayout};
+
+    let format = SampleFormat::Int {
+      bits: 16,
+      big_endian: false,
+    };
+    let file = MemoryFile::default();
+    let header = WavHeader::new(WavLayout::Riff, 8000, 1, format).unwrap();
+    let mut sink: Box<dyn AudioSink> =
+      Box::new(PcmWriter::new(file.clone(), header, format, 1).unwrap());
+    sink.write_samples(&[0.25; 100]).unwrap();
+    sink.finish().unwrap();
+
+    let bytes = file.take();
+    assert_eq!(InputFormat::from_magic(&bytes), Some(InputFormat::Wav));
+    let mut source = InputFormat::Wav
+      .decoder(Box::new(Cursor::new(bytes)))
+      .unwrap();
+    assert_eq!(source.read_block().unwrap().unwrap()[0].len(), 100);
+  }
+
+  #[test]
+  fn test_sniff_ignores_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blob.raw");
+    std::fs::write(&path, b"fLaC\0\0\0\x22").unwrap();
+    let path = path.to_str().unwrap();
+    assert_eq!(InputFormat::from_path(path), None);
+    assert_eq!(InputFormat::sniff(path), Ok(Some(InputFormat::Flac)));
+    assert_eq!(InputFormat::Flac.container(), "flac");
+    assert_eq!(InputFormat::OggOpus.container(), "ogg");
+
+    std::fs::write(dir.path().join("short.bin"), b"fL").unwrap();
+    let short = dir.path().join("short.bin");
+    assert_eq!(InputFormat::sniff(short.to_str().unwrap()), Ok(None));
+    assert!(InputFormat::sniff(dir.path().join("missing").to_str().unwrap()).is_err());
+  }
+}
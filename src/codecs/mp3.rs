@@ -0,0 +1,141 @@
+use symphonia::default::codecs::MpaDecoder;
+use symphonia::default::formats::MpaReader;
+
+use super::packets::PacketSource;
+
+/**
+ Streams the frames of an MP3 file. The encoder delay and padding of a LAME/Xing tag are trimmed,
+ so the decoded frames are sample-aligned with the audio that was encoded.
+*/
+pub type Mp3Source = PacketSource<MpaReader, MpaDecoder>;
+
+/**
+ Layer III bitrates in kbps by bitrate index, for MPEG-1 and for MPEG-2 / 2.5.
+*/
+const MPEG1_BITRATES: [u32; 15] = [
+  0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const MPEG2_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/**
+ Length in bytes of the Layer III frame starting with this header, `None` when it is not one: no
+ sync, a reserved version, another layer, a free or invalid bitrate or a reserved sample rate.
+*/
+fn frame_len(header: &[u8]) -> Option<usize> {
+  if header.len() < 4
+    || header[0] != 0xFF
+    || header[1] & 0xE0 != 0xE0
+    || header[1] & 0x18 == 0x08
+    || header[1] & 0x06 != 0x02
+  {
+    return None;
+  }
+  let bitrate_index = (header[2] >> 4) as usize;
+  let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+  if bitrate_index == 0 || bitrate_index == 0x0F || sample_rate_index == 3 {
+    return None;
+  }
+  let padding = ((header[2] >> 1) & 0x01) as u32;
+  let sample_rate = [44100, 48000, 32000][sample_rate_index];
+  let len = match (header[1] >> 3) & 0x03 {
+    // MPEG-1
+    3 => 144 * MPEG1_BITRATES[bitrate_index] * 1000 / sample_rate + padding,
+    // MPEG-2
+    2 => 72 * MPEG2_BITRATES[bitrate_index] * 1000 / (sample_rate / 2) + padding,
+    // MPEG-2.5
+    _ => 72 * MPEG2_BITRATES[bitrate_index] * 1000 / (sample_rate / 4) + padding,
+  };
+  Some(len as usize)
+}
+
+/**
+ Tells if the data starts with an MPEG-1/2 Layer III frame header, for files without ID3 tag. Like
+ other probers, the next frame must start with a header of the same version, layer and sample
+ rate, unless the data ends before it, so raw samples looking like one header are not taken for MP3.
+*/
+pub fn is_mp3_frame_header(data: &[u8]) -> bool {
+  let Some(len) = frame_len(data) else {
+    return false;
+  };
+  match data.get(len..len + 4) {
+    Some(next) => {
+      frame_len(next).is_some()
+        && next[1] & 0x1E == data[1] & 0x1E
+        && next[2] & 0x0C == data[2] & 0x0C
+    }
+    None => true,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::AudioSource;
+  use std::io::Cursor;
+
+  // MPEG-1 Layer III, 128 kbps, 44100 Hz, mono: 417 bytes and 1152 samples per frame
+  const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0xC0];
+  const FRAME_SIZE: usize = 417;
+  const SIDE_INFO_SIZE: usize = 17;
+
+  // Silent frames, preceded by an Info tag carrying an encoder delay and padding
+  fn silent_mp3(nbr_frames: u32, raw_delay: u32, raw_padding: u32) -> Vec<u8> {
+    let mut tag_frame = FRAME_HEADER.to_vec();
+    tag_frame.extend_from_slice(&[0; SIDE_INFO_SIZE]);
+    tag_frame.extend_from_slice(b"Info");
+    tag_frame.extend_from_slice(&1u32.to_be_bytes()); // number of frames only
+    tag_frame.extend_from_slice(&nbr_frames.to_be_bytes());
+    tag_frame.extend_from_slice(b"Lavf58.76");
+    tag_frame.extend_from_slice(&[0; 12]); // revision, lowpass, replay gain, flags, bitrate
+    let trim = (raw_delay << 12) | raw_padding;
+    tag_frame.extend_from_slice(&trim.to_be_bytes()[1..]);
+    tag_frame.resize(FRAME_SIZE, 0);
+
+    let mut mp3 = tag_frame;
+    for _ in 0..nbr_frames {
+      mp3.extend_from_slice(&FRAME_HEADER);
+      mp3.resize(mp3.len() + FRAME_SIZE - FRAME_HEADER.len(), 0);
+    }
+    mp3
+  }
+
+  fn decoded_frames(mp3: Vec<u8>) -> usize {
+    let mut source = Mp3Source::new(Box::new(Cursor::new(mp3))).unwrap();
+    assert_eq!(source.spec().sample_rate, 44100);
+    assert_eq!(source.spec().channels, 1);
+    let mut nbr_frames = 0;
+    while let Some(block) = source.read_block().unwrap() {
+      nbr_frames += block[0].len();
+    }
+    nbr_frames
+  }
+
+  #[test]
+  fn test_mp3_source_trims_encoder_delay_and_padding() {
+    // The decoder delay of 529 samples is added to the delay of the tag
+    let mp3 = silent_mp3(4, 576, 529 + 300);
+    assert_eq!(decoded_frames(mp3), 4 * 1152 - (576 + 529) - 300);
+  }
+
+  #[test]
+  fn test_mp3_source_without_delay() {
+    let mp3 = silent_mp3(4, 0, 0);
+    assert_eq!(decoded_frames(mp3), 4 * 1152 - 529);
+  }
+
+  #[test]
+  fn test_is_mp3_frame_header() {
+    assert!(is_mp3_frame_header(&FRAME_HEADER));
+    assert!(is_mp3_frame_header(&silent_mp3(2, 0, 0)));
+    // Layer II
+    assert!(!is_mp3_frame_header(&[0xFF, 0xFD, 0x90, 0xC0]));
+    assert!(!is_mp3_frame_header(b"OggS"));
+    // Bitrate index 15, sample rate index 3
+    assert!(!is_mp3_frame_header(&[0xFF, 0xFB, 0xF0, 0xC0]));
+    assert!(!is_mp3_frame_header(&[0xFF, 0xFB, 0x9C, 0xC0]));
+    // Raw s16le samples starting with 0xFBFF, then no second header where the frame would end
+    let mut raw = FRAME_HEADER.to_vec();
+    raw.resize(FRAME_SIZE * 2, 0x11);
+    assert!(!is_mp3_frame_header(&raw));
+  }
+}
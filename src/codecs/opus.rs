@@ -0,0 +1,565 @@
+use std::io::Write;
+
+use symphonia::core::io::MediaSource;
+
+use super::{AudioSink, AudioSource, AudioSpec};
+
+#[cfg(feature = "opus")]
+use audiopus::coder::{Decoder, Encoder};
+#[cfg(feature = "opus")]
+use audiopus::packet::Packet;
+#[cfg(feature = "opus")]
+use audiopus::{Application, Channels, MutSignals, SampleRate};
+#[cfg(feature = "opus")]
+use log::debug;
+#[cfg(feature = "opus")]
+use ogg::writing::PacketWriteEndInfo;
+use ogg::PacketReader;
+#[cfg(feature = "opus")]
+use ogg::PacketWriter;
+
+/**
+ Opus always decodes at 48 kHz, granule positions and pre-skip are counted at this rate too.
+*/
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/**
+ Sample rates an Opus encoder can be fed with.
+*/
+pub const OPUS_ENCODER_SAMPLE_RATES: [u32; 5] = [48000, 24000, 16000, 12000, 8000];
+
+/**
+ Identification header of an Ogg Opus stream, as described in RFC 7845 section 5.1.
+ Only the channel mapping family 0 (mono or stereo) is supported.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpusHead {
+  pub channels: usize,
+  /// Frames at 48 kHz to drop at the start of the decoded stream
+  pub pre_skip: u16,
+  /// Sample rate of the original audio, informative only
+  pub input_sample_rate: u32,
+  /// Gain to apply to the decoded stream, in Q7.8 dB
+  pub output_gain: i16,
+}
+
+impl OpusHead {
+  pub fn parse(data: &[u8]) -> Result<Self, String> {
+    if data.len() < 19 || !data.starts_with(b"OpusHead") {
+      return Err("Stream has no OpusHead header".to_string());
+    }
+    if data[8] >> 4 != 0 {
+      return Err(format!("Unsupported OpusHead version {}", data[8]));
+    }
+    let channels = data[9] as usize;
+    let mapping_family = data[18];
+    if mapping_family != 0 || channels == 0 || channels > 2 {
+      return Err(format!(
+        "Unsupported Opus channel mapping family {} with {} channel(s)",
+        mapping_family, channels
+      ));
+    }
+    Ok(OpusHead {
+      channels,
+      pre_skip: u16::from_le_bytes([data[10], data[11]]),
+      input_sample_rate: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+      output_gain: i16::from_le_bytes([data[16], data[17]]),
+    })
+  }
+
+  #[cfg_attr(not(feature = "opus"), allow(dead_code))]
+  pub fn to_bytes(self) -> Vec<u8> {
+    let mut bytes = b"OpusHead".to_vec();
+    bytes.push(1);
+    bytes.push(self.channels as u8);
+    bytes.extend_from_slice(&self.pre_skip.to_le_bytes());
+    bytes.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&self.output_gain.to_le_bytes());
+    bytes.push(0);
+    bytes
+  }
+
+  /**
+   Linear factor of the output gain, `10^(gain / (20 * 256))`.
+  */
+  #[cfg_attr(not(feature = "opus"), allow(dead_code))]
+  pub fn gain_factor(&self) -> f32 {
+    10f32.powf(self.output_gain as f32 / (20.0 * 256.0))
+  }
+}
+
+/**
+ Tells if an in-memory file is an Ogg stream whose first packet is an OpusHead.
+*/
+pub fn is_ogg_opus(data: &[u8]) -> bool {
+  data.starts_with(b"OggS") && data.len() >= 36 && &data[28..36] == b"OpusHead"
+}
+
+fn read_opus_head(
+  reader: &mut PacketReader<Box<dyn MediaSource>>,
+) -> Result<(OpusHead, u32), String> {
+  let packet = reader
+    .read_packet()
+    .map_err(|err| format!("Invalid Ogg stream : {}", err))?
+    .ok_or_else(|| "Ogg stream is empty".to_string())?;
+  Ok((OpusHead::parse(&packet.data)?, packet.stream_serial()))
+}
+
+fn check_opus_spec(spec: AudioSpec) -> Result<(), String> {
+  if !OPUS_ENCODER_SAMPLE_RATES.contains(&spec.sample_rate) {
+    return Err(format!(
+      "Opus cannot be encoded at {} Hz, use one of {:?}",
+      spec.sample_rate, OPUS_ENCODER_SAMPLE_RATES
+    ));
+  }
+  if spec.channels == 0 || spec.channels > 2 {
+    return Err(format!(
+      "Opus can only be encoded in mono or stereo, not {} channels",
+      spec.channels
+    ));
+  }
+  Ok(())
+}
+
+/**
+ Streams the frames of an Ogg Opus file at 48 kHz. The pre-skip frames are dropped, the end is cut
+ at the granule position of the last page and the output gain of the OpusHead is applied.
+*/
+#[cfg(feature = "opus")]
+pub struct OpusSource {
+  reader: PacketReader<Box<dyn MediaSource>>,
+  decoder: Decoder,
+  head: OpusHead,
+  serial: u32,
+  gain: f32,
+  decoded: Vec<f32>,
+  // Frames of the stream decoded so far, pre-skip included
+  position: u64,
+}
+
+#[cfg(feature = "opus")]
+impl OpusSource {
+  pub fn new(source: Box<dyn MediaSource>) -> Result<Self, String> {
+    let mut reader = PacketReader::new(source);
+    let (head, serial) = read_opus_head(&mut reader)?;
+    let tags = reader
+      .read_packet()
+      .map_err(|err| format!("Invalid Ogg stream : {}", err))?
+      .ok_or_else(|| "Ogg Opus stream has no OpusTags header".to_string())?;
+    if !tags.data.starts_with(b"OpusTags") {
+      return Err("Ogg Opus stream has no OpusTags header".to_string());
+    }
+    let channels = if head.channels == 1 {
+      Channels::Mono
+    } else {
+      Channels::Stereo
+    };
+    let decoder = Decoder::new(SampleRate::Hz48000, channels)
+      .map_err(|err| format!("Cannot create Opus decoder : {}", err))?;
+    debug!("Opus stream is {:?}", head);
+
+    Ok(OpusSource {
+      reader,
+      decoder,
+      head,
+      serial,
+      gain: head.gain_factor(),
+      // 120 ms, the longest Opus packet
+      decoded: vec![0.0; 5760 * head.channels],
+      position: 0,
+    })
+  }
+}
+
+#[cfg(feature = "opus")]
+impl AudioSource for OpusSource {
+  fn spec(&self) -> AudioSpec {
+    AudioSpec {
+      sample_rate: OPUS_SAMPLE_RATE,
+      channels: self.head.channels,
+      bits_per_sample: 32,
+    }
+  }
+
+  fn codec(&self) -> String {
+    "opus".to_string()
+  }
+
+  fn sample_format(&self) -> String {
+    "f32".to_string()
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    let channels = self.head.channels;
+    loop {
+      let packet = match self
+        .reader
+        .read_packet()
+        .map_err(|err| format!("Cannot read Opus packet : {}", err))?
+      {
+        Some(packet) => packet,
+        None => return Ok(None),
+      };
+      if packet.stream_serial() != self.serial {
+        continue;
+      }
+      let decoded = Packet::try_from(&packet.data[..]).and_then(|input| {
+        let output = MutSignals::try_from(&mut self.decoded[..])?;
+        self.decoder.decode_float(Some(input), output, false)
+      });
+      let nbr_frames = match decoded {
+        Ok(nbr_frames) => nbr_frames as u64,
+        // Opus packets carry no timestamp, the error is at the 48 kHz frames decoded before it
+        Err(err) => {
+          return Err(format!(
+            "Cannot decode Opus packet at frame {} : {}",
+            self.position, err
+          ))
+        }
+      };
+
+      let start = self.position;
+      self.position += nbr_frames;
+      let first = std::cmp::max(start, self.head.pre_skip as u64);
+      // A page granule position counts the frames decoded up to its last packet, only the last
+      // page of the stream may end before them to cut the padding
+      let last = std::cmp::min(self.position, packet.absgp_page());
+      if last <= first {
+        continue;
+      }
+      let kept =
+        &self.decoded[(first - start) as usize * channels..(last - start) as usize * channels];
+      return Ok(Some(
+        (0..channels)
+          .map(|chan| {
+            kept
+              .iter()
+              .skip(chan)
+              .step_by(channels)
+              .map(|sample| sample * self.gain)
+              .collect()
+          })
+          .collect(),
+      ));
+    }
+  }
+}
+
+/**
+ Encodes interleaved f32 samples as an Ogg Opus stream, in 20 ms packets.
+
+ The OpusHead announces the encoder lookahead as pre-skip and the last granule position cuts the
+ padding of the final packet, so a decoder gives back exactly the frames that were written.
+*/
+#[cfg(feature = "opus")]
+pub struct OpusWriter<W: Write> {
+  writer: PacketWriter<W>,
+  encoder: Encoder,
+  serial: u32,
+  channels: usize,
+  frame_size: usize,
+  // 48 kHz frames per encoder frame
+  scale: u64,
+  lookahead: u64,
+  pre_skip: u64,
+  pending: Vec<f32>,
+  packet: Vec<u8>,
+  nbr_input_frames: u64,
+  nbr_encoded_frames: u64,
+}
+
+#[cfg(feature = "opus")]
+impl<W: Write> OpusWriter<W> {
+  pub fn new(writer: W, spec: AudioSpec) -> Result<Self, String> {
+    check_opus_spec(spec)?;
+    let sample_rate =
+      SampleRate::try_from(spec.sample_rate as i32).map_err(|err| err.to_string())?;
+    let channels = if spec.channels == 1 {
+      Channels::Mono
+    } else {
+      Channels::Stereo
+    };
+    let encoder = Encoder::new(sample_rate, channels, Application::Audio)
+      .map_err(|err| format!("Cannot create Opus encoder : {}", err))?;
+    let lookahead = encoder
+      .lookahead()
+      .map_err(|err| format!("Cannot get Opus encoder lookahead : {}", err))?
+      as u64;
+    let scale = (OPUS_SAMPLE_RATE / spec.sample_rate) as u64;
+    let head = OpusHead {
+      channels: spec.channels,
+      pre_skip: (lookahead * scale) as u16,
+      input_sample_rate: spec.sample_rate,
+      output_gain: 0,
+    };
+    let serial = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|duration| duration.subsec_nanos())
+      .unwrap_or(0);
+
+    let mut writer = PacketWriter::new(writer);
+    let vendor = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    writer
+      .write_packet(
+        head.to_bytes().into_boxed_slice(),
+        serial,
+        PacketWriteEndInfo::EndPage,
+        0,
+      )
+      .and_then(|_| {
+        writer.write_packet(
+          tags.into_boxed_slice(),
+          serial,
+          PacketWriteEndInfo::EndPage,
+          0,
+        )
+      })
+      .map_err(|err| format!("Cannot write Opus headers : {}", err))?;
+    debug!("Opus stream is {:?}", head);
+
+    Ok(OpusWriter {
+      writer,
+      encoder,
+      serial,
+      channels: spec.channels,
+      frame_size: spec.sample_rate as usize / 50,
+      scale,
+      lookahead,
+      pre_skip: head.pre_skip as u64,
+      pending: Vec::new(),
+      // Recommended maximum size of an Opus packet
+      packet: vec![0; 4000],
+      nbr_input_frames: 0,
+      nbr_encoded_frames: 0,
+    })
+  }
+
+  #[cfg(test)]
+  pub fn into_inner(self) -> W {
+    self.writer.into_inner()
+  }
+
+  fn encode_frame(&mut self, end_info: PacketWriteEndInfo) -> Result<(), String> {
+    let frame: Vec<f32> = self
+      .pending
+      .drain(..self.frame_size * self.channels)
+      .collect();
+    let size = self
+      .encoder
+      .encode_float(&frame, &mut self.packet)
+      .map_err(|err| format!("Cannot encode Opus packet : {}", err))?;
+    self.nbr_encoded_frames += self.frame_size as u64;
+    // Decoded frames include the pre-skip, the last page ends at the input frames after it
+    let granule = match end_info {
+      PacketWriteEndInfo::EndStream => self.pre_skip + self.nbr_input_frames * self.scale,
+      _ => self.nbr_encoded_frames * self.scale,
+    };
+    self
+      .writer
+      .write_packet(self.packet[..size].into(), self.serial, end_info, granule)
+      .map_err(|err| format!("Cannot write Opus packet : {}", err))
+  }
+}
+
+#[cfg(feature = "opus")]
+impl<W: Write> AudioSink for OpusWriter<W> {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    self.pending.extend_from_slice(samples);
+    self.nbr_input_frames += (samples.len() / self.channels) as u64;
+    // The last packet is kept for `finish`, it has to end the stream
+    while self.pending.len() > self.frame_size * self.channels {
+      self.encode_frame(PacketWriteEndInfo::NormalPacket)?;
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    // Silence is appended so the frames delayed by the lookahead get out of the encoder
+    let nbr_frames = self.nbr_input_frames + self.lookahead - self.nbr_encoded_frames;
+    let nbr_packets = std::cmp::max(1, nbr_frames.div_ceil(self.frame_size as u64)) as usize;
+    self
+      .pending
+      .resize(nbr_packets * self.frame_size * self.channels, 0.0);
+    for packet in 0..nbr_packets {
+      self.encode_frame(if packet + 1 == nbr_packets {
+        PacketWriteEndInfo::EndStream
+      } else {
+        PacketWriteEndInfo::NormalPacket
+      })?;
+    }
+    debug!(
+      "Opus stream of {:?} frames in {:?} packets",
+      self.nbr_input_frames,
+      self.nbr_encoded_frames / self.frame_size as u64
+    );
+    self
+      .writer
+      .inner_mut()
+      .flush()
+      .map_err(|err| format!("Cannot write Opus stream : {}", err))
+  }
+}
+
+/**
+ Decoder of an Ogg Opus stream, only available when the crate is built with the `opus` feature.
+*/
+#[cfg(feature = "opus")]
+pub fn opus_source(source: Box<dyn MediaSource>) -> Result<Box<dyn AudioSource>, String> {
+  Ok(Box::new(OpusSource::new(source)?))
+}
+
+#[cfg(not(feature = "opus"))]
+pub fn opus_source(source: Box<dyn MediaSource>) -> Result<Box<dyn AudioSource>, String> {
+  let (head, _serial) = read_opus_head(&mut PacketReader::new(source))?;
+  Err(format!(
+    "Cannot decode Ogg Opus stream of {} channel(s), built without the `opus` feature",
+    head.channels
+  ))
+}
+
+/**
+ Encoder of an Ogg Opus stream, only available when the crate is built with the `opus` feature.
+*/
+#[cfg(feature = "opus")]
+pub fn opus_sink<W: Write + 'static>(
+  writer: W,
+  spec: AudioSpec,
+) -> Result<Box<dyn AudioSink>, String> {
+  Ok(Box::new(OpusWriter::new(writer, spec)?))
+}
+
+#[cfg(not(feature = "opus"))]
+pub fn opus_sink<W: Write + 'static>(
+  writer: W,
+  spec: AudioSpec,
+) -> Result<Box<dyn AudioSink>, String> {
+  check_opus_spec(spec)?;
+  drop(writer);
+  Err("Cannot encode Ogg Opus stream, built without the `opus` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ogg::writing::PacketWriteEndInfo;
+  use ogg::PacketWriter;
+  use std::io::Cursor;
+
+  fn opus_head() -> OpusHead {
+    OpusHead {
+      channels: 2,
+      pre_skip: 312,
+      input_sample_rate: 44100,
+      output_gain: -256 * 6,
+    }
+  }
+
+  #[test]
+  fn test_opus_head_round_trip() {
+    let head = opus_head();
+    let bytes = head.to_bytes();
+    assert_eq!(bytes.len(), 19);
+    assert_eq!(OpusHead::parse(&bytes), Ok(head));
+    assert!((head.gain_factor() - 0.501_187).abs() < 1e-5);
+
+    let mut surround = bytes.clone();
+    surround[18] = 1;
+    assert!(OpusHead::parse(&surround).is_err());
+    assert!(OpusHead::parse(b"OpusTags").is_err());
+  }
+
+  #[test]
+  fn test_is_ogg_opus() {
+    let mut writer = PacketWriter::new(Vec::new());
+    writer
+      .write_packet(
+        opus_head().to_bytes().into_boxed_slice(),
+        7,
+        PacketWriteEndInfo::EndPage,
+        0,
+      )
+      .unwrap();
+    let stream = writer.into_inner();
+    assert!(is_ogg_opus(&stream));
+    assert!(!is_ogg_opus(b"OggS"));
+
+    let mut reader = PacketReader::new(Box::new(Cursor::new(stream)) as Box<dyn MediaSource>);
+    assert_eq!(read_opus_head(&mut reader), Ok((opus_head(), 7)));
+  }
+
+  #[test]
+  fn test_opus_spec_checks() {
+    let spec = AudioSpec {
+      sample_rate: 44100,
+      channels: 2,
+      bits_per_sample: 16,
+    };
+    assert!(opus_sink(Vec::new(), spec).is_err());
+    assert!(opus_sink(
+      Vec::new(),
+      AudioSpec {
+        sample_rate: 24000,
+        channels: 3,
+        ..spec
+      }
+    )
+    .is_err());
+  }
+
+  #[cfg(feature = "opus")]
+  #[test]
+  fn test_opus_round_trip() {
+    let spec = AudioSpec {
+      sample_rate: 24000,
+      channels: 1,
+      bits_per_sample: 32,
+    };
+    let samples: Vec<f32> = (0..24000).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+    let mut writer = OpusWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+    writer.write_samples(&samples).unwrap();
+    writer.finish().unwrap();
+    let stream = writer.into_inner().into_inner();
+    assert!(is_ogg_opus(&stream));
+
+    let mut source = OpusSource::new(Box::new(Cursor::new(stream))).unwrap();
+    assert_eq!(source.spec().sample_rate, OPUS_SAMPLE_RATE);
+    let mut nbr_frames = 0;
+    while let Some(block) = source.read_block().unwrap() {
+      nbr_frames += block[0].len();
+    }
+    assert_eq!(nbr_frames, 48000);
+  }
+
+  #[cfg(feature = "opus")]
+  #[test]
+  fn test_opus_granule_positions() {
+    let spec = AudioSpec {
+      sample_rate: 24000,
+      channels: 1,
+      bits_per_sample: 32,
+    };
+    // 500 packets, more than the 255 segments of a page
+    let samples: Vec<f32> = (0..240000).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+    let mut writer = OpusWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+    let pre_skip = writer.pre_skip;
+    writer.write_samples(&samples).unwrap();
+    writer.finish().unwrap();
+    let mut reader = PacketReader::new(Cursor::new(writer.into_inner().into_inner()));
+    let mut granules = Vec::new();
+    while let Some(packet) = reader.read_packet().unwrap() {
+      if packet.last_in_page() {
+        granules.push(packet.absgp_page());
+      }
+    }
+    // OpusHead and OpusTags pages, then audio pages ending on whole 20 ms packets at 48 kHz
+    let (last, pages) = granules[2..].split_last().unwrap();
+    assert!(!pages.is_empty());
+    assert!(pages.iter().all(|granule| granule % 960 == 0));
+    assert_eq!(*last, pre_skip + 480000);
+  }
+}
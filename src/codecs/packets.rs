@@ -0,0 +1,174 @@
+use log::debug;
+
+use symphonia::core::audio::{AudioBuffer, Signal};
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+
+use super::{AudioSource, AudioSpec};
+
+/**
+ Streams the decoded packets of a compressed container, the format being taken from the header of
+ its first track decodable by `D` (STREAMINFO for FLAC, identification header for Vorbis, ...).
+
+ # Example
+
+ ```
+ let mut source = FlacSource::new(Box::new(File::open("voice.flac")?))?;
+ let spec = source.spec(); // sample rate, channels and bit depth of the STREAMINFO
+ while let Some(block) = source.read_block()? {
+   // one Vec<f32> per channel
+ }
+ ```
+*/
+pub struct PacketSource<F: FormatReader, D: Decoder> {
+  reader: F,
+  decoder: D,
+  track_id: u32,
+  spec: AudioSpec,
+  codec: &'static str,
+  lossless: bool,
+  nbr_frames: Option<u64>,
+  // Timestamp the next packet of the track starts at, once a first packet is read
+  next_ts: Option<u64>,
+}
+
+impl<F: FormatReader, D: Decoder> PacketSource<F, D> {
+  /**
+   Encoder delay and padding announced by the container (LAME/Xing tag for MP3, granule positions
+   for Ogg) are trimmed, so the frames are aligned with the original audio.
+  */
+  pub fn new(source: Box<dyn MediaSource>) -> Result<Self, String> {
+    let options = FormatOptions {
+      enable_gapless: true,
+      ..Default::default()
+    };
+    Self::with_options(source, &options)
+  }
+
+  pub fn with_options(
+    source: Box<dyn MediaSource>,
+    options: &FormatOptions,
+  ) -> Result<Self, String> {
+    let stream = MediaSourceStream::new(source, Default::default());
+    let reader = F::try_new(stream, options).map_err(|err| format!("Invalid stream : {}", err))?;
+    let (track, descriptor) = reader
+      .tracks()
+      .iter()
+      .find_map(|track| {
+        D::supported_codecs()
+          .iter()
+          .find(|descriptor| descriptor.codec == track.codec_params.codec)
+          .map(|descriptor| (track, descriptor))
+      })
+      .ok_or_else(|| "Stream has no track of a supported codec".to_string())?;
+    let params = &track.codec_params;
+    let codec = descriptor.short_name;
+
+    let spec = AudioSpec {
+      sample_rate: params
+        .sample_rate
+        .ok_or_else(|| format!("{} header has no sample rate", codec))?,
+      channels: params
+        .channels
+        .ok_or_else(|| format!("{} header has no channels", codec))?
+        .count(),
+      // Lossy codecs have no bit depth, they are decoded as f32
+      bits_per_sample: params.bits_per_sample.unwrap_or(32),
+    };
+    let lossless = params.bits_per_sample.is_some();
+    let nbr_frames = params.n_frames;
+    let track_id = track.id;
+    let decoder = D::try_new(params, &DecoderOptions::default())
+      .map_err(|err| format!("Cannot create {} decoder : {}", codec, err))?;
+    debug!("{} stream is {:?}", codec, spec);
+
+    Ok(PacketSource {
+      reader,
+      decoder,
+      track_id,
+      spec,
+      codec,
+      lossless,
+      nbr_frames,
+      next_ts: None,
+    })
+  }
+}
+
+impl<F: FormatReader, D: Decoder> AudioSource for PacketSource<F, D> {
+  fn spec(&self) -> AudioSpec {
+    self.spec
+  }
+
+  fn codec(&self) -> String {
+    self.codec.to_string()
+  }
+
+  fn sample_format(&self) -> String {
+    if self.lossless {
+      format!("s{}", self.spec.bits_per_sample)
+    } else {
+      "f32".to_string()
+    }
+  }
+
+  fn nbr_frames(&self) -> Option<u64> {
+    self.nbr_frames
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    loop {
+      let packet = match self.reader.next_packet() {
+        Ok(packet) => packet,
+        Err(SymphoniaError::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+          let end = self.next_ts.unwrap_or(0);
+          if self.lossless && self.nbr_frames.is_some_and(|nbr_frames| end < nbr_frames) {
+            return Err(format!(
+              "{} stream ends at frame {} instead of {}, it is truncated",
+              self.codec,
+              end,
+              self.nbr_frames.unwrap_or(0)
+            ));
+          }
+          return Ok(None);
+        }
+        Err(err) => return Err(format!("Cannot read {} packet : {}", self.codec, err)),
+      };
+      if packet.track_id() != self.track_id {
+        continue;
+      }
+      // Packets failing their checksum are dropped by the reader, leaving a gap
+      if let Some(next_ts) = self.next_ts.filter(|&next_ts| packet.ts() > next_ts) {
+        return Err(format!(
+          "{} stream is missing frames {} to {}, it is corrupted",
+          self.codec,
+          next_ts,
+          packet.ts()
+        ));
+      }
+      self.next_ts = Some(packet.ts() + packet.dur());
+      match self.decoder.decode(&packet) {
+        Ok(decoded) => {
+          let mut planar = AudioBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+          decoded.convert(&mut planar);
+          return Ok(Some(
+            (0..self.spec.channels)
+              .map(|chan| planar.chan(chan).to_vec())
+              .collect(),
+          ));
+        }
+        // A corrupted packet fails the stream instead of shortening and shifting the audio after it
+        Err(err) => {
+          return Err(format!(
+            "Cannot decode {} packet at frame {} : {}",
+            self.codec,
+            packet.ts(),
+            err
+          ))
+        }
+      }
+    }
+  }
+}
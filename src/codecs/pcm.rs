@@ -0,0 +1,625 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use log::debug;
+
+use super::raw::sample_format_name;
+use super::{AudioSink, AudioSource, AudioSpec};
+
+/**
+ Frames handed out by each `read_block` of a `PcmSource`.
+*/
+const BLOCK_FRAMES: usize = 4096;
+
+/**
+ Encoding of the samples of an uncompressed container.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+  /// Signed integers of 8 to 32 bits, two's complement
+  Int { bits: u32, big_endian: bool },
+  /// IEEE floats of 32 or 64 bits
+  Float { bits: u32, big_endian: bool },
+  /// Unsigned bytes centered on 128, the 8 bits PCM of WAV
+  UInt8,
+  /// G.711 mu-law bytes
+  MuLaw,
+  /// G.711 A-law bytes
+  ALaw,
+}
+
+impl SampleFormat {
+  pub fn bytes_per_sample(self) -> usize {
+    match self {
+      SampleFormat::Int { bits, .. } | SampleFormat::Float { bits, .. } => {
+        bits.div_ceil(8) as usize
+      }
+      SampleFormat::UInt8 | SampleFormat::MuLaw | SampleFormat::ALaw => 1,
+    }
+  }
+
+  pub fn bits_per_sample(self) -> u32 {
+    match self {
+      SampleFormat::Int { bits, .. } | SampleFormat::Float { bits, .. } => bits,
+      SampleFormat::UInt8 | SampleFormat::MuLaw | SampleFormat::ALaw => 8,
+    }
+  }
+
+  /**
+   Decodes one sample, integers are scaled by their maximum like the raw `DataType.I16` input.
+  */
+  pub fn decode(self, bytes: &[u8]) -> f32 {
+    match self {
+      SampleFormat::Int { bits, big_endian } => {
+        let size = self.bytes_per_sample();
+        let mut value: i64 = 0;
+        for i in 0..size {
+          let byte = if big_endian {
+            bytes[i]
+          } else {
+            bytes[size - 1 - i]
+          };
+          value = (value << 8) | byte as i64;
+        }
+        // Sign extension of the container width, then drop the unused low bits
+        let shift = 64 - 8 * size as u32;
+        let value = ((value << shift) >> shift) >> (8 * size as u32 - bits);
+        (value as f64 / ((1i64 << (bits - 1)) - 1) as f64) as f32
+      }
+      SampleFormat::Float {
+        bits: 64,
+        big_endian,
+      } => {
+        let bytes: [u8; 8] = bytes[..8].try_into().unwrap();
+        (if big_endian {
+          f64::from_be_bytes(bytes)
+        } else {
+          f64::from_le_bytes(bytes)
+        }) as f32
+      }
+      SampleFormat::Float { big_endian, .. } => {
+        let bytes: [u8; 4] = bytes[..4].try_into().unwrap();
+        if big_endian {
+          f32::from_be_bytes(bytes)
+        } else {
+          f32::from_le_bytes(bytes)
+        }
+      }
+      SampleFormat::UInt8 => (bytes[0] as f32 - 128.0) / 127.0,
+      SampleFormat::MuLaw => mulaw_to_i16(bytes[0]) as f32 / i16::MAX as f32,
+      SampleFormat::ALaw => alaw_to_i16(bytes[0]) as f32 / i16::MAX as f32,
+    }
+  }
+
+  /**
+   Appends the encoding of one sample, integers being clamped to their range.
+  */
+  pub fn encode(self, sample: f32, output: &mut Vec<u8>) {
+    match self {
+      SampleFormat::Int { bits, big_endian } => {
+        let size = self.bytes_per_sample();
+        let max = ((1i64 << (bits - 1)) - 1) as f64;
+        let value =
+          ((sample.clamp(-1.0, 1.0) as f64 * max).round() as i64) << (8 * size as u32 - bits);
+        let bytes = value.to_le_bytes();
+        if big_endian {
+          output.extend(bytes[..size].iter().rev());
+        } else {
+          output.extend_from_slice(&bytes[..size]);
+        }
+      }
+      SampleFormat::Float {
+        bits: 64,
+        big_endian,
+      } => {
+        let sample = sample as f64;
+        output.extend_from_slice(&if big_endian {
+          sample.to_be_bytes()
+        } else {
+          sample.to_le_bytes()
+        });
+      }
+      SampleFormat::Float { big_endian, .. } => {
+        output.extend_from_slice(&if big_endian {
+          sample.to_be_bytes()
+        } else {
+          sample.to_le_bytes()
+        });
+      }
+      SampleFormat::UInt8 => {
+        output.push((sample.clamp(-1.0, 1.0) * 127.0).round() as i32 as u8 ^ 0x80)
+      }
+      SampleFormat::MuLaw => output.push(i16_to_mulaw(
+        (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16,
+      )),
+      SampleFormat::ALaw => output.push(i16_to_alaw(
+        (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16,
+      )),
+    }
+  }
+}
+
+/**
+ G.711 mu-law expansion to 16 bits linear PCM.
+*/
+fn mulaw_to_i16(byte: u8) -> i16 {
+  let byte = !byte;
+  let exponent = (byte >> 4) & 0x07;
+  let mantissa = (byte & 0x0F) as i16;
+  let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+  if byte & 0x80 != 0 {
+    -magnitude
+  } else {
+    magnitude
+  }
+}
+
+/**
+ G.711 mu-law compression of 16 bits linear PCM, with the usual bias of 0x84 and clip at 32635.
+*/
+fn i16_to_mulaw(sample: i16) -> u8 {
+  let sign = if sample < 0 { 0x80 } else { 0 };
+  let magnitude = (sample as i32).abs().min(32635) + 0x84;
+  let exponent = 31 - (magnitude >> 7).leading_zeros();
+  let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+  !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+/**
+ G.711 A-law expansion to 16 bits linear PCM.
+*/
+fn alaw_to_i16(byte: u8) -> i16 {
+  let byte = byte ^ 0x55;
+  let exponent = (byte >> 4) & 0x07;
+  let mantissa = (byte & 0x0F) as i16;
+  let magnitude = if exponent == 0 {
+    (mantissa << 4) + 8
+  } else {
+    ((mantissa << 4) + 0x108) << (exponent - 1)
+  };
+  // The sign bit is set for positive samples
+  if byte & 0x80 != 0 {
+    magnitude
+  } else {
+    -magnitude
+  }
+}
+
+/**
+ G.711 A-law compression of 16 bits linear PCM.
+*/
+fn i16_to_alaw(sample: i16) -> u8 {
+  let (sign, magnitude) = if sample >= 0 {
+    (0x80, sample as i32)
+  } else {
+    (0x00, -(sample as i32) - 1)
+  };
+  let (exponent, mantissa) = if magnitude < 256 {
+    (0, (magnitude >> 4) & 0x0F)
+  } else {
+    let exponent = 32 - (magnitude >> 8).leading_zeros();
+    (exponent, (magnitude >> (exponent + 3)) & 0x0F)
+  };
+  (sign | (exponent << 4) as u8 | mantissa as u8) ^ 0x55
+}
+
+/**
+ Streams the interleaved samples of the data chunk of an uncompressed container, the reader being
+ positioned at its start by the container parser.
+*/
+pub struct PcmSource<R: Read> {
+  reader: R,
+  spec: AudioSpec,
+  format: SampleFormat,
+  data_len: Option<u64>,
+  // Bytes read since the start of the data chunk
+  position: u64,
+  // Bytes left in the data chunk, `u64::MAX` when it runs to the end of the file
+  remaining: u64,
+  nbr_frames: Option<u64>,
+  bytes: Vec<u8>,
+}
+
+impl<R: Read> PcmSource<R> {
+  pub fn new(
+    reader: R,
+    sample_rate: u32,
+    channels: usize,
+    format: SampleFormat,
+    data_len: Option<u64>,
+  ) -> Result<Self, String> {
+    if channels == 0 || sample_rate == 0 {
+      return Err(format!(
+        "Invalid PCM stream of {} channel(s) at {} Hz",
+        channels, sample_rate
+      ));
+    }
+    let spec = AudioSpec {
+      sample_rate,
+      channels,
+      bits_per_sample: format.bits_per_sample(),
+    };
+    debug!("PCM stream is {:?} of {:?}", spec, format);
+    Ok(PcmSource {
+      reader,
+      spec,
+      format,
+      data_len,
+      position: 0,
+      remaining: data_len.unwrap_or(u64::MAX),
+      nbr_frames: data_len.map(|len| len / (format.bytes_per_sample() * channels) as u64),
+      bytes: Vec::new(),
+    })
+  }
+}
+
+impl<R: Read + Seek> AudioSource for PcmSource<R> {
+  fn spec(&self) -> AudioSpec {
+    self.spec
+  }
+
+  fn codec(&self) -> String {
+    format!("pcm_{}", self.sample_format())
+  }
+
+  fn sample_format(&self) -> String {
+    pcm_sample_format(self.format)
+  }
+
+  fn nbr_frames(&self) -> Option<u64> {
+    self.nbr_frames
+  }
+
+  fn seek_frame(&mut self, frame: u64) -> Result<bool, String> {
+    let frame_len = (self.format.bytes_per_sample() * self.spec.channels) as u64;
+    let target = self
+      .data_len
+      .map_or(frame.saturating_mul(frame_len), |len| {
+        std::cmp::min(frame.saturating_mul(frame_len), len)
+      });
+    let offset = target as i64 - self.position as i64;
+    // Pipes cannot seek, they are read up to the frame instead
+    if let Err(err) = self.reader.seek(SeekFrom::Current(offset)) {
+      debug!(
+        "Cannot seek in the PCM stream, decoding up to the frame : {}",
+        err
+      );
+      return Ok(false);
+    }
+    self.position = target;
+    self.remaining = self.data_len.map_or(u64::MAX, |len| len - target);
+    Ok(true)
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    let channels = self.spec.channels;
+    let frame_len = self.format.bytes_per_sample() * channels;
+    let wanted = std::cmp::min((BLOCK_FRAMES * frame_len) as u64, self.remaining) as usize;
+    self.bytes.resize(wanted, 0);
+    let mut filled = 0;
+    while filled < wanted {
+      match self.reader.read(&mut self.bytes[filled..]) {
+        Ok(0) => break,
+        Ok(read) => filled += read,
+        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+        Err(err) => return Err(format!("Cannot read PCM samples : {}", err)),
+      }
+    }
+    self.position += filled as u64;
+    self.remaining -= filled as u64;
+    // A truncated last frame is dropped
+    let nbr_frames = filled / frame_len;
+    if nbr_frames == 0 {
+      return Ok(None);
+    }
+    Ok(Some(decode_frames(
+      &self.bytes[..nbr_frames * frame_len],
+      self.format,
+      channels,
+    )))
+  }
+}
+
+fn pcm_sample_format(format: SampleFormat) -> String {
+  sample_format_name(format)
+    .map_or_else(|| format!("s{}", format.bits_per_sample()), str::to_string)
+}
+
+/**
+ Decodes whole interleaved frames into one vector per channel.
+*/
+fn decode_frames(bytes: &[u8], format: SampleFormat, channels: usize) -> Vec<Vec<f32>> {
+  let sample_len = format.bytes_per_sample();
+  let frame_len = sample_len * channels;
+  let mut block = vec![Vec::with_capacity(bytes.len() / frame_len); channels];
+  for frame in bytes.chunks_exact(frame_len) {
+    for (channel, sample) in block.iter_mut().zip(frame.chunks_exact(sample_len)) {
+      channel.push(format.decode(sample));
+    }
+  }
+  block
+}
+
+/**
+ Streams interleaved samples already in memory, like a memory-mapped file, decoding each block
+ straight from the bytes instead of copying them through a reader. A truncated last frame is
+ dropped.
+*/
+pub struct PcmSliceSource<B: AsRef<[u8]>> {
+  data: B,
+  position: usize,
+  spec: AudioSpec,
+  format: SampleFormat,
+}
+
+impl<B: AsRef<[u8]>> PcmSliceSource<B> {
+  pub fn new(
+    data: B,
+    sample_rate: u32,
+    channels: usize,
+    format: SampleFormat,
+  ) -> Result<Self, String> {
+    if channels == 0 || sample_rate == 0 {
+      return Err(format!(
+        "Invalid PCM stream of {} channel(s) at {} Hz",
+        channels, sample_rate
+      ));
+    }
+    let spec = AudioSpec {
+      sample_rate,
+      channels,
+      bits_per_sample: format.bits_per_sample(),
+    };
+    debug!("In-memory PCM stream is {:?} of {:?}", spec, format);
+    Ok(PcmSliceSource {
+      data,
+      position: 0,
+      spec,
+      format,
+    })
+  }
+
+  fn frame_len(&self) -> usize {
+    self.format.bytes_per_sample() * self.spec.channels
+  }
+}
+
+impl<B: AsRef<[u8]>> AudioSource for PcmSliceSource<B> {
+  fn spec(&self) -> AudioSpec {
+    self.spec
+  }
+
+  fn codec(&self) -> String {
+    format!("pcm_{}", self.sample_format())
+  }
+
+  fn sample_format(&self) -> String {
+    pcm_sample_format(self.format)
+  }
+
+  fn nbr_frames(&self) -> Option<u64> {
+    Some((self.data.as_ref().len() / self.frame_len()) as u64)
+  }
+
+  fn seek_frame(&mut self, frame: u64) -> Result<bool, String> {
+    let frame_len = self.frame_len();
+    let last_frame = self.data.as_ref().len() / frame_len;
+    self.position = std::cmp::min(frame, last_frame as u64) as usize * frame_len;
+    Ok(true)
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    let frame_len = self.frame_len();
+    let left = &self.data.as_ref()[self.position..];
+    let nbr_frames = std::cmp::min(BLOCK_FRAMES, left.len() / frame_len);
+    if nbr_frames == 0 {
+      return Ok(None);
+    }
+    let block = decode_frames(
+      &left[..nbr_frames * frame_len],
+      self.format,
+      self.spec.channels,
+    );
+    self.position += nbr_frames * frame_len;
+    Ok(Some(block))
+  }
+}
+
+/**
+ Fixed-size header of an uncompressed container, rewritten with the real sizes once every sample has
+ been written.
+*/
+pub trait PcmHeader {
+  /**
+   The header for `nbr_frames` frames, its length must not depend on `nbr_frames`.
+  */
+  fn header(&self, nbr_frames: u64) -> Result<Vec<u8>, String>;
+
+  /**
+   Bytes written after the samples, like the pad byte of an odd-sized IFF chunk.
+  */
+  fn trailer(&self, _nbr_frames: u64) -> Vec<u8> {
+    Vec::new()
+  }
+}
+
+/**
+ Encodes interleaved f32 samples in an uncompressed container described by `H`.
+*/
+pub struct PcmWriter<W: Write + Seek, H: PcmHeader> {
+  writer: W,
+  header: H,
+  format: SampleFormat,
+  channels: usize,
+  stream_start: u64,
+  nbr_frames: u64,
+  bytes: Vec<u8>,
+}
+
+impl<W: Write + Seek, H: PcmHeader> PcmWriter<W, H> {
+  pub fn new(
+    mut writer: W,
+    header: H,
+    format: SampleFormat,
+    channels: usize,
+  ) -> Result<Self, String> {
+    if channels == 0 {
+      return Err("Cannot write a PCM stream without channels".to_string());
+    }
+    let stream_start = writer.stream_position().map_err(|err| err.to_string())?;
+    writer
+      .write_all(&header.header(0)?)
+      .map_err(|err| format!("Cannot write header : {}", err))?;
+    Ok(PcmWriter {
+      writer,
+      header,
+      format,
+      channels,
+      stream_start,
+      nbr_frames: 0,
+      bytes: Vec::new(),
+    })
+  }
+
+  #[cfg(test)]
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+}
+
+impl<W: Write + Seek, H: PcmHeader> AudioSink for PcmWriter<W, H> {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    self.bytes.clear();
+    for &sample in samples {
+      self.format.encode(sample, &mut self.bytes);
+    }
+    self.nbr_frames += (samples.len() / self.channels) as u64;
+    self
+      .writer
+      .write_all(&self.bytes)
+      .map_err(|err| format!("Cannot write samples : {}", err))
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    let header = self.header.header(self.nbr_frames)?;
+    let trailer = self.header.trailer(self.nbr_frames);
+    self
+      .writer
+      .write_all(&trailer)
+      .map_err(|err| format!("Cannot write samples : {}", err))?;
+    let end = self
+      .writer
+      .stream_position()
+      .map_err(|err| err.to_string())?;
+    self
+      .writer
+      .seek(SeekFrom::Start(self.stream_start))
+      .and_then(|_| self.writer.write_all(&header))
+      .and_then(|_| self.writer.seek(SeekFrom::Start(end)))
+      .and_then(|_| self.writer.flush())
+      .map_err(|err| format!("Cannot complete header : {}", err))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  #[test]
+  fn test_sample_format_round_trip() {
+    let formats = [
+      SampleFormat::Int {
+        bits: 8,
+        big_endian: true,
+      },
+      SampleFormat::Int {
+        bits: 16,
+        big_endian: false,
+      },
+      SampleFormat::Int {
+        bits: 24,
+        big_endian: true,
+      },
+      SampleFormat::Int {
+        bits: 32,
+        big_endian: false,
+      },
+      SampleFormat::Float {
+        bits: 32,
+        big_endian: true,
+      },
+      SampleFormat::Float {
+        bits: 64,
+        big_endian: false,
+      },
+      SampleFormat::UInt8,
+    ];
+    for format in formats {
+      for sample in [-1.0f32, -0.5, 0.0, 0.25, 1.0] {
+        let mut bytes = Vec::new();
+        format.encode(sample, &mut bytes);
+        assert_eq!(bytes.len(), format.bytes_per_sample());
+        assert!(
+          (format.decode(&bytes) - sample).abs() < 0.01,
+          "{:?}",
+          format
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_g711_round_trip() {
+    // Reference values of ITU-T G.711
+    assert_eq!(i16_to_mulaw(0), 0xFF);
+    assert_eq!(mulaw_to_i16(0x00), -32124);
+    assert_eq!(mulaw_to_i16(0x80), 32124);
+    assert_eq!(i16_to_alaw(0), 0xD5);
+    assert_eq!(alaw_to_i16(0x55), -8);
+    assert_eq!(alaw_to_i16(0xAA), 32256);
+    for sample in (i16::MIN + 1..=i16::MAX).step_by(7) {
+      // Less than one step of the segment of the sample, at most 1/16 of its magnitude
+      let tolerance = std::cmp::max(sample.unsigned_abs() / 16, 16) as i32;
+      let mulaw = mulaw_to_i16(i16_to_mulaw(sample)) as i32;
+      assert!((mulaw - sample as i32).abs() <= tolerance, "{}", sample);
+      let alaw = alaw_to_i16(i16_to_alaw(sample)) as i32;
+      assert!((alaw - sample as i32).abs() <= tolerance, "{}", sample);
+    }
+    for byte in 0..=255u8 {
+      // 0x7F is the negative zero of mu-law
+      if byte != 0x7F {
+        assert_eq!(i16_to_mulaw(mulaw_to_i16(byte)), byte);
+      }
+      assert_eq!(i16_to_alaw(alaw_to_i16(byte)), byte);
+    }
+  }
+
+  #[test]
+  fn test_sample_format_byte_order() {
+    let mut bytes = Vec::new();
+    SampleFormat::Int {
+      bits: 16,
+      big_endian: true,
+    }
+    .encode(1.0, &mut bytes);
+    SampleFormat::Int {
+      bits: 16,
+      big_endian: false,
+    }
+    .encode(-1.0, &mut bytes);
+    assert_eq!(bytes, vec![0x7F, 0xFF, 0x01, 0x80]);
+  }
+
+  #[test]
+  fn test_pcm_source_drops_truncated_frame() {
+    let format = SampleFormat::Int {
+      bits: 16,
+      big_endian: false,
+    };
+    let data: Vec<u8> = vec![0xFF, 0x7F, 0x01, 0x80, 0x00, 0x00, 0x12];
+    let mut source = PcmSource::new(Cursor::new(&data[..]), 8000, 2, format, Some(6)).unwrap();
+    let block = source.read_block().unwrap().unwrap();
+    assert_eq!(block, vec![vec![1.0], vec![-1.0]]);
+    assert_eq!(source.read_block().unwrap(), None);
+  }
+}
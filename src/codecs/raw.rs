@@ -0,0 +1,303 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::str::FromStr;
+
+use log::debug;
+use memmap2::Mmap;
+
+use super::pcm::{PcmSliceSource, PcmSource, SampleFormat};
+use super::{AudioSink, AudioSource};
+
+/**
+ Headerless samples described by an ffmpeg-style descriptor, `<sample format>:<channels>:<rate>`,
+ like `s16le:2:44100`, `f32be:1:48000` or `mulaw:1:8000`.
+
+ The sample formats are the ones of ffmpeg: `u8`, `s8`, `s16le`, `s16be`, `s24le`, `s24be`,
+ `s32le`, `s32be`, `f32le`, `f32be`, `f64le`, `f64be`, `mulaw` and `alaw`.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawFormat {
+  pub format: SampleFormat,
+  pub channels: usize,
+  pub sample_rate: u32,
+}
+
+const SAMPLE_FORMAT_NAMES: [(&str, SampleFormat); 14] = [
+  ("u8", SampleFormat::UInt8),
+  ("s8", int(8, false)),
+  ("s16le", int(16, false)),
+  ("s16be", int(16, true)),
+  ("s24le", int(24, false)),
+  ("s24be", int(24, true)),
+  ("s32le", int(32, false)),
+  ("s32be", int(32, true)),
+  ("f32le", float(32, false)),
+  ("f32be", float(32, true)),
+  ("f64le", float(64, false)),
+  ("f64be", float(64, true)),
+  ("mulaw", SampleFormat::MuLaw),
+  ("alaw", SampleFormat::ALaw),
+];
+
+const fn int(bits: u32, big_endian: bool) -> SampleFormat {
+  SampleFormat::Int { bits, big_endian }
+}
+
+const fn float(bits: u32, big_endian: bool) -> SampleFormat {
+  SampleFormat::Float { bits, big_endian }
+}
+
+/**
+ The ffmpeg name of a sample format, `None` for the ones without a raw descriptor (like 20 bits in 3
+ bytes).
+*/
+pub fn sample_format_name(format: SampleFormat) -> Option<&'static str> {
+  SAMPLE_FORMAT_NAMES
+    .iter()
+    .find(|(_, known)| *known == format)
+    .map(|(name, _)| *name)
+}
+
+impl FromStr for RawFormat {
+  type Err = String;
+
+  fn from_str(descriptor: &str) -> Result<Self, Self::Err> {
+    let invalid = |reason: &str| {
+      format!(
+        "Invalid raw format \"{}\", {} (expected <format>:<channels>:<rate> like s16le:2:44100)",
+        descriptor, reason
+      )
+    };
+    let fields: Vec<&str> = descriptor.trim().split(':').collect();
+    let [name, channels, sample_rate] = fields[..] else {
+      return Err(invalid("it needs 3 fields"));
+    };
+    let format = SAMPLE_FORMAT_NAMES
+      .iter()
+      .find(|(known, _)| known.eq_ignore_ascii_case(name))
+      .map(|(_, format)| *format)
+      .ok_or_else(|| invalid("unknown sample format"))?;
+    let channels = channels
+      .parse::<usize>()
+      .ok()
+      .filter(|&channels| channels > 0)
+      .ok_or_else(|| invalid("channels must be a positive integer"))?;
+    let sample_rate = sample_rate
+      .parse::<u32>()
+      .ok()
+      .filter(|&sample_rate| sample_rate > 0)
+      .ok_or_else(|| invalid("sample rate must be a positive integer"))?;
+    Ok(RawFormat {
+      format,
+      channels,
+      sample_rate,
+    })
+  }
+}
+
+impl fmt::Display for RawFormat {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}:{}:{}",
+      sample_format_name(self.format).unwrap_or("?"),
+      self.channels,
+      self.sample_rate
+    )
+  }
+}
+
+impl RawFormat {
+  /**
+   Streams the samples of a reader holding nothing but samples of this format.
+  */
+  pub fn source<R: Read + Seek>(&self, reader: R) -> Result<PcmSource<R>, String> {
+    PcmSource::new(reader, self.sample_rate, self.channels, self.format, None)
+  }
+
+  /**
+   Streams a file holding nothing but samples of this format. The samples are decoded straight from
+   a memory map of the file, so large files are not copied through a reader, or read through a
+   buffer when the file cannot be mapped (pipes, some network file systems...).
+  */
+  pub fn open(&self, path: &str) -> Result<Box<dyn AudioSource>, String> {
+    let file = File::open(path).map_err(|err| format!("Can't open file {} : {}", path, err))?;
+    // SAFETY: the map is only read, and like any input the file must not be truncated or rewritten
+    // while it is resampled
+    match unsafe { Mmap::map(&file) } {
+      Ok(map) => {
+        #[cfg(unix)]
+        if let Err(err) = map.advise(memmap2::Advice::Sequential) {
+          debug!("Cannot advise sequential reads of {} : {}", path, err);
+        }
+        Ok(Box::new(PcmSliceSource::new(
+          map,
+          self.sample_rate,
+          self.channels,
+          self.format,
+        )?))
+      }
+      Err(err) => {
+        debug!("Cannot map {}, reading it through a buffer : {}", path, err);
+        Ok(Box::new(self.source(BufReader::new(file))?))
+      }
+    }
+  }
+
+  /**
+   Encodes interleaved f32 samples in this format.
+  */
+  pub fn encode(&self, samples: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(samples.len() * self.format.bytes_per_sample());
+    for &sample in samples {
+      self.format.encode(sample, &mut bytes);
+    }
+    bytes
+  }
+}
+
+/**
+ Writes interleaved samples as headerless bytes, block by block, `encode` turning each block of
+ samples into bytes.
+*/
+pub struct RawWriter<W: Write, E: Fn(&[f32]) -> Vec<u8>> {
+  writer: W,
+  encode: E,
+}
+
+impl<W: Write, E: Fn(&[f32]) -> Vec<u8>> RawWriter<W, E> {
+  pub fn new(writer: W, encode: E) -> Self {
+    RawWriter { writer, encode }
+  }
+}
+
+impl<W: Write, E: Fn(&[f32]) -> Vec<u8>> AudioSink for RawWriter<W, E> {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    self
+      .writer
+      .write_all(&(self.encode)(samples))
+      .map_err(|err| format!("Cannot write raw samples : {}", err))
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    self
+      .writer
+      .flush()
+      .map_err(|err| format!("Cannot write raw samples : {}", err))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  #[test]
+  fn test_parse_raw_format() {
+    assert_eq!(
+      "s16le:2:44100".parse(),
+      Ok(RawFormat {
+        format: int(16, false),
+        channels: 2,
+        sample_rate: 44100,
+      })
+    );
+    assert_eq!(
+      "F32BE:1:48000".parse::<RawFormat>().map(|raw| raw.format),
+      Ok(float(32, true))
+    );
+    assert_eq!(
+      "mulaw:1:8000"
+        .parse::<RawFormat>()
+        .map(|raw| raw.to_string()),
+      Ok("mulaw:1:8000".to_string())
+    );
+    for invalid in [
+      "s16le:2",
+      "s16:2:44100",
+      "s16le:0:44100",
+      "s16le:2:fast",
+      "",
+    ] {
+      assert!(invalid.parse::<RawFormat>().is_err(), "{}", invalid);
+    }
+  }
+
+  fn read_all(mut source: Box<dyn AudioSource + '_>) -> Vec<Vec<f32>> {
+    let mut planar = vec![Vec::new(); source.spec().channels];
+    while let Some(block) = source.read_block().unwrap() {
+      for (all, channel) in planar.iter_mut().zip(block) {
+        all.extend(channel);
+      }
+    }
+    planar
+  }
+
+  #[test]
+  fn test_open_maps_raw_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.raw");
+    let raw: RawFormat = "s16be:2:8000".parse().unwrap();
+    let samples: Vec<f32> = (0..20001)
+      .map(|i| ((i as f32) * 0.01).sin() * 0.5)
+      .collect();
+    let mut bytes = raw.encode(&samples);
+    // A truncated frame is dropped
+    bytes.push(0);
+    std::fs::write(&path, &bytes).unwrap();
+
+    let source = raw.open(path.to_str().unwrap()).unwrap();
+    assert_eq!(source.nbr_frames(), Some(10000));
+    let expected = read_all(Box::new(raw.source(Cursor::new(&bytes[..])).unwrap()));
+    assert_eq!(read_all(source), expected);
+    assert_eq!(expected[0].len(), 10000);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_open_reads_files_that_cannot_be_mapped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.fifo");
+    let created = std::process::Command::new("mkfifo").arg(&path).status();
+    if !created.is_ok_and(|status| status.success()) {
+      return;
+    }
+    let raw: RawFormat = "f32le:1:8000".parse().unwrap();
+    let bytes = raw.encode(&[0.25; 5000]);
+    let fifo = path.clone();
+    let writer = std::thread::spawn(move || std::fs::write(fifo, bytes).unwrap());
+
+    let planar = read_all(raw.open(path.to_str().unwrap()).unwrap());
+    writer.join().unwrap();
+    assert_eq!(planar[0], vec![0.25; 5000]);
+  }
+
+  #[test]
+  fn test_raw_writer_writes_to_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.raw");
+    let raw: RawFormat = "s16le:1:8000".parse().unwrap();
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = RawWriter::new(std::io::BufWriter::new(file), |samples| raw.encode(samples));
+    writer.write_samples(&[0.0, 1.0]).unwrap();
+    writer.write_samples(&[-1.0]).unwrap();
+    writer.finish().unwrap();
+    assert_eq!(
+      std::fs::read(path).unwrap(),
+      [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]
+    );
+  }
+
+  #[test]
+  fn test_raw_format_round_trip() {
+    let raw: RawFormat = "s24be:2:16000".parse().unwrap();
+    let samples = [0.5, -0.5, 0.25, -0.25];
+    let bytes = raw.encode(&samples);
+    assert_eq!(bytes.len(), 12);
+    let mut source = raw.source(Cursor::new(&bytes[..])).unwrap();
+    let block = source.read_block().unwrap().unwrap();
+    assert!((block[0][1] - 0.25).abs() < 1e-6);
+    assert!((block[1][0] + 0.5).abs() < 1e-6);
+  }
+}
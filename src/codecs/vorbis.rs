@@ -0,0 +1,22 @@
+use symphonia::default::codecs::VorbisDecoder;
+use symphonia::default::formats::OggReader;
+
+use super::packets::PacketSource;
+
+/**
+ Streams the frames of an Ogg Vorbis file, the sample rate and channels being taken from the Vorbis
+ identification header.
+*/
+pub type VorbisSource = PacketSource<OggReader, VorbisDecoder>;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  #[test]
+  fn test_vorbis_source_rejects_other_data() {
+    let data = b"RIFF\0\0\0\0WAVE".to_vec();
+    assert!(VorbisSource::new(Box::new(Cursor::new(data))).is_err());
+  }
+}
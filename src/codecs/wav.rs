@@ -0,0 +1,834 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::debug;
+
+use super::pcm::{PcmHeader, PcmSource, SampleFormat};
+use crate::stream::rescale_position;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/**
+ Chunk identifiers of Sony Wave64, the RIFF four characters followed by a fixed GUID suffix.
+*/
+const W64_RIFF: [u8; 16] = [
+  0x72, 0x69, 0x66, 0x66, 0x2E, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00,
+];
+const W64_WAVE: [u8; 16] = w64_guid(b"wave");
+const W64_FMT: [u8; 16] = w64_guid(b"fmt ");
+const W64_DATA: [u8; 16] = w64_guid(b"data");
+/**
+ The `list` GUID has the suffix of the `riff` one.
+*/
+const W64_LIST: [u8; 16] = [
+  0x6C, 0x69, 0x73, 0x74, 0x2E, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00,
+];
+
+const fn w64_guid(id: &[u8; 4]) -> [u8; 16] {
+  [
+    id[0], id[1], id[2], id[3], 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB,
+    0x8A,
+  ]
+}
+
+/**
+ Tells if an in-memory file is a RIFF WAVE, an RF64 / BW64 or a Wave64 file.
+*/
+pub fn is_wav(data: &[u8]) -> bool {
+  (data.len() >= 12 && matches!(&data[..4], b"RIFF" | b"RF64" | b"BW64") && &data[8..12] == b"WAVE")
+    || data.starts_with(&W64_RIFF)
+}
+
+/**
+ Sample format of a `fmt ` chunk, `WAVE_FORMAT_EXTENSIBLE` being resolved to its sub format.
+*/
+fn parse_fmt(fmt: &[u8]) -> Result<(usize, u32, SampleFormat), String> {
+  if fmt.len() < 16 {
+    return Err("WAV fmt chunk is too short".to_string());
+  }
+  let read_u16 = |at: usize| u16::from_le_bytes([fmt[at], fmt[at + 1]]);
+  let mut format_tag = read_u16(0);
+  let channels = read_u16(2) as usize;
+  let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+  let block_align = read_u16(12) as usize;
+  let mut bits = read_u16(14) as u32;
+  if format_tag == WAVE_FORMAT_EXTENSIBLE && fmt.len() >= 40 {
+    let valid_bits = read_u16(18) as u32;
+    if valid_bits > 0 {
+      bits = valid_bits;
+    }
+    format_tag = read_u16(24);
+  }
+  let container_bits = block_align.checked_div(channels).unwrap_or(0) as u32 * 8;
+  let format = match (format_tag, container_bits) {
+    (WAVE_FORMAT_PCM, 8) => SampleFormat::UInt8,
+    (WAVE_FORMAT_PCM, 16 | 24 | 32) if bits > 0 && bits <= container_bits => {
+      // Samples with less valid bits are left-justified in their container
+      SampleFormat::Int {
+        bits: container_bits,
+        big_endian: false,
+      }
+    }
+    (WAVE_FORMAT_IEEE_FLOAT, 32 | 64) => SampleFormat::Float {
+      bits: container_bits,
+      big_endian: false,
+    },
+    _ => {
+      return Err(format!(
+        "Unsupported WAV format 0x{:04X} of {} bits",
+        format_tag, bits
+      ))
+    }
+  };
+  Ok((channels, sample_rate, format))
+}
+
+/**
+ Reads the RIFF / RF64 / Wave64 header, telling if the file is a Wave64 one.
+*/
+fn read_wav_header<R: Read>(reader: &mut R) -> Result<bool, String> {
+  let mut magic = [0u8; 12];
+  reader.read_exact(&mut magic).map_err(read_error)?;
+  let is_w64 = magic[..] == W64_RIFF[..12];
+  if is_w64 {
+    let mut wave = [0u8; 28];
+    reader.read_exact(&mut wave).map_err(read_error)?;
+    if wave[..4] != W64_RIFF[12..] || wave[12..] != W64_WAVE {
+      return Err("Not a Wave64 file".to_string());
+    }
+  } else if !is_wav(&magic) {
+    return Err("Not a WAV file".to_string());
+  }
+  Ok(is_w64)
+}
+
+/**
+ Reads the id and size of the next chunk, `None` at the end of the file. Wave64 chunks have a GUID
+ and a 64-bit size counting their header, the GUIDs of the RIFF chunks are turned into their ids.
+*/
+fn read_chunk_header<R: Read>(
+  reader: &mut R,
+  is_w64: bool,
+) -> Result<Option<([u8; 4], u64)>, String> {
+  let mut id = [0u8; 16];
+  let id_len = if is_w64 { 16 } else { 4 };
+  match reader.read_exact(&mut id[..id_len]) {
+    Ok(()) => {}
+    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+    Err(err) => return Err(read_error(err)),
+  }
+  if !is_w64 {
+    let size = reader.read_u32::<LittleEndian>().map_err(read_error)? as u64;
+    return Ok(Some((id[..4].try_into().unwrap(), size)));
+  }
+  let size = reader.read_u64::<LittleEndian>().map_err(read_error)?;
+  let riff_id = match id {
+    W64_LIST => *b"LIST",
+    _ if id[4..] == w64_guid(b"    ")[4..] => id[..4].try_into().unwrap(),
+    _ => [0; 4],
+  };
+  Ok(Some((riff_id, size.saturating_sub(24))))
+}
+
+/**
+ Offset of the chunk following the one of `size` bytes starting at `start`.
+*/
+fn next_chunk(start: u64, size: u64, is_w64: bool) -> u64 {
+  if is_w64 {
+    (start + size).div_ceil(8) * 8
+  } else {
+    start + size + (size & 1)
+  }
+}
+
+fn read_error(err: std::io::Error) -> String {
+  format!("Invalid WAV file : {}", err)
+}
+
+/**
+ Reads the `size` bytes of a chunk. The buffer only grows with the bytes actually read, so a size
+ corrupted in the header fails on the end of the file instead of allocating it up front.
+*/
+fn read_chunk<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>, String> {
+  let mut chunk = Vec::new();
+  reader
+    .by_ref()
+    .take(size)
+    .read_to_end(&mut chunk)
+    .map_err(read_error)?;
+  if (chunk.len() as u64) < size {
+    return Err(format!(
+      "Invalid WAV file : chunk of {} bytes ends after {}",
+      size,
+      chunk.len()
+    ));
+  }
+  Ok(chunk)
+}
+
+/**
+ Parses the chunks of a RIFF WAVE, RF64 / BW64 or Wave64 file and returns a source positioned at
+ the samples of its data chunk. The 64-bit sizes of the `ds64` chunk replace the 32-bit ones.
+*/
+pub fn wav_source<R: Read + Seek>(mut reader: R) -> Result<PcmSource<R>, String> {
+  let is_w64 = read_wav_header(&mut reader)?;
+  let mut fmt = None;
+  let mut data = None;
+  let mut ds64_data_len = None;
+  while let Some((id, size)) = read_chunk_header(&mut reader, is_w64)? {
+    let start = reader.stream_position().map_err(read_error)?;
+    match &id {
+      b"fmt " => {
+        fmt = Some(parse_fmt(&read_chunk(&mut reader, size)?)?);
+      }
+      b"ds64" => {
+        let _riff_size = reader.read_u64::<LittleEndian>().map_err(read_error)?;
+        ds64_data_len = Some(reader.read_u64::<LittleEndian>().map_err(read_error)?);
+      }
+      b"data" => {
+        let data_len = data_chunk_len(size, ds64_data_len, is_w64);
+        data = Some((start, data_len));
+        // The samples are usually the last chunk, no need to skip them
+        if fmt.is_some() || data_len.is_none() {
+          break;
+        }
+      }
+      _ => debug!("Skipping WAV chunk {}", String::from_utf8_lossy(&id)),
+    }
+    reader
+      .seek(SeekFrom::Start(next_chunk(start, size, is_w64)))
+      .map_err(read_error)?;
+  }
+
+  let (channels, sample_rate, format) =
+    fmt.ok_or_else(|| "WAV file has no fmt chunk".to_string())?;
+  let (data_start, data_len) = data.ok_or_else(|| "WAV file has no data chunk".to_string())?;
+  reader
+    .seek(SeekFrom::Start(data_start))
+    .map_err(read_error)?;
+  PcmSource::new(reader, sample_rate, channels, format, data_len)
+}
+
+/**
+ Size of a data chunk, `None` when a RIFF one still being recorded runs to the end of the file.
+*/
+fn data_chunk_len(size: u64, ds64_data_len: Option<u64>, is_w64: bool) -> Option<u64> {
+  match (size, ds64_data_len) {
+    (0xFFFF_FFFF, Some(data_len)) if !is_w64 => Some(data_len),
+    (0xFFFF_FFFF, None) if !is_w64 => None,
+    _ => Some(size),
+  }
+}
+
+/**
+ A chunk of a WAV file that is not about the samples, like `LIST` (INFO tags), `bext` (Broadcast
+ WAV origination) or `iXML`.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WavChunk {
+  pub id: [u8; 4],
+  pub data: Vec<u8>,
+}
+
+/**
+ Chunks describing the samples of the input, rewritten for the output instead of being copied.
+*/
+const SAMPLE_CHUNKS: [&[u8; 4]; 7] = [
+  b"fmt ", b"data", b"ds64", b"fact", b"JUNK", b"junk", b"PAD ",
+];
+
+/**
+ Reads the metadata chunks of a WAV file, the ones before and after its samples.
+*/
+pub fn wav_metadata<R: Read + Seek>(mut reader: R) -> Result<Vec<WavChunk>, String> {
+  let is_w64 = read_wav_header(&mut reader)?;
+  let mut chunks = Vec::new();
+  let mut ds64_data_len = None;
+  while let Some((id, size)) = read_chunk_header(&mut reader, is_w64)? {
+    let start = reader.stream_position().map_err(read_error)?;
+    let size = match &id {
+      b"ds64" => {
+        let _riff_size = reader.read_u64::<LittleEndian>().map_err(read_error)?;
+        ds64_data_len = Some(reader.read_u64::<LittleEndian>().map_err(read_error)?);
+        size
+      }
+      b"data" => match data_chunk_len(size, ds64_data_len, is_w64) {
+        Some(data_len) => data_len,
+        // Nothing can follow samples running to the end of the file
+        None => break,
+      },
+      _ if id == [0; 4] || SAMPLE_CHUNKS.contains(&&id) => size,
+      _ => {
+        let data = read_chunk(&mut reader, size)?;
+        chunks.push(WavChunk { id, data });
+        size
+      }
+    };
+    reader
+      .seek(SeekFrom::Start(next_chunk(start, size, is_w64)))
+      .map_err(read_error)?;
+  }
+  debug!(
+    "WAV metadata chunks {:?}",
+    chunks
+      .iter()
+      .map(|chunk| String::from_utf8_lossy(&chunk.id))
+      .collect::<Vec<_>>()
+  );
+  Ok(chunks)
+}
+
+/**
+ Offset of the TimeReference of a `bext` chunk, the 64-bit number of samples since midnight of its
+ first sample, after the description (256), originator (32), originator reference (32), date (10)
+ and time (8).
+*/
+const BEXT_TIME_REFERENCE: usize = 338;
+
+/**
+ Rescales the little-endian u32 sample position at `at`, if the chunk is long enough.
+*/
+fn rescale_u32_at(data: &mut [u8], at: usize, rescale: &impl Fn(u64) -> u64) {
+  if let Some(field) = data.get_mut(at..at + 4) {
+    let position = u32::from_le_bytes(field.try_into().unwrap()) as u64;
+    let position = std::cmp::min(rescale(position), u32::MAX as u64) as u32;
+    field.copy_from_slice(&position.to_le_bytes());
+  }
+}
+
+fn read_u32_at(data: &[u8], at: usize) -> usize {
+  data.get(at..at + 4).map_or(0, |field| {
+    u32::from_le_bytes(field.try_into().unwrap()) as usize
+  })
+}
+
+/**
+ Rescales the sample positions of metadata chunks from the input to the output sample rate, so they
+ point at the same audio once resampled: the TimeReference of `bext`, the cue points of `cue `, the
+ loops and sample period of `smpl` and the region lengths of the `ltxt` of a `LIST` `adtl`.
+*/
+pub fn rescale_wav_metadata(
+  chunks: &mut [WavChunk],
+  sample_rate_input: u32,
+  sample_rate_output: u32,
+) {
+  let rescale = |position: u64| rescale_position(position, sample_rate_input, sample_rate_output);
+  for chunk in chunks.iter_mut() {
+    let data = &mut chunk.data;
+    match &chunk.id {
+      b"bext" if data.len() >= BEXT_TIME_REFERENCE + 8 => {
+        let field = &mut data[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8];
+        let time_reference = u64::from_le_bytes(field.try_into().unwrap());
+        field.copy_from_slice(&rescale(time_reference).to_le_bytes());
+      }
+      // Cue points of 24 bytes, their play position and their offset in the data chunk
+      b"cue " => {
+        let nbr_points = std::cmp::min(read_u32_at(data, 0), data.len().saturating_sub(4) / 24);
+        for point in 0..nbr_points {
+          rescale_u32_at(data, 4 + 24 * point + 4, &rescale);
+          rescale_u32_at(data, 4 + 24 * point + 20, &rescale);
+        }
+      }
+      // 36 bytes of header, the sample period in ns at 8, then loops of 24 bytes
+      b"smpl" => {
+        if let Some(field) = data.get_mut(8..12) {
+          let sample_period = (1_000_000_000 + sample_rate_output / 2) / sample_rate_output;
+          field.copy_from_slice(&sample_period.to_le_bytes());
+        }
+        let nbr_loops = std::cmp::min(read_u32_at(data, 28), data.len().saturating_sub(36) / 24);
+        for sample_loop in 0..nbr_loops {
+          rescale_u32_at(data, 36 + 24 * sample_loop + 8, &rescale);
+          rescale_u32_at(data, 36 + 24 * sample_loop + 12, &rescale);
+        }
+      }
+      b"LIST" if data.starts_with(b"adtl") => {
+        let mut at = 4;
+        while at + 8 <= data.len() {
+          let size = read_u32_at(data, at + 4);
+          if &data[at..at + 4] == b"ltxt" {
+            rescale_u32_at(data, at + 12, &rescale);
+          }
+          at += 8 + size + (size & 1);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+/**
+ Keeps the metadata of a WAV input that still applies to a part of it starting at input frame
+ `start`: the TimeReference of `bext` moves to the start of the part, and the markers of the whole
+ file (`cue `, `smpl`, `LIST` `adtl`) are dropped.
+*/
+pub fn cut_wav_metadata(chunks: &mut Vec<WavChunk>, start: u64) {
+  chunks.retain(|chunk| {
+    !(&chunk.id == b"cue "
+      || &chunk.id == b"smpl"
+      || (&chunk.id == b"LIST" && chunk.data.starts_with(b"adtl")))
+  });
+  for chunk in chunks.iter_mut() {
+    if &chunk.id == b"bext" && chunk.data.len() >= BEXT_TIME_REFERENCE + 8 {
+      let field = &mut chunk.data[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8];
+      let time_reference = u64::from_le_bytes(field.try_into().unwrap());
+      field.copy_from_slice(&time_reference.saturating_add(start).to_le_bytes());
+    }
+  }
+}
+
+/**
+ Layout of a written WAV file.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavLayout {
+  /// RIFF WAVE, turned into RF64 in place when the samples exceed 4 GB
+  Riff,
+  /// RF64 with its `ds64` chunk, whatever the size
+  Rf64,
+  /// Sony Wave64 with 64-bit chunk sizes
+  Wave64,
+}
+
+/**
+ Header of a WAV file of little-endian integer or float samples.
+
+ A RIFF header reserves a `JUNK` chunk of the size of a `ds64` chunk so it can become an RF64 header
+ without moving the samples, as recommended by EBU Tech 3306.
+*/
+pub struct WavHeader {
+  layout: WavLayout,
+  sample_rate: u32,
+  channels: usize,
+  format: SampleFormat,
+  chunks: Vec<WavChunk>,
+}
+
+impl WavHeader {
+  pub fn new(
+    layout: WavLayout,
+    sample_rate: u32,
+    channels: usize,
+    format: SampleFormat,
+  ) -> Result<Self, String> {
+    match format {
+      SampleFormat::Int {
+        bits: 16 | 24 | 32,
+        big_endian: false,
+      }
+      | SampleFormat::Float {
+        bits: 32 | 64,
+        big_endian: false,
+      }
+      | SampleFormat::UInt8 => Ok(WavHeader {
+        layout,
+        sample_rate,
+        channels,
+        format,
+        chunks: Vec::new(),
+      }),
+      _ => Err(format!("WAV cannot hold samples of {:?}", format)),
+    }
+  }
+
+  /**
+   Metadata chunks written between the fmt and data chunks.
+  */
+  pub fn with_chunks(mut self, chunks: Vec<WavChunk>) -> Self {
+    self.chunks = chunks;
+    self
+  }
+
+  fn data_len(&self, nbr_frames: u64) -> u64 {
+    nbr_frames * (self.format.bytes_per_sample() * self.channels) as u64
+  }
+
+  fn fmt_chunk(&self) -> Vec<u8> {
+    let bytes_per_sample = self.format.bytes_per_sample();
+    let format_tag = match self.format {
+      SampleFormat::Float { .. } => WAVE_FORMAT_IEEE_FLOAT,
+      _ => WAVE_FORMAT_PCM,
+    };
+    let block_align = bytes_per_sample * self.channels;
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&format_tag.to_le_bytes());
+    fmt.extend_from_slice(&(self.channels as u16).to_le_bytes());
+    fmt.extend_from_slice(&self.sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+    fmt.extend_from_slice(&(block_align as u16).to_le_bytes());
+    fmt.extend_from_slice(&(self.format.bits_per_sample() as u16).to_le_bytes());
+    fmt
+  }
+}
+
+impl PcmHeader for WavHeader {
+  fn header(&self, nbr_frames: u64) -> Result<Vec<u8>, String> {
+    let data_len = self.data_len(nbr_frames);
+    let fmt = self.fmt_chunk();
+    let mut header = Vec::new();
+
+    if self.layout == WavLayout::Wave64 {
+      let fmt_len = 24 + fmt.len() as u64;
+      header.extend_from_slice(&W64_RIFF);
+      header.extend_from_slice(&0u64.to_le_bytes());
+      header.extend_from_slice(&W64_WAVE);
+      header.extend_from_slice(&W64_FMT);
+      header.extend_from_slice(&fmt_len.to_le_bytes());
+      header.extend_from_slice(&fmt);
+      header.resize(header.len().div_ceil(8) * 8, 0);
+      for chunk in &self.chunks {
+        let guid = match &chunk.id {
+          b"LIST" => W64_LIST,
+          id => w64_guid(id),
+        };
+        header.extend_from_slice(&guid);
+        header.extend_from_slice(&(24 + chunk.data.len() as u64).to_le_bytes());
+        header.extend_from_slice(&chunk.data);
+        header.resize(header.len().div_ceil(8) * 8, 0);
+      }
+      let riff_len = header.len() as u64 + 24 + data_len.div_ceil(8) * 8;
+      header[16..24].copy_from_slice(&riff_len.to_le_bytes());
+      header.extend_from_slice(&W64_DATA);
+      header.extend_from_slice(&(24 + data_len).to_le_bytes());
+      return Ok(header);
+    }
+
+    // RIFF size after the JUNK / ds64 (8 + 28), fmt, metadata and data chunk headers
+    let chunks_len: u64 = self
+      .chunks
+      .iter()
+      .map(|chunk| 8 + chunk.data.len() as u64 + (chunk.data.len() as u64 & 1))
+      .sum();
+    let riff_len = 4 + 36 + 8 + fmt.len() as u64 + chunks_len + 8 + data_len + (data_len & 1);
+    let is_rf64 = self.layout == WavLayout::Rf64 || riff_len > u32::MAX as u64;
+    if is_rf64 {
+      header.extend_from_slice(b"RF64");
+      header.extend_from_slice(&u32::MAX.to_le_bytes());
+      header.extend_from_slice(b"WAVE");
+      header.extend_from_slice(b"ds64");
+      header.extend_from_slice(&28u32.to_le_bytes());
+      header.extend_from_slice(&riff_len.to_le_bytes());
+      header.extend_from_slice(&data_len.to_le_bytes());
+      header.extend_from_slice(&nbr_frames.to_le_bytes());
+      header.extend_from_slice(&0u32.to_le_bytes()); // no table of other chunk sizes
+    } else {
+      header.extend_from_slice(b"RIFF");
+      header.extend_from_slice(&(riff_len as u32).to_le_bytes());
+      header.extend_from_slice(b"WAVE");
+      header.extend_from_slice(b"JUNK");
+      header.extend_from_slice(&28u32.to_le_bytes());
+      header.extend_from_slice(&[0; 28]);
+    }
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+    header.extend_from_slice(&fmt);
+    for chunk in &self.chunks {
+      header.extend_from_slice(&chunk.id);
+      header.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+      header.extend_from_slice(&chunk.data);
+      header.resize(header.len() + (chunk.data.len() & 1), 0);
+    }
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&(if is_rf64 { u32::MAX } else { data_len as u32 }).to_le_bytes());
+    Ok(header)
+  }
+
+  fn trailer(&self, nbr_frames: u64) -> Vec<u8> {
+    let data_len = self.data_len(nbr_frames);
+    let padded_len = if self.layout == WavLayout::Wave64 {
+      data_len.div_ceil(8) * 8
+    } else {
+      data_len + (data_len & 1)
+    };
+    vec![0; (padded_len - data_len) as usize]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::pcm::PcmWriter;
+  use crate::codecs::{AudioSink, AudioSource};
+  use std::io::Cursor;
+
+  const INT16: SampleFormat = SampleFormat::Int {
+    bits: 16,
+    big_endian: false,
+  };
+
+  fn write(layout: WavLayout, format: SampleFormat, samples: &[f32], channels: usize) -> Vec<u8> {
+    let header = WavHeader::new(layout, 16000, channels, format).unwrap();
+    let mut writer = PcmWriter::new(Cursor::new(Vec::new()), header, format, channels).unwrap();
+    writer.write_samples(samples).unwrap();
+    writer.finish().unwrap();
+    writer.into_inner().into_inner()
+  }
+
+  fn read(file: Vec<u8>) -> (u32, Vec<Vec<f32>>) {
+    let mut source = wav_source(Cursor::new(file)).unwrap();
+    let mut planar = vec![Vec::new(); source.spec().channels];
+    while let Some(block) = source.read_block().unwrap() {
+      for (all, chan) in planar.iter_mut().zip(block) {
+        all.extend(chan);
+      }
+    }
+    (source.spec().sample_rate, planar)
+  }
+
+  #[test]
+  fn test_wav_round_trip_in_every_layout() {
+    let samples: Vec<f32> = (0..2 * 1001)
+      .map(|i| ((i as f32) * 0.02).sin() * 0.8)
+      .collect();
+    for layout in [WavLayout::Riff, WavLayout::Rf64, WavLayout::Wave64] {
+      for format in [
+        INT16,
+        SampleFormat::Float {
+          bits: 32,
+          big_endian: false,
+        },
+      ] {
+        let file = write(layout, format, &samples, 2);
+        assert!(is_wav(&file));
+        let (sample_rate, planar) = read(file);
+        assert_eq!(sample_rate, 16000);
+        assert_eq!(planar[0].len(), 1001);
+        assert!((planar[1][500] - samples[1001]).abs() < 1e-4);
+      }
+    }
+  }
+
+  #[test]
+  fn test_wav_sizes() {
+    // 3 mono 8-bit frames need a pad byte
+    let file = write(WavLayout::Riff, SampleFormat::UInt8, &[0.0, 0.5, -0.5], 1);
+    assert_eq!(&file[..4], b"RIFF");
+    assert_eq!(&file[12..16], b"JUNK");
+    assert_eq!(file.len() % 2, 0);
+    assert_eq!(
+      u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize,
+      file.len() - 8
+    );
+    assert_eq!(read(file).1[0].len(), 3);
+
+    let file = write(WavLayout::Wave64, INT16, &[0.0, 0.5, -0.5], 1);
+    assert_eq!(file.len() % 8, 0);
+    assert_eq!(
+      u64::from_le_bytes(file[16..24].try_into().unwrap()) as usize,
+      file.len()
+    );
+  }
+
+  #[test]
+  fn test_riff_header_becomes_rf64_over_4_gb() {
+    let header = WavHeader::new(WavLayout::Riff, 48000, 2, INT16).unwrap();
+    let small = header.header(1000).unwrap();
+    let nbr_frames = 1_200_000_000u64;
+    let large = header.header(nbr_frames).unwrap();
+    assert_eq!(small.len(), large.len());
+    assert_eq!(&large[..4], b"RF64");
+    assert_eq!(&large[12..16], b"ds64");
+    assert_eq!(
+      u64::from_le_bytes(large[28..36].try_into().unwrap()),
+      nbr_frames * 4
+    );
+    assert_eq!(
+      u64::from_le_bytes(large[36..44].try_into().unwrap()),
+      nbr_frames
+    );
+
+    // The data size of the ds64 chunk is used instead of the 32-bit one
+    let mut file = large;
+    file.extend_from_slice(&[0x10, 0x00, 0xF0, 0xFF]);
+    let mut source = wav_source(Cursor::new(file)).unwrap();
+    assert_eq!(source.spec().channels, 2);
+    let block = source.read_block().unwrap().unwrap();
+    assert_eq!(block[0].len(), 1);
+  }
+
+  #[test]
+  fn test_wav_fmt_size_beyond_the_file() {
+    for layout in [WavLayout::Riff, WavLayout::Wave64] {
+      let mut file = write(layout, INT16, &[0.0, 0.5, -0.5], 1);
+      let fmt = file.windows(4).position(|id| id == b"fmt ").unwrap();
+      if layout == WavLayout::Wave64 {
+        file[fmt + 16..fmt + 24].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+      } else {
+        file[fmt + 4..fmt + 8].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+      }
+      let err = wav_source(Cursor::new(file)).err().unwrap();
+      assert!(err.starts_with("Invalid WAV file"), "{}", err);
+    }
+  }
+
+  fn bext(time_reference: u64) -> WavChunk {
+    let mut data = vec![0u8; 602];
+    data[..11].copy_from_slice(b"Field sound");
+    data[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+      .copy_from_slice(&time_reference.to_le_bytes());
+    WavChunk { id: *b"bext", data }
+  }
+
+  #[test]
+  fn test_wav_metadata_round_trip() {
+    let list = WavChunk {
+      id: *b"LIST",
+      data: b"INFOINAM\x05\0\0\0Take\0\0".to_vec(),
+    };
+    let cue = WavChunk {
+      id: *b"cue ",
+      data: vec![0; 4],
+    };
+    let ixml = WavChunk {
+      id: *b"iXML",
+      data: b"<BWFXML/>".to_vec(),
+    };
+    for layout in [WavLayout::Riff, WavLayout::Rf64, WavLayout::Wave64] {
+      let header = WavHeader::new(layout, 48000, 1, INT16)
+        .unwrap()
+        .with_chunks(vec![list.clone(), bext(48000 * 3600), cue.clone()]);
+      let mut writer = PcmWriter::new(Cursor::new(Vec::new()), header, INT16, 1).unwrap();
+      writer.write_samples(&[0.0, 0.5, -0.5]).unwrap();
+      writer.finish().unwrap();
+      let mut file = writer.into_inner().into_inner();
+      assert_eq!(read(file.clone()).1[0].len(), 3);
+
+      // A chunk after the samples, with a pad byte
+      if layout != WavLayout::Wave64 {
+        file.extend_from_slice(&ixml.id);
+        file.extend_from_slice(&(ixml.data.len() as u32).to_le_bytes());
+        file.extend_from_slice(&ixml.data);
+        file.push(0);
+      }
+      let mut chunks = wav_metadata(Cursor::new(file)).unwrap();
+      let mut expected = vec![list.clone(), bext(48000 * 3600), cue.clone()];
+      if layout != WavLayout::Wave64 {
+        expected.push(ixml.clone());
+      }
+      assert_eq!(chunks, expected);
+
+      rescale_wav_metadata(&mut chunks, 48000, 16000);
+      assert_eq!(chunks[1], bext(16000 * 3600));
+    }
+  }
+
+  #[test]
+  fn test_wav_metadata_size_beyond_the_file() {
+    let list = WavChunk {
+      id: *b"LIST",
+      data: b"INFO".to_vec(),
+    };
+    let header = WavHeader::new(WavLayout::Riff, 48000, 1, INT16)
+      .unwrap()
+      .with_chunks(vec![list]);
+    let mut writer = PcmWriter::new(Cursor::new(Vec::new()), header, INT16, 1).unwrap();
+    writer.write_samples(&[0.0, 0.5, -0.5]).unwrap();
+    writer.finish().unwrap();
+    let mut file = writer.into_inner().into_inner();
+    let at = file.windows(4).position(|id| id == b"LIST").unwrap() + 4;
+    file[at..at + 4].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    let err = wav_metadata(Cursor::new(file)).unwrap_err();
+    assert!(err.starts_with("Invalid WAV file"), "{}", err);
+  }
+
+  #[test]
+  fn test_rescale_wav_markers() {
+    let u32s =
+      |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+    let mut cue = u32s(&[2]);
+    cue.extend(u32s(&[1, 44100, u32::from_le_bytes(*b"data"), 0, 0, 44100]));
+    cue.extend(u32s(&[2, 88200, u32::from_le_bytes(*b"data"), 0, 0, 88200]));
+    let mut smpl = u32s(&[0, 0, 22676, 60, 0, 0, 0, 1, 0]);
+    smpl.extend(u32s(&[1, 0, 441, 44100, 0, 0]));
+    let mut adtl = b"adtl".to_vec();
+    adtl.extend_from_slice(b"ltxt");
+    adtl.extend(u32s(&[20, 2, 4410]));
+    adtl.extend_from_slice(b"rgn \0\0\0\0\0\0\0\0");
+    let mut chunks = vec![
+      WavChunk {
+        id: *b"cue ",
+        data: cue,
+      },
+      WavChunk {
+        id: *b"smpl",
+        data: smpl,
+      },
+      WavChunk {
+        id: *b"LIST",
+        data: adtl,
+      },
+    ];
+    rescale_wav_metadata(&mut chunks, 44100, 16000);
+    assert_eq!(read_u32_at(&chunks[0].data, 4 + 4), 16000);
+    assert_eq!(read_u32_at(&chunks[0].data, 4 + 24 + 20), 32000);
+    assert_eq!(read_u32_at(&chunks[1].data, 8), 62500);
+    assert_eq!(read_u32_at(&chunks[1].data, 36 + 8), 160);
+    assert_eq!(read_u32_at(&chunks[1].data, 36 + 12), 16000);
+    assert_eq!(read_u32_at(&chunks[2].data, 4 + 12), 1600);
+
+    // Counts larger than the chunk are not followed
+    let mut truncated = vec![WavChunk {
+      id: *b"cue ",
+      data: u32s(&[1000, 1, 8]),
+    }];
+    rescale_wav_metadata(&mut truncated, 8000, 16000);
+    assert_eq!(truncated[0].data, u32s(&[1000, 1, 8]));
+  }
+
+  #[test]
+  fn test_cut_wav_metadata() {
+    let mut bext = vec![0u8; BEXT_TIME_REFERENCE + 8];
+    bext[BEXT_TIME_REFERENCE..].copy_from_slice(&1000u64.to_le_bytes());
+    let mut chunks = vec![
+      WavChunk {
+        id: *b"bext",
+        data: bext,
+      },
+      WavChunk {
+        id: *b"cue ",
+        data: vec![0; 4],
+      },
+      WavChunk {
+        id: *b"LIST",
+        data: b"INFO".to_vec(),
+      },
+      WavChunk {
+        id: *b"LIST",
+        data: b"adtl".to_vec(),
+      },
+    ];
+    cut_wav_metadata(&mut chunks, 44100);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(
+      chunks[0].data[BEXT_TIME_REFERENCE..],
+      45100u64.to_le_bytes()
+    );
+    assert_eq!(chunks[1].data, b"INFO");
+  }
+
+  #[test]
+  fn test_wav_extensible_fmt() {
+    let mut fmt = vec![0u8; 40];
+    fmt[..2].copy_from_slice(&WAVE_FORMAT_EXTENSIBLE.to_le_bytes());
+    fmt[2..4].copy_from_slice(&6u16.to_le_bytes());
+    fmt[4..8].copy_from_slice(&48000u32.to_le_bytes());
+    fmt[12..14].copy_from_slice(&18u16.to_le_bytes());
+    fmt[14..16].copy_from_slice(&24u16.to_le_bytes());
+    fmt[18..20].copy_from_slice(&20u16.to_le_bytes());
+    fmt[24..26].copy_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+    assert_eq!(
+      parse_fmt(&fmt),
+      Ok((
+        6,
+        48000,
+        SampleFormat::Int {
+          bits: 24,
+          big_endian: false
+        }
+      ))
+    );
+    fmt[24..26].copy_from_slice(&0x0055u16.to_le_bytes());
+    assert!(parse_fmt(&fmt).is_err());
+  }
+}
@@ -0,0 +1,172 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::channels::{check_sample_rates, remix_channels};
+use crate::codecs::{AudioSink, AudioSource};
+
+/**
+ What goes between two concatenated sources.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Join {
+  /// This many silent frames
+  Silence(u64),
+  /// The last frames of a source mixed with as many first frames of the next one, through
+  /// equal-power fades
+  Crossfade(u64),
+}
+
+/**
+ Streams sources of the same sample rate one after the other into an `AudioSink` of `channels`
+ channels, remixing each one with `remix_channels`, and finishes it. Only the frames of a crossfade
+ are held back. Returns the output frame where each source starts and the number of output frames.
+
+ A source shorter than the crossfade only mixes with the start of it, the rest of the previous
+ source fading out alone.
+*/
+pub fn concat_sources(
+  sources: &mut [Box<dyn AudioSource>],
+  channels: usize,
+  join: Join,
+  sink: &mut dyn AudioSink,
+) -> Result<(Vec<u64>, u64), String> {
+  check_sample_rates(sources)?;
+  let held = match join {
+    Join::Crossfade(frames) => frames as usize * channels,
+    Join::Silence(_) => 0,
+  };
+  // Interleaved frames of the output not written yet, the ones a crossfade may still mix
+  let mut tail = Vec::new();
+  let mut samples = Vec::new();
+  let mut nbr_frames = 0;
+  let mut starts = Vec::with_capacity(sources.len());
+  for (index, source) in sources.iter_mut().enumerate() {
+    if let (Join::Silence(frames), true) = (join, index > 0) {
+      write_frames(
+        sink,
+        &mut tail,
+        held,
+        &vec![0.0; frames as usize * channels],
+      )?;
+      nbr_frames += frames;
+    }
+    // Frames at the end of the output mixed with the start of this source
+    let fade = tail.len() / channels;
+    let gains = |frame: usize| {
+      let angle = (frame as f32 + 0.5) / fade as f32 * FRAC_PI_2;
+      (angle.cos(), angle.sin())
+    };
+    let mut faded = 0;
+    starts.push(nbr_frames - fade as u64);
+    while let Some(block) = source.read_block()? {
+      let block = remix_channels(block, channels);
+      let len = block[0].len();
+      let mut from = 0;
+      while faded < fade && from < len {
+        let (fade_out, fade_in) = gains(faded);
+        for (channel, frames) in block.iter().enumerate() {
+          let sample = &mut tail[faded * channels + channel];
+          *sample = *sample * fade_out + frames[from] * fade_in;
+        }
+        faded += 1;
+        from += 1;
+      }
+      samples.clear();
+      for frame in from..len {
+        samples.extend(block.iter().map(|channel| channel[frame]));
+      }
+      write_frames(sink, &mut tail, held, &samples)?;
+      nbr_frames += (len - from) as u64;
+    }
+    for frame in faded..fade {
+      let (fade_out, _) = gains(frame);
+      for sample in &mut tail[frame * channels..(frame + 1) * channels] {
+        *sample *= fade_out;
+      }
+    }
+  }
+  sink.write_samples(&tail)?;
+  sink.finish()?;
+  Ok((starts, nbr_frames))
+}
+
+// Adds interleaved samples to the output, holding back its last `held` samples in `tail`
+fn write_frames(
+  sink: &mut dyn AudioSink,
+  tail: &mut Vec<f32>,
+  held: usize,
+  samples: &[f32],
+) -> Result<(), String> {
+  tail.extend_from_slice(samples);
+  if tail.len() > held {
+    let ready = tail.len() - held;
+    sink.write_samples(&tail[..ready])?;
+    tail.drain(..ready);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::AudioSpec;
+
+  struct BlockSource(Vec<Vec<Vec<f32>>>);
+
+  impl AudioSource for BlockSource {
+    fn spec(&self) -> AudioSpec {
+      AudioSpec {
+        sample_rate: 16000,
+        channels: self.0[0].len(),
+        bits_per_sample: 32,
+      }
+    }
+
+    fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+      Ok((!self.0.is_empty()).then(|| self.0.remove(0)))
+    }
+
+    fn codec(&self) -> String {
+      "pcm_f32le".to_string()
+    }
+
+    fn sample_format(&self) -> String {
+      "f32le".to_string()
+    }
+  }
+
+  fn sources(clips: &[Vec<Vec<f32>>]) -> Vec<Box<dyn AudioSource>> {
+    clips
+      .iter()
+      .map(|clip| Box::new(BlockSource(vec![clip.clone()])) as Box<dyn AudioSource>)
+      .collect()
+  }
+
+  #[test]
+  fn test_sources_are_concatenated_with_silence() {
+    let clips = [vec![vec![1.0, 1.0]], vec![vec![0.5], vec![-0.5]]];
+    let mut output = Vec::new();
+    let joined = concat_sources(&mut sources(&clips), 2, Join::Silence(1), &mut output);
+    assert_eq!(joined, Ok((vec![0, 3], 4)));
+    assert_eq!(output, [1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.5, -0.5]);
+  }
+
+  #[test]
+  fn test_sources_are_crossfaded() {
+    let clips = [vec![vec![1.0; 6]], vec![vec![-1.0; 6]], vec![vec![0.5; 2]]];
+    let mut output = Vec::new();
+    let joined = concat_sources(&mut sources(&clips), 1, Join::Crossfade(4), &mut output);
+    assert_eq!(joined, Ok((vec![0, 2, 4], 8)));
+    assert_eq!(output.len(), 8);
+    assert_eq!(output[..2], [1.0, 1.0]);
+    // The first clip fades out while the second one fades in
+    assert!(output[2] > 0.0 && output[5] < 0.0);
+    let (fade_out, fade_in) = (
+      (0.5f32 * FRAC_PI_2 / 4.0).cos(),
+      (0.5f32 * FRAC_PI_2 / 4.0).sin(),
+    );
+    assert!((output[2] - (fade_out - fade_in)).abs() < 1e-6);
+    // The last clip is shorter than the crossfade, the rest of the second one fades out alone
+    let angle = 3.5 * FRAC_PI_2 / 4.0;
+    assert!((output[7] - -angle.cos()).abs() < 1e-6);
+  }
+}
@@ -0,0 +1,201 @@
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+use crate::codecs::has_extension;
+
+/**
+ Which files of a directory are selected: the ones whose path relative to the directory matches one
+ of the `include` globs, where `*` does not cross `/` and `**` matches any directories, and whose
+ extension is one of `extensions`, ignoring case, when they are given.
+*/
+pub struct FileFilter {
+  include: Option<GlobSet>,
+  extensions: Option<Vec<String>>,
+}
+
+impl FileFilter {
+  pub fn new(include: Option<&[String]>, extensions: Option<&[String]>) -> Result<Self, String> {
+    let include = include
+      .map(|patterns| {
+        let mut globs = GlobSetBuilder::new();
+        for pattern in patterns {
+          let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| format!("Invalid glob {} : {}", pattern, err))?;
+          globs.add(glob);
+        }
+        globs.build().map_err(|err| err.to_string())
+      })
+      .transpose()?;
+    let extensions = extensions.map(|extensions| {
+      extensions
+        .iter()
+        .map(|extension| extension.trim_start_matches('.').to_string())
+        .collect()
+    });
+    Ok(FileFilter {
+      include,
+      extensions,
+    })
+  }
+
+  /**
+   Whether the filter has neither globs nor extensions, and selects every file.
+  */
+  pub fn is_empty(&self) -> bool {
+    self.include.is_none() && self.extensions.is_none()
+  }
+
+  pub fn matches(&self, relative: &Path) -> bool {
+    let path = relative.to_string_lossy();
+    self
+      .include
+      .as_ref()
+      .is_none_or(|include| include.is_match(relative))
+      && self.extensions.as_ref().is_none_or(|extensions| {
+        extensions
+          .iter()
+          .any(|extension| has_extension(&path, extension))
+      })
+  }
+}
+
+/**
+ Paths relative to `root` of the files under it selected by `select`, sorted by name, and in their
+ place the path and error of the entries that cannot be listed, like an unreadable directory or a
+ broken link. The `skipped` directory, like an output directory inside the input one, is not walked.
+*/
+pub fn find_files(
+  root: &Path,
+  skipped: Option<&Path>,
+  select: impl Fn(&Path) -> bool,
+) -> Vec<Result<PathBuf, (PathBuf, String)>> {
+  let skipped = skipped.and_then(|skipped| skipped.canonicalize().ok());
+  WalkDir::new(root)
+    .follow_links(true)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_entry(|entry| {
+      !entry.file_type().is_dir()
+        || skipped.is_none()
+        || entry.path().canonicalize().ok() != skipped
+    })
+    .filter_map(|entry| match entry {
+      Ok(entry) if entry.file_type().is_file() => {
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        select(relative).then(|| Ok(relative.to_path_buf()))
+      }
+      Ok(_) => None,
+      Err(err) => {
+        let path = err.path().unwrap_or(root).to_path_buf();
+        let reason = format!("Cannot list {} : {}", path.display(), err);
+        Some(Err((path, reason)))
+      }
+    })
+    .collect()
+}
+
+/**
+ Path under `output_directory` mirroring `relative`, with its extension replaced by
+ `output_extension` when given.
+*/
+pub fn mirror_path(
+  output_directory: &Path,
+  relative: &Path,
+  output_extension: Option<&str>,
+) -> PathBuf {
+  let path = output_directory.join(relative);
+  match output_extension {
+    Some(extension) => path.with_extension(extension.trim_start_matches('.')),
+    None => path,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for file in [
+      "a.wav",
+      "b.WAV",
+      "notes.txt",
+      "calls/c.flac",
+      "calls/2024/d.wav",
+      "out/a.flac",
+    ] {
+      let path = dir.path().join(file);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, b"").unwrap();
+    }
+    dir
+  }
+
+  fn found(root: &Path, skipped: Option<&Path>, filter: &FileFilter) -> Vec<String> {
+    find_files(root, skipped, |path| filter.matches(path))
+      .into_iter()
+      .map(|path| path.unwrap().to_string_lossy().replace('\\', "/"))
+      .collect()
+  }
+
+  #[test]
+  fn test_files_are_selected_by_glob_and_extension() {
+    let dir = tree();
+    let out = dir.path().join("out");
+    let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+    let wav = FileFilter::new(None, Some(&strings(&[".wav"]))).unwrap();
+    assert_eq!(
+      found(dir.path(), Some(&out), &wav),
+      ["a.wav", "b.WAV", "calls/2024/d.wav"]
+    );
+    let calls = FileFilter::new(Some(&strings(&["calls/**/*"])), None).unwrap();
+    assert_eq!(
+      found(dir.path(), Some(&out), &calls),
+      ["calls/2024/d.wav", "calls/c.flac"]
+    );
+    let top = FileFilter::new(Some(&strings(&["*.wav", "*.flac"])), None).unwrap();
+    assert_eq!(found(dir.path(), Some(&out), &top), ["a.wav"]);
+    // Without the output directory skipped, its files are found too
+    let all = FileFilter::new(None, None).unwrap();
+    assert!(all.is_empty());
+    assert_eq!(found(dir.path(), None, &all).len(), 6);
+    assert_eq!(found(dir.path(), Some(&out), &all).len(), 5);
+    assert!(FileFilter::new(Some(&strings(&["calls/[a"])), None).is_err());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_entries_that_cannot_be_listed_are_kept_in_place() {
+    let dir = tree();
+    std::os::unix::fs::symlink(
+      dir.path().join("missing.wav"),
+      dir.path().join("broken.wav"),
+    )
+    .unwrap();
+    let wav = FileFilter::new(None, Some(&["wav".to_string()])).unwrap();
+    let files = find_files(dir.path(), None, |path| wav.matches(path));
+    assert_eq!(files.len(), 4);
+    assert_eq!(files[1], Ok(PathBuf::from("b.WAV")));
+    let (path, reason) = files[2].clone().unwrap_err();
+    assert_eq!(path, dir.path().join("broken.wav"));
+    assert!(reason.starts_with("Cannot list"), "{}", reason);
+    assert_eq!(files[3], Ok(PathBuf::from("calls/2024/d.wav")));
+  }
+
+  #[test]
+  fn test_mirror_path() {
+    assert_eq!(
+      mirror_path(Path::new("out"), Path::new("calls/c.wav"), Some(".flac")),
+      Path::new("out/calls/c.flac")
+    );
+    assert_eq!(
+      mirror_path(Path::new("out"), Path::new("c.wav"), None),
+      Path::new("out/c.wav")
+    );
+  }
+}
@@ -5,9 +5,7 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use log::{debug, error};
 use num_traits::FromPrimitive;
 use std::convert::TryInto;
-use std::fs::File;
 use std::io::prelude::Read;
-use std::io::{BufWriter, Write};
 
 /**
  Reads data from a Read trait and converts it into a vector of vectors containing 32-bit floating-point numbers (f32).
@@ -53,7 +51,7 @@ pub fn f32_buffer_to_vecs<R: Read>(input_reader: &mut R, channels: usize) -> Vec
       let value = match buffer.as_slice().try_into() {
         Ok(bytes) => f32::from_le_bytes(bytes),
         Err(error) => {
-          error!("Error of conversion to f32 {}", error.to_string());
+          error!("Error of conversion to f32 {}", error);
           0.0
         }
       };
@@ -153,7 +151,6 @@ pub fn i16_buffer_to_vecs<R: Read>(input_reader: &mut R, channels: usize) -> Vec
 
  Returns an error if `frames_to_skip + frames_to_write` exceeds the length of frames for any channel.
 **/
-
 pub fn skip_frames(
   frames: Vec<Vec<f32>>,
   frames_to_skip: usize,
@@ -217,51 +214,31 @@ pub fn append_frames(buffers: &mut [Vec<f32>], additional: &[Vec<f32>], nbr_fram
 }
 
 /**
-Write a vector of bytes to a file on disk.
-
-This function takes a vector of bytes (`frames`) and a file path (`output`), and writes the
-bytes to the specified file. It creates the file if it doesn't exist and overwrites it if it
-does. The function also handles errors related to file creation and writing.
-
-# Arguments
-
-* `frames` - A vector of bytes to be written to the file.
-* `output` - The file path where the bytes will be written.
-
-# Errors
+ Appends planar frames to an interleaved buffer, that is the layout expected in the output files and buffers.
 
-If an error occurs during file creation or writing, this function will log the error using the
-`error!` macro from a logging framework (not shown here). The error message will contain
-details about the specific error encountered.
+ # Arguments
 
-# Examples
+ * `frames` - A slice of vectors, one per channel, all of the same length.
+ * `output` - The interleaved buffer to extend.
 
-```rust
-# use std::fs;
-# use tempfile::tempdir;
+ # Example
 
-# fn main() {
-#     let temp_dir = tempdir().expect("Failed to create temporary directory");
-#     let output_file = temp_dir.path().join("output.bin");
-let frames = vec![0, 1, 2, 3, 4];
-let output_path = output_file.to_str().expect("Invalid path").to_string();
+ ```
+ use my_audio_library::interleave_frames;
 
-write_frames_to_disk(frames, output_path);
+ let mut output = vec![];
+ interleave_frames(&[vec![1.0, 2.0], vec![3.0, 4.0]], &mut output);
 
-// You can now assert the contents of the file or handle any errors gracefully in tests.
-# }
-```
+ assert_eq!(output, vec![1.0, 3.0, 2.0, 4.0]);
+ ```
 */
-pub fn write_frames_to_disk(frames: Vec<u8>, output: String) {
-  let file = File::create(output).expect("Cannot create output file");
-  let mut file_out_disk = BufWriter::new(file);
-
-  if let Err(err) = file_out_disk.write_all(&frames) {
-    error!("Cannot send data to file : {:?}", err);
-  }
-
-  if let Err(err) = file_out_disk.flush() {
-    error!("Cannot clear tmp : {:?}", err);
+pub fn interleave_frames(frames: &[Vec<f32>], output: &mut Vec<f32>) {
+  let nbr_frames = frames.first().map_or(0, |channel| channel.len());
+  output.reserve(nbr_frames * frames.len());
+  for frame in 0..nbr_frames {
+    for channel in frames {
+      output.push(channel[frame]);
+    }
   }
 }
 
@@ -432,19 +409,17 @@ mod tests {
     assert_eq!(audio_buffers[0], vec![1.0, 2.0, 5.0]);
     assert_eq!(audio_buffers[1], vec![3.0, 4.0, 7.0]);
   }
-  use std::{fs, io::Cursor};
-  use tempfile::tempdir;
-  #[test]
-  fn test_write_frames_to_disk() {
-    let temp_dir = tempdir().expect("Failed to create temporary directory");
-    let output_file = temp_dir.path().join("output.bin");
-    let output_path = output_file.to_str().expect("Invalid path").to_string();
-    let frames = vec![0, 1, 2, 3, 4];
 
-    write_frames_to_disk(frames.clone(), output_path);
+  /**
+   * ? interleave_frames Unit Tests
+   */
+  #[test]
+  fn test_interleave_frames() {
+    let mut output = vec![0.5];
+    interleave_frames(&[vec![1.0, 2.0], vec![3.0, 4.0]], &mut output);
 
-    // Read the file and check its contents
-    let file_contents = fs::read(output_file).expect("Failed to read file");
-    assert_eq!(file_contents, frames);
+    assert_eq!(output, vec![0.5, 1.0, 3.0, 2.0, 4.0]);
   }
+
+  use std::io::Cursor;
 }
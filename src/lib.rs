@@ -3,23 +3,52 @@ extern crate napi_derive;
 extern crate num_traits;
 extern crate rubato;
 
+mod batch;
+mod channels;
+mod codecs;
+mod concat;
+mod directory;
 mod helpers;
+mod output;
+mod probe;
+mod segment;
+mod stream;
 
 use log::debug;
 use num_traits::FromPrimitive;
 use rubato::{implement_resampler, FastFixedOut, PolynomialDegree};
 
 use std::fs::File;
-use std::io::{BufReader, Cursor};
+use std::io::{BufReader, Cursor, Seek, Write};
+use std::path::Path;
 use std::time::Instant;
 use std::vec;
 
 use napi::bindgen_prelude::*;
-use napi::JsUndefined;
 use napi_derive::napi;
+use symphonia::core::io::MediaSource;
 
-use crate::helpers::{
-  append_frames, f32_buffer_to_vecs, i16_buffer_to_vecs, skip_frames, write_frames_to_disk,
+use crate::batch::{default_threads, run_jobs};
+use crate::channels::{interleave_sources, ChannelSplitSink};
+use crate::codecs::aiff::AiffHeader;
+use crate::codecs::au::AuHeader;
+use crate::codecs::flac::{encode_flac, FlacWriter, DEFAULT_COMPRESSION_LEVEL};
+use crate::codecs::opus::opus_sink;
+use crate::codecs::pcm::{PcmWriter, SampleFormat};
+use crate::codecs::raw::{RawFormat, RawWriter};
+use crate::codecs::wav::{
+  cut_wav_metadata, rescale_wav_metadata, wav_metadata, WavChunk, WavHeader, WavLayout,
+};
+use crate::codecs::{has_extension, AudioSink, AudioSource, AudioSpec, InputFormat, MemoryFile};
+use crate::concat::{concat_sources, Join};
+use crate::directory::{find_files, mirror_path, FileFilter};
+use crate::helpers::{append_frames, f32_buffer_to_vecs, i16_buffer_to_vecs, skip_frames};
+use crate::output::{is_up_to_date, parent_directory, template_path, OutputFile};
+use crate::probe::probe_source;
+use crate::segment::{Segment, SegmentLayout, SegmentSink};
+use crate::stream::{
+  re_sample_audio_source, re_sample_source_into_sink, rescale_position, FrameRange, PlanarSource,
+  ResampledSource,
 };
 
 implement_resampler!(SliceResampler, &[&[T]], &mut [Vec<T>]);
@@ -47,72 +76,1434 @@ pub enum DataType {
   F32,
 }
 
+/**
+ * Container of the resampled file, raw samples of `type_of_bin_data` unless `output_path` ends with
+ * `.flac`, `.opus`, `.aif`, `.aiff`, `.aifc`, `.wav`, `.rf64`, `.w64`, `.au` or `.snd`. FLAC and
+ * AIFF keep 16 bits for `DataType.I16` and 24 bits for `DataType.F32`, AIFF-C keeps little-endian 16
+ * bits (`sowt`) or 32-bit floats (`fl32`), the WAV and AU containers keep `type_of_bin_data`. A WAV
+ * file becomes RF64 when its samples exceed 4 GB and keeps the metadata chunks (`LIST`, `bext`,
+ * `iXML`, `cue `, `smpl`...) of a WAV input, their sample positions rescaled
+ */
+#[napi]
+pub enum OutputContainer {
+  Raw,
+  Flac,
+  /// Needs an output sample rate of 48000, 24000, 16000, 12000 or 8000 and the `opus` feature
+  Opus,
+  Aiff,
+  Aifc,
+  Wav,
+  Rf64,
+  Wave64,
+  Au,
+}
+
+/**
+ * `input_raw_path` can also be a `.flac`, an Ogg Vorbis `.ogg`, an `.mp3`, an Ogg Opus `.opus`, an
+ * AIFF `.aif` / `.aiff` / `.aifc`, a WAV `.wav` / `.rf64` / `.w64`, an AU `.au` / `.snd` or a CAF
+ * `.caf` file, its sample rate and channels are then taken from the file header instead of
+ * `args_audio_to_re_sample`. Opus is always decoded at 48 kHz.
+ *
+ * Raw samples are described either by `args_audio_to_re_sample` and `type_of_bin_data` or by the
+ * `input_format` and `output_format` descriptors, like `s16le:2:44100`. An `input_format` of `auto`
+ * finds the container from the first bytes of the file instead of its extension
+ */
 #[napi(object)]
 pub struct ArgsAudioFile {
-  pub args_audio_to_re_sample: ArgsAudioToReSample,
+  pub args_audio_to_re_sample: Option<ArgsAudioToReSample>,
   pub input_raw_path: String,
   pub output_path: String,
-  pub type_of_bin_data: DataType,
+  pub type_of_bin_data: Option<DataType>,
+  /// Raw input as `<format>:<channels>:<rate>`, like `s16le:2:44100`, `f32be:1:48000` or `mulaw:1:8000`
+  pub input_format: Option<String>,
+  /// Raw output as `<format>:<channels>:<rate>`, the rate being the one to resample to
+  pub output_format: Option<String>,
+  pub output_container: Option<OutputContainer>,
+  /// FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default
+  pub compression_level: Option<u8>,
+  /// How the output is flushed to the disk before and after it replaces `output_path`, `File` by default
+  pub output_sync: Option<OutputSync>,
+  /// What happens when `output_path` already exists, `Overwrite` by default
+  pub write_policy: Option<WritePolicy>,
+  /// Creates the missing directories of `output_path` instead of throwing `OutputDirectoryMissing`
+  pub create_output_directory: Option<bool>,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
+  /// Splits the output into files of this many seconds, named after `output_path` with its
+  /// `{segment}` replaced by `000`, `001`..., or with `_000`, `_001`... before its extension
+  pub segment_duration: Option<f64>,
+  /// Seconds at the end of each segment repeated at the start of the next one
+  pub segment_overlap: Option<f64>,
+  /// Moves each cut between segments to the closest zero crossing, at most 10 ms away
+  pub segment_at_zero_crossing: Option<bool>,
+  /// Writes each channel to its own mono file, named after `output_path` with its `{channel}`
+  /// replaced by `0`, `1`..., or with `_0`, `_1`... before its extension
+  pub split_channels: Option<bool>,
+}
+
+/**
+ * Unit of the `start` and `end` of the part of an input to resample. The part is resampled with
+ * enough of the audio around it to be the same as the matching part of the resampled whole input,
+ * the uncompressed inputs (raw, WAV, AIFF, AU, CAF LPCM) seeking straight to it
+ */
+#[napi]
+pub enum RangeUnit {
+  Seconds,
+  Frames,
+}
+
+/**
+ * Range of input frames of `start` and `end`, the whole input when both are missing
+ */
+fn frame_range(
+  start: Option<f64>,
+  end: Option<f64>,
+  range_unit: Option<RangeUnit>,
+  sample_rate_input: u32,
+) -> Result<FrameRange> {
+  let range_unit = range_unit.unwrap_or(RangeUnit::Seconds);
+  let to_frame = |bound: f64| {
+    if !bound.is_finite() || bound < 0.0 {
+      return Err(Error::from_reason(format!(
+        "Invalid range bound {}, it must be a positive number",
+        bound
+      )));
+    }
+    Ok(match range_unit {
+      RangeUnit::Seconds => (bound * sample_rate_input as f64).round() as u64,
+      RangeUnit::Frames => bound.round() as u64,
+    })
+  };
+  FrameRange::new(
+    start.map(to_frame).transpose()?.unwrap_or(0),
+    end.map(to_frame).transpose()?,
+  )
+  .map_err(Error::from_reason)
+}
+
+/**
+ * Path of a segment of the output, `index` counting from 0
+ */
+fn segment_path(output_path: &str, index: usize) -> String {
+  template_path(output_path, "segment", &format!("{:03}", index))
 }
 
+/**
+ * Path of the mono file of a channel of the output, `channel` counting from 0
+ */
+fn channel_path(output_path: &str, channel: usize) -> String {
+  template_path(output_path, "channel", &channel.to_string())
+}
+
+/**
+ * The path checked against the write policy before resampling, the one of the first segment or
+ * channel of a split output. Every output path is checked again before it is committed
+ */
+fn primary_output_path(args: &ArgsAudioFile) -> String {
+  if args.segment_duration.is_some() {
+    segment_path(&args.output_path, 0)
+  } else if args.split_channels.unwrap_or(false) {
+    channel_path(&args.output_path, 0)
+  } else {
+    args.output_path.clone()
+  }
+}
+
+/**
+ * Segments of `segment_duration` seconds overlapping by `segment_overlap` seconds, in output frames
+ */
+fn segment_layout(
+  segment_duration: f64,
+  segment_overlap: Option<f64>,
+  segment_at_zero_crossing: bool,
+  sample_rate_output: u32,
+) -> Result<SegmentLayout> {
+  let to_frames = |seconds: f64| {
+    if !seconds.is_finite() || seconds < 0.0 {
+      return Err(Error::from_reason(format!(
+        "Invalid segment duration {}, it must be a positive number",
+        seconds
+      )));
+    }
+    Ok((seconds * sample_rate_output as f64).round() as u64)
+  };
+  let zero_crossing_window = if segment_at_zero_crossing {
+    (sample_rate_output / 100) as u64
+  } else {
+    0
+  };
+  SegmentLayout::new(
+    to_frames(segment_duration)?,
+    to_frames(segment_overlap.unwrap_or(0.0))?,
+    zero_crossing_window,
+  )
+  .map_err(Error::from_reason)
+}
+
+/**
+ * The output is written to a hidden temporary file next to `output_path` and renamed over it once
+ * complete, so `output_path` never holds partial audio. `OutputSync` chooses what is flushed to the
+ * disk around the rename: nothing (`None`, fastest, the rename may then reach the disk before the
+ * samples), the samples before the rename (`File`) or also the rename itself (`Directory`)
+ */
 #[napi]
-pub fn re_sample_audio_file(args: ArgsAudioFile) {
+pub enum OutputSync {
+  None,
+  File,
+  Directory,
+}
+
+/**
+ * What happens when `output_path` already exists: it is replaced (`Overwrite`), the call throws an
+ * `OutputExists` error (`ErrorIfExists`), or nothing is resampled when it was modified after the
+ * input (`SkipIfUpToDate`)
+ */
+#[napi]
+pub enum WritePolicy {
+  Overwrite,
+  ErrorIfExists,
+  SkipIfUpToDate,
+}
+
+/**
+ * `code` of the errors thrown by `reSampleAudioFile`, `interleaveAudioFiles` and `concatAudioFiles`,
+ * and of the failed jobs of `reSampleAudioFiles`
+ */
+#[napi(string_enum)]
+pub enum FileErrorCode {
+  /// `output_path` exists and the write policy is `ErrorIfExists`
+  OutputExists,
+  /// The directory of `output_path` does not exist and `create_output_directory` is not set
+  OutputDirectoryMissing,
+  /// The directory of `output_path` cannot be created, or the output cannot be written there
+  OutputNotWritable,
+  /// Any other error, about the input, the formats or the resampling
+  GenericFailure,
+}
+
+impl AsRef<str> for FileErrorCode {
+  fn as_ref(&self) -> &str {
+    match self {
+      FileErrorCode::OutputExists => "OutputExists",
+      FileErrorCode::OutputDirectoryMissing => "OutputDirectoryMissing",
+      FileErrorCode::OutputNotWritable => "OutputNotWritable",
+      FileErrorCode::GenericFailure => "GenericFailure",
+    }
+  }
+}
+
+fn file_error<R: ToString>(code: FileErrorCode, reason: R) -> Error<FileErrorCode> {
+  Error::new(code, reason)
+}
+
+/**
+ * Checks `output_path` against the write policy before anything is resampled, creating its
+ * directory when asked
+ */
+fn check_output_path(
+  output_path: &str,
+  write_policy: WritePolicy,
+  create_output_directory: bool,
+) -> Result<(), FileErrorCode> {
+  let path = Path::new(output_path);
+  if matches!(write_policy, WritePolicy::ErrorIfExists) && path.exists() {
+    return Err(file_error(
+      FileErrorCode::OutputExists,
+      format!("Output file {} already exists", output_path),
+    ));
+  }
+  let directory = parent_directory(path);
+  if !directory.is_dir() {
+    if !create_output_directory {
+      return Err(file_error(
+        FileErrorCode::OutputDirectoryMissing,
+        format!("Output directory {} does not exist", directory.display()),
+      ));
+    }
+    std::fs::create_dir_all(directory).map_err(|err| {
+      file_error(
+        FileErrorCode::OutputNotWritable,
+        format!(
+          "Cannot create output directory {} : {}",
+          directory.display(),
+          err
+        ),
+      )
+    })?;
+  }
+  Ok(())
+}
+
+/**
+ * Little-endian samples of `type_of_bin_data`, the integers clamped and rounded like the raw formats
+ * so a resampler overshoot past 1.0 never drops a sample
+ */
+fn samples_to_bytes(samples: &[f32], type_of_bin_data: &DataType) -> Vec<u8> {
+  let format = data_type_format(type_of_bin_data);
+  let mut bytes = Vec::with_capacity(samples.len() * format.bytes_per_sample());
+  for &sample in samples {
+    format.encode(sample, &mut bytes);
+  }
+  bytes
+}
+
+fn data_type_format(type_of_bin_data: &DataType) -> SampleFormat {
+  match type_of_bin_data {
+    DataType::I16 => SampleFormat::Int {
+      bits: 16,
+      big_endian: false,
+    },
+    DataType::F32 => SampleFormat::Float {
+      bits: 32,
+      big_endian: false,
+    },
+  }
+}
+
+/**
+ * `input_format` asking for the container to be found from the magic bytes of the input
+ */
+fn is_auto_format(input_format: Option<&str>) -> bool {
+  input_format.is_some_and(|format| format.eq_ignore_ascii_case("auto"))
+}
+
+/**
+ * Parses an optional raw format descriptor, like `s16le:2:44100`
+ */
+fn parse_raw_format(descriptor: Option<&str>) -> Result<Option<RawFormat>> {
+  descriptor
+    .map(str::parse)
+    .transpose()
+    .map_err(Error::from_reason)
+}
+
+/**
+ * Checks that the channels of a raw `output_format` are the ones of the input
+ */
+fn check_raw_output(output_format: &RawFormat, channels: usize) -> Result<()> {
+  if output_format.channels != channels {
+    return Err(Error::from_reason(format!(
+      "Output format {} has {} channels but the input has {}",
+      output_format, output_format.channels, channels
+    )));
+  }
+  Ok(())
+}
+
+/**
+ * Raw output samples of `output_format`, whose channels must be the ones of the input
+ */
+fn encode_raw_output(
+  samples: &[f32],
+  channels: usize,
+  output_format: &RawFormat,
+) -> Result<Vec<u8>> {
+  check_raw_output(output_format, channels)?;
+  Ok(output_format.encode(samples))
+}
+
+fn flac_bits_per_sample(type_of_bin_data: &DataType) -> u32 {
+  match type_of_bin_data {
+    DataType::I16 => 16,
+    DataType::F32 => 24,
+  }
+}
+
+/**
+ * Container matching the extension of an output path, raw when it is unknown
+ */
+fn output_container_of(output_path: &str) -> OutputContainer {
+  if has_extension(output_path, "flac") {
+    OutputContainer::Flac
+  } else if has_extension(output_path, "opus") {
+    OutputContainer::Opus
+  } else if has_extension(output_path, "aif") || has_extension(output_path, "aiff") {
+    OutputContainer::Aiff
+  } else if has_extension(output_path, "aifc") {
+    OutputContainer::Aifc
+  } else if has_extension(output_path, "wav") {
+    OutputContainer::Wav
+  } else if has_extension(output_path, "rf64") {
+    OutputContainer::Rf64
+  } else if has_extension(output_path, "w64") {
+    OutputContainer::Wave64
+  } else if has_extension(output_path, "au") || has_extension(output_path, "snd") {
+    OutputContainer::Au
+  } else {
+    OutputContainer::Raw
+  }
+}
+
+/**
+ * Encoder of `output_container` writing to `file`, raw samples of `type_of_bin_data` for
+ * `OutputContainer.Raw`
+ */
+fn create_sink<W: Write + Seek + 'static>(
+  file: W,
+  output_container: &OutputContainer,
+  spec: AudioSpec,
+  type_of_bin_data: &DataType,
+  compression_level: Option<u8>,
+  wav_chunks: Vec<WavChunk>,
+) -> std::result::Result<Box<dyn AudioSink>, String> {
+  let type_of_bin_data = *type_of_bin_data;
+  Ok(match output_container {
+    OutputContainer::Raw => Box::new(RawWriter::new(file, move |samples| {
+      samples_to_bytes(samples, &type_of_bin_data)
+    })),
+    OutputContainer::Flac => Box::new(FlacWriter::new(
+      file,
+      spec,
+      compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL),
+    )?),
+    OutputContainer::Opus => opus_sink(file, spec)?,
+    OutputContainer::Aiff | OutputContainer::Aifc => {
+      // AIFF-C is written for little-endian or float samples
+      let format = match (output_container, type_of_bin_data) {
+        (OutputContainer::Aiff, _) => SampleFormat::Int {
+          bits: spec.bits_per_sample,
+          big_endian: true,
+        },
+        (_, DataType::I16) => SampleFormat::Int {
+          bits: 16,
+          big_endian: false,
+        },
+        (_, DataType::F32) => SampleFormat::Float {
+          bits: 32,
+          big_endian: true,
+        },
+      };
+      let header = AiffHeader::new(spec.sample_rate, spec.channels, format)?;
+      Box::new(PcmWriter::new(file, header, format, spec.channels)?)
+    }
+    OutputContainer::Wav | OutputContainer::Rf64 | OutputContainer::Wave64 => {
+      let layout = match output_container {
+        OutputContainer::Rf64 => WavLayout::Rf64,
+        OutputContainer::Wave64 => WavLayout::Wave64,
+        _ => WavLayout::Riff,
+      };
+      let format = match type_of_bin_data {
+        DataType::I16 => SampleFormat::Int {
+          bits: 16,
+          big_endian: false,
+        },
+        DataType::F32 => SampleFormat::Float {
+          bits: 32,
+          big_endian: false,
+        },
+      };
+      let header =
+        WavHeader::new(layout, spec.sample_rate, spec.channels, format)?.with_chunks(wav_chunks);
+      Box::new(PcmWriter::new(file, header, format, spec.channels)?)
+    }
+    OutputContainer::Au => {
+      let format = match type_of_bin_data {
+        DataType::I16 => SampleFormat::Int {
+          bits: 16,
+          big_endian: true,
+        },
+        DataType::F32 => SampleFormat::Float {
+          bits: 32,
+          big_endian: true,
+        },
+      };
+      let header = AuHeader::new(spec.sample_rate, spec.channels, format)?;
+      Box::new(PcmWriter::new(file, header, format, spec.channels)?)
+    }
+  })
+}
+
+#[napi(object)]
+pub struct ReSampledAudioFile {
+  /// Sample rate of the input, found in the header of encoded files
+  pub sample_rate_input: u32,
+  /// Channels of the input, kept in the output
+  pub channels: u32,
+  /// The output was up to date and left untouched, with `WritePolicy.SkipIfUpToDate`
+  pub skipped: bool,
+  /// The files written with `segment_duration`, in order
+  pub segments: Option<Vec<AudioSegment>>,
+  /// The mono file of each channel written with `split_channels`
+  pub channel_paths: Option<Vec<String>>,
+}
+
+#[napi(object)]
+pub struct AudioSegment {
+  pub path: String,
+  /// First frame of the segment in the whole output
+  pub start: i64,
+  pub frames: i64,
+}
+
+/**
+ * Errors about `output_path` have the `code` of a `FileErrorCode`, the other ones `GenericFailure`
+ */
+#[napi]
+pub fn re_sample_audio_file(args: ArgsAudioFile) -> Result<ReSampledAudioFile, FileErrorCode> {
+  let write_policy = args.write_policy.unwrap_or(WritePolicy::Overwrite);
+  let output_sync = args.output_sync.unwrap_or(OutputSync::File);
+  let output_path = primary_output_path(&args);
+  check_output_path(
+    &output_path,
+    write_policy,
+    args.create_output_directory.unwrap_or(false),
+  )?;
+  let input_path = args.input_raw_path.clone();
+  let (mut re_sampled, output_files) = re_sample_file(args, write_policy)
+    .map_err(|err| file_error(FileErrorCode::GenericFailure, err.reason))?;
+  re_sampled.skipped |= commit_outputs(output_files, &input_path, write_policy, output_sync)?;
+  Ok(re_sampled)
+}
+
+/**
+ * Commits the outputs of a file together once all of them are complete. Every path is checked
+ * against the write policy first: none is renamed when one of them exists with `ErrorIfExists`, and
+ * the ones modified after the input are left untouched with `SkipIfUpToDate`. Returns whether every
+ * output was up to date
+ */
+fn commit_outputs(
+  output_files: Vec<OutputFile>,
+  input_path: &str,
+  write_policy: WritePolicy,
+  output_sync: OutputSync,
+) -> Result<bool, FileErrorCode> {
+  let nbr_outputs = output_files.len();
+  let output_files: Vec<OutputFile> = output_files
+    .into_iter()
+    .filter(|output_file| {
+      let up_to_date = matches!(write_policy, WritePolicy::SkipIfUpToDate)
+        && is_up_to_date(output_file.path(), Path::new(input_path));
+      if up_to_date {
+        debug!("{} is up to date, keeping it", output_file.path().display());
+      }
+      !up_to_date
+    })
+    .collect();
+  if matches!(write_policy, WritePolicy::ErrorIfExists) {
+    if let Some(existing) = output_files
+      .iter()
+      .find(|output_file| output_file.path().exists())
+    {
+      return Err(file_error(
+        FileErrorCode::OutputExists,
+        format!("Output file {} already exists", existing.path().display()),
+      ));
+    }
+  }
+  let all_up_to_date = nbr_outputs > 0 && output_files.is_empty();
+  for output_file in output_files {
+    commit_output(output_file, write_policy, output_sync)?;
+  }
+  Ok(all_up_to_date)
+}
+
+/**
+ * Renames a complete output over its path, with the error codes of the file functions
+ */
+fn commit_output(
+  output_file: OutputFile,
+  write_policy: WritePolicy,
+  output_sync: OutputSync,
+) -> Result<(), FileErrorCode> {
+  let output_path = output_file.path().to_path_buf();
+  output_file
+    .commit(
+      !matches!(output_sync, OutputSync::None),
+      matches!(output_sync, OutputSync::Directory),
+    )
+    .map_err(|reason| {
+      // Created by someone else while resampling
+      let code = if matches!(write_policy, WritePolicy::ErrorIfExists) && output_path.exists() {
+        FileErrorCode::OutputExists
+      } else {
+        FileErrorCode::OutputNotWritable
+      };
+      file_error(code, reason)
+    })
+}
+
+/**
+ * An opened input file, with its container or the raw format given for it
+ */
+struct InputFile {
+  source: Box<dyn AudioSource>,
+  format: Option<InputFormat>,
+  raw_format: Option<RawFormat>,
+}
+
+/**
+ * Opens an input file holding raw samples of `input_format`, or a container found from its magic
+ * bytes with `input_format: "auto"` or else from its extension, or raw samples of `raw_fallback`
+ */
+fn open_input_file(
+  path: &str,
+  input_format: Option<&str>,
+  raw_fallback: impl FnOnce() -> Result<RawFormat>,
+) -> Result<InputFile> {
+  let auto_format = is_auto_format(input_format);
+  let raw_format = if auto_format {
+    None
+  } else {
+    parse_raw_format(input_format)?
+  };
+  // An input format descriptor always means raw samples, whatever the extension
+  let format = if auto_format {
+    InputFormat::sniff(path).map_err(Error::from_reason)?
+  } else if raw_format.is_none() {
+    InputFormat::from_path(path)
+  } else {
+    None
+  };
+  let source = match (format, raw_format) {
+    (Some(format), _) => format.open(path),
+    (None, Some(raw)) => raw.open(path),
+    (None, None) => raw_fallback()?.open(path),
+  }
+  .map_err(Error::from_reason)?;
+  Ok(InputFile {
+    source,
+    format,
+    raw_format,
+  })
+}
+
+/**
+ * How the files of the file functions are encoded
+ */
+struct FileOutput {
+  container: OutputContainer,
+  /// Raw samples of this format instead of the ones of `type_of_bin_data`
+  raw_format: Option<RawFormat>,
+  type_of_bin_data: DataType,
+  compression_level: Option<u8>,
+  sample_rate: u32,
+  channels: usize,
+}
+
+impl FileOutput {
+  /**
+   * An encoder writing to a temporary file, renamed to `path` once committed
+   */
+  fn writer(
+    &self,
+    path: &str,
+    overwrite: bool,
+    wav_chunks: Vec<WavChunk>,
+  ) -> std::result::Result<(Box<dyn AudioSink>, OutputFile), String> {
+    // Nothing is written at path until its whole output is resampled
+    let output_file = OutputFile::create(path, overwrite)?;
+    let file = output_file.writer()?;
+    let writer: Box<dyn AudioSink> = match (&self.container, self.raw_format) {
+      (OutputContainer::Raw, Some(raw)) => {
+        Box::new(RawWriter::new(file, move |samples| raw.encode(samples)))
+      }
+      _ => {
+        let spec = AudioSpec {
+          sample_rate: self.sample_rate,
+          channels: self.channels,
+          bits_per_sample: flac_bits_per_sample(&self.type_of_bin_data),
+        };
+        create_sink(
+          file,
+          &self.container,
+          spec,
+          &self.type_of_bin_data,
+          self.compression_level,
+          wav_chunks,
+        )?
+      }
+    };
+    Ok((writer, output_file))
+  }
+}
+
+/**
+ * Format of a raw output: the one of `output_format`, checked against the channels of the output,
+ * or `default_format` samples without `type_of_bin_data`
+ */
+fn raw_output_format(
+  output_format: Option<RawFormat>,
+  type_of_bin_data: Option<&DataType>,
+  default_format: SampleFormat,
+  channels: usize,
+  sample_rate: u32,
+) -> Result<Option<RawFormat>> {
+  match (output_format, type_of_bin_data) {
+    (Some(raw), _) => {
+      check_raw_output(&raw, channels)?;
+      Ok(Some(raw))
+    }
+    (None, None) => Ok(Some(RawFormat {
+      format: default_format,
+      channels,
+      sample_rate,
+    })),
+    (None, Some(_)) => Ok(None),
+  }
+}
+
+/**
+ * Resamples the file into temporary outputs left to commit, one per channel with `split_channels`
+ * or per segment with `segment_duration`, or none when the output is up to date
+ */
+fn re_sample_file(
+  args: ArgsAudioFile,
+  write_policy: WritePolicy,
+) -> Result<(ReSampledAudioFile, Vec<OutputFile>)> {
   let ArgsAudioFile {
     input_raw_path,
     output_path,
     args_audio_to_re_sample,
     type_of_bin_data,
+    input_format,
+    output_format,
+    output_container,
+    compression_level,
+    start,
+    end,
+    range_unit,
+    segment_duration,
+    segment_overlap,
+    segment_at_zero_crossing,
+    split_channels,
+    ..
   } = args;
-  let ArgsAudioToReSample {
-    channels,
-    sample_rate_input,
+  let split_channels = split_channels.unwrap_or(false);
+  if split_channels && segment_duration.is_some() {
+    return Err(Error::from_reason(
+      "splitChannels and segmentDuration cannot be used together",
+    ));
+  }
+  let output_raw_format = parse_raw_format(output_format.as_deref())?;
+  let sample_rate_output = output_raw_format
+    .map(|raw| raw.sample_rate)
+    .or(
+      args_audio_to_re_sample
+        .as_ref()
+        .map(|args| args.sample_rate_output as u32),
+    )
+    .ok_or_else(|| {
+      Error::from_reason("The output sample rate needs outputFormat or argsAudioToReSample")
+    })?;
+  let output_container = output_container.unwrap_or_else(|| output_container_of(&output_path));
+  if output_raw_format.is_some() && !matches!(output_container, OutputContainer::Raw) {
+    return Err(Error::from_reason("outputFormat only describes raw output"));
+  }
+  let conversion_time = Instant::now();
+  let InputFile {
+    mut source,
+    format: detected_format,
+    raw_format: input_raw_format,
+  } = open_input_file(&input_raw_path, input_format.as_deref(), || {
+    match (&args_audio_to_re_sample, &type_of_bin_data) {
+      (Some(args), Some(type_of_bin_data)) => Ok(RawFormat {
+        format: data_type_format(type_of_bin_data),
+        channels: args.channels as usize,
+        sample_rate: args.sample_rate_input as u32,
+      }),
+      _ => Err(Error::from_reason(
+        "Raw input needs inputFormat or argsAudioToReSample and typeOfBinData",
+      )),
+    }
+  })?;
+  let input_spec = source.spec();
+  let range = frame_range(start, end, range_unit, input_spec.sample_rate)?;
+  // The paths of the segments are only known once written, they are checked before their commit
+  let known_paths: Vec<String> = if segment_duration.is_some() {
+    Vec::new()
+  } else if split_channels {
+    (0..input_spec.channels)
+      .map(|channel| channel_path(&output_path, channel))
+      .collect()
+  } else {
+    vec![output_path.clone()]
+  };
+  if matches!(write_policy, WritePolicy::SkipIfUpToDate)
+    && !known_paths.is_empty()
+    && known_paths
+      .iter()
+      .all(|path| is_up_to_date(Path::new(path), Path::new(&input_raw_path)))
+  {
+    debug!("{} is up to date, skipping it", output_path);
+    return Ok((
+      ReSampledAudioFile {
+        sample_rate_input: input_spec.sample_rate,
+        channels: input_spec.channels as u32,
+        skipped: true,
+        segments: None,
+        channel_paths: None,
+      },
+      Vec::new(),
+    ));
+  }
+  // The metadata of a WAV input (INFO tags, Broadcast WAV origination, iXML...) follows it into a
+  // WAV output
+  let wav_chunks = match (detected_format, &output_container) {
+    (
+      Some(InputFormat::Wav),
+      OutputContainer::Wav | OutputContainer::Rf64 | OutputContainer::Wave64,
+    ) => {
+      let file = File::open(&input_raw_path)
+        .map_err(|err| Error::from_reason(format!("Cannot open input file : {}", err)))?;
+      let mut chunks = wav_metadata(BufReader::new(file)).map_err(Error::from_reason)?;
+      if range != FrameRange::default() {
+        cut_wav_metadata(&mut chunks, range.start);
+      }
+      rescale_wav_metadata(&mut chunks, input_spec.sample_rate, sample_rate_output);
+      chunks
+    }
+    _ => Vec::new(),
+  };
+  // Channels of each output file
+  let output_channels = if split_channels {
+    1
+  } else {
+    input_spec.channels
+  };
+  if let Some(raw) = output_raw_format.filter(|raw| split_channels && raw.channels != 1) {
+    return Err(Error::from_reason(format!(
+      "Output format {} of split channels must have 1 channel",
+      raw
+    )));
+  }
+  // Raw output keeps the sample format of a raw input described by inputFormat
+  let raw_format = raw_output_format(
+    output_raw_format,
+    type_of_bin_data.as_ref(),
+    input_raw_format.map_or(data_type_format(&DataType::F32), |raw| raw.format),
+    output_channels,
     sample_rate_output,
-  } = args_audio_to_re_sample;
-  let file_in_disk = File::open(input_raw_path).expect("Can't open file");
-  let mut file_in_reader = BufReader::new(file_in_disk);
+  )?;
+  let overwrite = !matches!(write_policy, WritePolicy::ErrorIfExists);
+  let file_output = FileOutput {
+    container: output_container,
+    raw_format,
+    type_of_bin_data: type_of_bin_data.unwrap_or(DataType::F32),
+    compression_level,
+    sample_rate: sample_rate_output,
+    channels: output_channels,
+  };
+  let create_writer =
+    |path: &str, wav_chunks: Vec<WavChunk>| file_output.writer(path, overwrite, wav_chunks);
+  // Every block is written as soon as it is resampled, the memory used does not grow with the file
+  let (segments, channel_paths, output_files) = match segment_duration {
+    Some(segment_duration) => {
+      let layout = segment_layout(
+        segment_duration,
+        segment_overlap,
+        segment_at_zero_crossing.unwrap_or(false),
+        sample_rate_output,
+      )?;
+      // Committed together once every segment is written, like the split channels
+      let mut output_files = Vec::new();
+      let mut writer = SegmentSink::new(input_spec.channels, layout, |segment: &Segment| {
+        let mut chunks = wav_chunks.clone();
+        cut_wav_metadata(&mut chunks, segment.start);
+        let (writer, output_file) =
+          create_writer(&segment_path(&output_path, segment.index), chunks)?;
+        output_files.push(output_file);
+        Ok(writer)
+      });
+      re_sample_source_into_sink(source.as_mut(), sample_rate_output, range, &mut writer)
+        .map_err(Error::from_reason)?;
+      let segments = writer
+        .segments()
+        .iter()
+        .map(|segment| AudioSegment {
+          path: segment_path(&output_path, segment.index),
+          start: segment.start as i64,
+          frames: segment.frames as i64,
+        })
+        .collect();
+      drop(writer);
+      (Some(segments), None, output_files)
+    }
+    None if split_channels => {
+      let channel_paths: Vec<String> = (0..input_spec.channels)
+        .map(|channel| channel_path(&output_path, channel))
+        .collect();
+      let (writers, output_files) = channel_paths
+        .iter()
+        .map(|path| create_writer(path, wav_chunks.clone()))
+        .collect::<std::result::Result<(Vec<_>, Vec<_>), String>>()
+        .map_err(Error::from_reason)?;
+      let mut writer = ChannelSplitSink::new(writers);
+      re_sample_source_into_sink(source.as_mut(), sample_rate_output, range, &mut writer)
+        .map_err(Error::from_reason)?;
+      (None, Some(channel_paths), output_files)
+    }
+    None => {
+      let (mut writer, output_file) =
+        create_writer(&output_path, wav_chunks).map_err(Error::from_reason)?;
+      re_sample_source_into_sink(source.as_mut(), sample_rate_output, range, writer.as_mut())
+        .map_err(Error::from_reason)?;
+      (None, None, vec![output_file])
+    }
+  };
+  debug!(
+    "Time to convert the file was {:?}",
+    conversion_time.elapsed()
+  );
+  Ok((
+    ReSampledAudioFile {
+      sample_rate_input: input_spec.sample_rate,
+      channels: input_spec.channels as u32,
+      skipped: false,
+      segments,
+      channel_paths,
+    },
+    output_files,
+  ))
+}
 
-  // Depending of sub-data-type we can use i16 or f32
-  let indata: Vec<Vec<f32>> = match type_of_bin_data {
-    DataType::I16 => i16_buffer_to_vecs(&mut file_in_reader, channels as usize),
-    DataType::F32 => f32_buffer_to_vecs(&mut file_in_reader, channels as usize),
+#[napi(object)]
+pub struct ArgsAudioFileBatch {
+  pub jobs: Vec<ArgsAudioFile>,
+  /// Worker threads running the jobs, one per CPU by default
+  pub threads: Option<u32>,
+}
+
+#[napi(object)]
+pub struct AudioFileJobResult {
+  pub success: bool,
+  /// What `reSampleAudioFile` returns for the job, when it succeeded
+  pub result: Option<ReSampledAudioFile>,
+  /// `code` of the error of a failed job
+  pub error_code: Option<FileErrorCode>,
+  /// Message of the error of a failed job
+  pub error: Option<String>,
+  /// Milliseconds spent on the job
+  pub duration_ms: f64,
+}
+
+/**
+ * Runs `reSampleAudioFile` for every job across native worker threads and returns the result of
+ * each job, in order. A failed job does not stop the other ones
+ */
+#[napi]
+pub fn re_sample_audio_files(args: ArgsAudioFileBatch) -> Vec<AudioFileJobResult> {
+  run_file_jobs(args.jobs, args.threads)
+}
+
+fn run_file_jobs(jobs: Vec<ArgsAudioFile>, threads: Option<u32>) -> Vec<AudioFileJobResult> {
+  let threads = threads.map_or_else(default_threads, |threads| threads as usize);
+  let batch_time = Instant::now();
+  let nbr_jobs = jobs.len();
+  let results = run_jobs(jobs, threads, |job| {
+    re_sample_audio_file(job).map_err(|err| (err.status, err.reason))
+  });
+  debug!(
+    "Time to run {} jobs on {} threads was {:?}",
+    nbr_jobs,
+    threads,
+    batch_time.elapsed()
+  );
+  results
+    .into_iter()
+    .map(|(result, duration)| {
+      let duration = duration.as_secs_f64() * 1000.0;
+      // A panicking job has no error code
+      let result = result.unwrap_or_else(|reason| Err((FileErrorCode::GenericFailure, reason)));
+      match result {
+        Ok(result) => AudioFileJobResult {
+          success: true,
+          result: Some(result),
+          error_code: None,
+          error: None,
+          duration_ms: duration,
+        },
+        Err((code, reason)) => AudioFileJobResult {
+          success: false,
+          result: None,
+          error_code: Some(code),
+          error: Some(reason),
+          duration_ms: duration,
+        },
+      }
+    })
+    .collect()
+}
+
+#[napi(object)]
+pub struct ArgsAudioDirectory {
+  pub input_directory: String,
+  /// Directory of the outputs, mirroring the paths of the inputs under `input_directory`
+  pub output_directory: String,
+  /// Globs selecting the inputs by their path relative to `input_directory`, like `**/*.wav`,
+  /// `*` not crossing `/`
+  pub include: Option<Vec<String>>,
+  /// Extensions selecting the inputs, like `wav` or `.flac`, ignoring case. Without `include` nor
+  /// `extensions`, every file of a known container is selected, or every file with `input_format`
+  pub extensions: Option<Vec<String>>,
+  /// Extension replacing the one of the inputs in the output paths, like `flac`
+  pub output_extension: Option<String>,
+  pub args_audio_to_re_sample: Option<ArgsAudioToReSample>,
+  pub type_of_bin_data: Option<DataType>,
+  /// Raw inputs as `<format>:<channels>:<rate>`, or `auto` to find the container of each input from its magic bytes
+  pub input_format: Option<String>,
+  /// Raw outputs as `<format>:<channels>:<rate>`, the rate being the one to resample to
+  pub output_format: Option<String>,
+  pub output_container: Option<OutputContainer>,
+  /// FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default
+  pub compression_level: Option<u8>,
+  /// How each output is flushed to the disk before and after it replaces its path, `File` by default
+  pub output_sync: Option<OutputSync>,
+  /// What happens to the outputs that already exist, `SkipIfUpToDate` by default
+  pub write_policy: Option<WritePolicy>,
+  /// Worker threads converting the files, one per CPU by default
+  pub threads: Option<u32>,
+}
+
+#[napi(object)]
+pub struct DirectoryFileResult {
+  pub input_path: String,
+  pub output_path: String,
+  pub result: AudioFileJobResult,
+}
+
+#[napi(object)]
+pub struct ReSampledDirectory {
+  /// Every selected input, sorted by path, and the entries that could not be listed as failed ones
+  pub files: Vec<DirectoryFileResult>,
+  pub converted: u32,
+  /// Outputs left untouched because they were up to date
+  pub skipped: u32,
+  pub failed: u32,
+}
+
+/**
+ * Resamples the files of a directory selected by globs or extensions to the same paths under an
+ * output directory, across native worker threads, skipping by default the outputs modified after
+ * their input. A failed file does not stop the other ones
+ */
+#[napi]
+pub fn re_sample_directory(args: ArgsAudioDirectory) -> Result<ReSampledDirectory> {
+  let ArgsAudioDirectory {
+    input_directory,
+    output_directory,
+    include,
+    extensions,
+    output_extension,
+    args_audio_to_re_sample,
+    type_of_bin_data,
+    input_format,
+    output_format,
+    output_container,
+    compression_level,
+    output_sync,
+    write_policy,
+    threads,
+  } = args;
+  let input_root = Path::new(&input_directory);
+  if !input_root.is_dir() {
+    return Err(Error::from_reason(format!(
+      "Input directory {} does not exist",
+      input_directory
+    )));
+  }
+  let output_root = Path::new(&output_directory);
+  let filter =
+    FileFilter::new(include.as_deref(), extensions.as_deref()).map_err(Error::from_reason)?;
+  let select = |relative: &Path| {
+    if filter.is_empty() && input_format.is_none() {
+      InputFormat::from_path(&relative.to_string_lossy()).is_some()
+    } else {
+      filter.matches(relative)
+    }
   };
+  let found = find_files(input_root, Some(output_root), select);
+  let output_path = |relative: &Path| {
+    mirror_path(output_root, relative, output_extension.as_deref())
+      .to_string_lossy()
+      .into_owned()
+  };
+  let jobs: Vec<ArgsAudioFile> = found
+    .iter()
+    .flatten()
+    .map(|relative| ArgsAudioFile {
+      args_audio_to_re_sample: args_audio_to_re_sample
+        .as_ref()
+        .map(|args| ArgsAudioToReSample { ..*args }),
+      input_raw_path: input_root.join(relative).to_string_lossy().into_owned(),
+      output_path: output_path(relative),
+      type_of_bin_data,
+      input_format: input_format.clone(),
+      output_format: output_format.clone(),
+      output_container,
+      compression_level,
+      output_sync,
+      write_policy: Some(write_policy.unwrap_or(WritePolicy::SkipIfUpToDate)),
+      create_output_directory: Some(true),
+      start: None,
+      end: None,
+      range_unit: None,
+      segment_duration: None,
+      segment_overlap: None,
+      segment_at_zero_crossing: None,
+      split_channels: None,
+    })
+    .collect();
+  let mut results = run_file_jobs(jobs, threads).into_iter();
+  let files: Vec<DirectoryFileResult> = found
+    .into_iter()
+    .map(|found| match found {
+      Ok(relative) => DirectoryFileResult {
+        input_path: input_root.join(&relative).to_string_lossy().into_owned(),
+        output_path: output_path(&relative),
+        result: results.next().unwrap(),
+      },
+      // An entry that cannot be listed fails alone, like a file that cannot be converted
+      Err((path, reason)) => DirectoryFileResult {
+        input_path: path.to_string_lossy().into_owned(),
+        output_path: output_path(path.strip_prefix(input_root).unwrap_or(&path)),
+        result: AudioFileJobResult {
+          success: false,
+          result: None,
+          error_code: Some(FileErrorCode::GenericFailure),
+          error: Some(reason),
+          duration_ms: 0.0,
+        },
+      },
+    })
+    .collect();
+  let count = |selected: fn(&AudioFileJobResult) -> bool| {
+    files.iter().filter(|file| selected(&file.result)).count() as u32
+  };
+  let skipped = count(|result| result.result.as_ref().is_some_and(|result| result.skipped));
+  let failed = count(|result| !result.success);
+  let converted = files.len() as u32 - skipped - failed;
+  debug!(
+    "Converted {} files of {}, {} up to date, {} failed",
+    converted, input_directory, skipped, failed
+  );
+  Ok(ReSampledDirectory {
+    files,
+    converted,
+    skipped,
+    failed,
+  })
+}
 
-  let start = Instant::now();
-  let re_sampled_f32_data = re_sample_audio_buffer(
-    indata,
-    sample_rate_input,
+#[napi(object)]
+pub struct AudioFileInput {
+  pub path: String,
+  /// Raw input as `<format>:<channels>:<rate>`, or `auto` to find the container from the magic
+  /// bytes, the container of the extension by default
+  pub input_format: Option<String>,
+}
+
+#[napi(object)]
+pub struct ArgsInterleaveAudioFiles {
+  /// Inputs of the channels of the output, in order, each one adding its channels
+  pub inputs: Vec<AudioFileInput>,
+  pub output_path: String,
+  /// Sample rate every input is resampled to, the one of `output_format` by default
+  pub sample_rate_output: Option<u32>,
+  pub type_of_bin_data: Option<DataType>,
+  /// Raw output as `<format>:<channels>:<rate>`, with the channels of all the inputs
+  pub output_format: Option<String>,
+  pub output_container: Option<OutputContainer>,
+  /// FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default
+  pub compression_level: Option<u8>,
+  /// How the output is flushed to the disk before and after it replaces `output_path`, `File` by default
+  pub output_sync: Option<OutputSync>,
+  /// Creates the missing directories of `output_path` instead of throwing `OutputDirectoryMissing`
+  pub create_output_directory: Option<bool>,
+}
+
+#[napi(object)]
+pub struct InterleavedAudioFile {
+  pub channels: u32,
+  /// Frames of the output, the ones of the longest input once resampled
+  pub frames: i64,
+}
+
+/**
+ * Resamples inputs of any rate, like the mono legs of a call, to a common rate and writes them as
+ * the channels of one output, the shorter ones padded with silence. The inputs are streamed side by
+ * side, errors have the `code` of a `FileErrorCode`
+ */
+#[napi]
+pub fn interleave_audio_files(
+  args: ArgsInterleaveAudioFiles,
+) -> Result<InterleavedAudioFile, FileErrorCode> {
+  let output_sync = args.output_sync.unwrap_or(OutputSync::File);
+  check_output_path(
+    &args.output_path,
+    WritePolicy::Overwrite,
+    args.create_output_directory.unwrap_or(false),
+  )?;
+  let (interleaved, output_file) =
+    interleave_files(args).map_err(|err| file_error(FileErrorCode::GenericFailure, err.reason))?;
+  commit_output(output_file, WritePolicy::Overwrite, output_sync)?;
+  Ok(interleaved)
+}
+
+/**
+ * Raw format, sample rate and container of the output of the functions joining several inputs
+ */
+fn joined_output_format(
+  output_path: &str,
+  output_format: Option<&str>,
+  output_container: Option<OutputContainer>,
+  sample_rate_output: Option<u32>,
+) -> Result<(Option<RawFormat>, u32, OutputContainer)> {
+  let output_raw_format = parse_raw_format(output_format)?;
+  let sample_rate_output = output_raw_format
+    .map(|raw| raw.sample_rate)
+    .or(sample_rate_output)
+    .ok_or_else(|| {
+      Error::from_reason("The output sample rate needs outputFormat or sampleRateOutput")
+    })?;
+  let output_container = output_container.unwrap_or_else(|| output_container_of(output_path));
+  if output_raw_format.is_some() && !matches!(output_container, OutputContainer::Raw) {
+    return Err(Error::from_reason("outputFormat only describes raw output"));
+  }
+  Ok((output_raw_format, sample_rate_output, output_container))
+}
+
+/**
+ * Opens every input, resampled to `sample_rate_output` as it is read
+ */
+fn open_resampled_inputs(
+  inputs: &[AudioFileInput],
+  sample_rate_output: u32,
+) -> Result<Vec<Box<dyn AudioSource>>> {
+  inputs
+    .iter()
+    .map(|input| {
+      let InputFile { source, .. } =
+        open_input_file(&input.path, input.input_format.as_deref(), || {
+          Err(Error::from_reason(format!(
+            "Raw input {} needs inputFormat",
+            input.path
+          )))
+        })?;
+      let source = ResampledSource::new(source, sample_rate_output).map_err(Error::from_reason)?;
+      Ok(Box::new(source) as Box<dyn AudioSource>)
+    })
+    .collect()
+}
+
+fn interleave_files(args: ArgsInterleaveAudioFiles) -> Result<(InterleavedAudioFile, OutputFile)> {
+  let ArgsInterleaveAudioFiles {
+    inputs,
+    output_path,
     sample_rate_output,
+    type_of_bin_data,
+    output_format,
+    output_container,
+    compression_level,
+    ..
+  } = args;
+  if inputs.is_empty() {
+    return Err(Error::from_reason("Cannot interleave without inputs"));
+  }
+  let (output_raw_format, sample_rate_output, output_container) = joined_output_format(
+    &output_path,
+    output_format.as_deref(),
+    output_container,
+    sample_rate_output,
+  )?;
+  let conversion_time = Instant::now();
+  let mut sources = open_resampled_inputs(&inputs, sample_rate_output)?;
+  let channels = sources.iter().map(|source| source.spec().channels).sum();
+  let file_output = FileOutput {
+    container: output_container,
+    raw_format: raw_output_format(
+      output_raw_format,
+      type_of_bin_data.as_ref(),
+      data_type_format(&DataType::F32),
+      channels,
+      sample_rate_output,
+    )?,
+    type_of_bin_data: type_of_bin_data.unwrap_or(DataType::F32),
+    compression_level,
+    sample_rate: sample_rate_output,
     channels,
-    channels,
+  };
+  let (mut writer, output_file) = file_output
+    .writer(&output_path, true, Vec::new())
+    .map_err(Error::from_reason)?;
+  let frames = interleave_sources(&mut sources, writer.as_mut()).map_err(Error::from_reason)?;
+  debug!(
+    "Time to interleave {} inputs was {:?}",
+    inputs.len(),
+    conversion_time.elapsed()
   );
+  Ok((
+    InterleavedAudioFile {
+      channels: channels as u32,
+      frames: frames as i64,
+    },
+    output_file,
+  ))
+}
 
-  let resample_final_data: Vec<u8> = match type_of_bin_data {
-    DataType::I16 => re_sampled_f32_data
-      .iter()
-      .filter_map(|&f32_value| i16::from_f32(f32_value * f32::from_i16(i16::MAX).unwrap())) // if datatype on entry file was int16 we need to retransform to it
-      .flat_map(|i| i.to_le_bytes())
-      .collect(),
-    DataType::F32 => re_sampled_f32_data
-      .iter()
-      .flat_map(|&f| f.to_le_bytes())
-      .collect(),
-  };
+#[napi(object)]
+pub struct ArgsConcatAudioFiles {
+  /// Inputs to join, in order
+  pub inputs: Vec<AudioFileInput>,
+  pub output_path: String,
+  /// Sample rate every input is resampled to, the one of `output_format` by default
+  pub sample_rate_output: Option<u32>,
+  /// Channels of the output, the ones of `output_format` or else of the first input by default
+  pub channels: Option<u32>,
+  /// Seconds of silence between two inputs
+  pub silence: Option<f64>,
+  /// Seconds over which an input fades out while the next one fades in, instead of `silence`
+  pub crossfade: Option<f64>,
+  pub type_of_bin_data: Option<DataType>,
+  /// Raw output as `<format>:<channels>:<rate>`
+  pub output_format: Option<String>,
+  pub output_container: Option<OutputContainer>,
+  /// FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default
+  pub compression_level: Option<u8>,
+  /// How the output is flushed to the disk before and after it replaces `output_path`, `File` by default
+  pub output_sync: Option<OutputSync>,
+  /// Creates the missing directories of `output_path` instead of throwing `OutputDirectoryMissing`
+  pub create_output_directory: Option<bool>,
+}
+
+#[napi(object)]
+pub struct ConcatenatedAudioFile {
+  pub channels: u32,
+  pub frames: i64,
+  /// Output frame where each input starts
+  pub starts: Vec<i64>,
+}
 
-  write_frames_to_disk(resample_final_data, output_path);
-  debug!("Time to convert the file was {:?}", start.elapsed());
-  JsUndefined::value_type();
+/**
+ * Resamples inputs of any rate and channels, like the clips of a playlist, to a common rate and
+ * channel layout and writes them one after the other to one output, with silence or a crossfade
+ * between them. Mono inputs are copied to every channel and inputs down to mono are averaged.
+ * Errors have the `code` of a `FileErrorCode`
+ */
+#[napi]
+pub fn concat_audio_files(
+  args: ArgsConcatAudioFiles,
+) -> Result<ConcatenatedAudioFile, FileErrorCode> {
+  let output_sync = args.output_sync.unwrap_or(OutputSync::File);
+  check_output_path(
+    &args.output_path,
+    WritePolicy::Overwrite,
+    args.create_output_directory.unwrap_or(false),
+  )?;
+  let (concatenated, output_file) =
+    concat_files(args).map_err(|err| file_error(FileErrorCode::GenericFailure, err.reason))?;
+  commit_output(output_file, WritePolicy::Overwrite, output_sync)?;
+  Ok(concatenated)
+}
+
+fn concat_files(args: ArgsConcatAudioFiles) -> Result<(ConcatenatedAudioFile, OutputFile)> {
+  let ArgsConcatAudioFiles {
+    inputs,
+    output_path,
+    sample_rate_output,
+    channels,
+    silence,
+    crossfade,
+    type_of_bin_data,
+    output_format,
+    output_container,
+    compression_level,
+    ..
+  } = args;
+  if inputs.is_empty() {
+    return Err(Error::from_reason("Cannot concatenate without inputs"));
+  }
+  let (output_raw_format, sample_rate_output, output_container) = joined_output_format(
+    &output_path,
+    output_format.as_deref(),
+    output_container,
+    sample_rate_output,
+  )?;
+  let to_frames = |seconds: f64| {
+    if !seconds.is_finite() || seconds < 0.0 {
+      return Err(Error::from_reason(format!(
+        "Invalid duration {} between inputs, it must be a positive number",
+        seconds
+      )));
+    }
+    Ok((seconds * sample_rate_output as f64).round() as u64)
+  };
+  let join = match (silence, crossfade) {
+    (Some(_), Some(_)) => {
+      return Err(Error::from_reason(
+        "silence and crossfade cannot be used together",
+      ))
+    }
+    (_, Some(crossfade)) => Join::Crossfade(to_frames(crossfade)?),
+    (silence, None) => Join::Silence(to_frames(silence.unwrap_or(0.0))?),
+  };
+  let conversion_time = Instant::now();
+  let mut sources = open_resampled_inputs(&inputs, sample_rate_output)?;
+  let channels = match (channels, output_raw_format) {
+    (Some(channels), _) => channels as usize,
+    (None, Some(raw)) => raw.channels,
+    (None, None) => sources[0].spec().channels,
+  };
+  if channels == 0 {
+    return Err(Error::from_reason("The output needs at least 1 channel"));
+  }
+  let file_output = FileOutput {
+    container: output_container,
+    raw_format: raw_output_format(
+      output_raw_format,
+      type_of_bin_data.as_ref(),
+      data_type_format(&DataType::F32),
+      channels,
+      sample_rate_output,
+    )?,
+    type_of_bin_data: type_of_bin_data.unwrap_or(DataType::F32),
+    compression_level,
+    sample_rate: sample_rate_output,
+    channels,
+  };
+  let (mut writer, output_file) = file_output
+    .writer(&output_path, true, Vec::new())
+    .map_err(Error::from_reason)?;
+  let (starts, frames) =
+    concat_sources(&mut sources, channels, join, writer.as_mut()).map_err(Error::from_reason)?;
+  debug!(
+    "Time to concatenate {} inputs was {:?}",
+    inputs.len(),
+    conversion_time.elapsed()
+  );
+  Ok((
+    ConcatenatedAudioFile {
+      channels: channels as u32,
+      frames: frames as i64,
+      starts: starts.into_iter().map(|start| start as i64).collect(),
+    },
+    output_file,
+  ))
 }
+
 #[napi(object)]
 pub struct ArgsAudioBuffer {
   pub args_audio_to_re_sample: ArgsAudioToReSample,
   pub input_buffer: Buffer,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
 }
 
 #[napi]
-pub fn re_sample_buffers(args: ArgsAudioBuffer) -> Buffer {
+pub fn re_sample_buffers(args: ArgsAudioBuffer) -> Result<Buffer> {
   let ArgsAudioBuffer {
     args_audio_to_re_sample,
     input_buffer,
+    start,
+    end,
+    range_unit,
   } = args;
   let ArgsAudioToReSample {
     channels,
@@ -134,30 +1525,411 @@ pub fn re_sample_buffers(args: ArgsAudioBuffer) -> Buffer {
     data[0].len(),
   );
 
-  let output_data = re_sample_audio_buffer(
-    data,
-    sample_rate_input,
-    sample_rate_output,
-    channels,
-    channels,
-  );
+  let range = frame_range(start, end, range_unit, sample_rate_input as u32)?;
+  let output_data =
+    re_sample_planar_range(data, sample_rate_input, sample_rate_output, channels, range)?;
 
   let mut result: Vec<u8> = Vec::new();
   result.extend(output_data.iter().flat_map(|&f| f.to_le_bytes()));
-  result.into()
+  Ok(result.into())
+}
+
+#[napi(object)]
+pub struct ArgsEncodedAudioBuffer {
+  /// A whole FLAC, Ogg Vorbis, Ogg Opus, MP3, WAV / RF64 / Wave64, AIFF / AIFF-C, AU or CAF file
+  pub input_buffer: Buffer,
+  pub sample_rate_output: Option<u32>,
+  pub type_of_bin_data: Option<DataType>,
+  /// Raw output as `<format>:<channels>:<rate>`, instead of `sample_rate_output` and `type_of_bin_data`
+  pub output_format: Option<String>,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
+}
+
+#[napi(object)]
+pub struct ReSampledEncodedAudio {
+  /// Interleaved raw samples of `output_format` or `type_of_bin_data`
+  pub buffer: Buffer,
+  /// Sample rate found in the header of the input
+  pub sample_rate_input: u32,
+  /// Channels found in the header of the input, kept in the output
+  pub channels: u32,
+}
+
+/**
+ * Decodes a file held in memory, of any container found from its first bytes, and returns its
+ * resampled raw samples
+ */
+#[napi]
+pub fn re_sample_encoded_buffer(args: ArgsEncodedAudioBuffer) -> Result<ReSampledEncodedAudio> {
+  let ArgsEncodedAudioBuffer {
+    input_buffer,
+    sample_rate_output,
+    type_of_bin_data,
+    output_format,
+    start,
+    end,
+    range_unit,
+  } = args;
+  let output_raw_format = parse_raw_format(output_format.as_deref())?;
+  let sample_rate_output = output_raw_format
+    .map(|raw| raw.sample_rate)
+    .or(sample_rate_output)
+    .ok_or_else(|| {
+      Error::from_reason("The output sample rate needs outputFormat or sampleRateOutput")
+    })?;
+  let input_format = InputFormat::from_magic(&input_buffer).ok_or_else(|| {
+    Error::from_reason("Input buffer is not a FLAC, Ogg, MP3, WAV, AIFF, AU or CAF file")
+  })?;
+  let mut source = input_format
+    .decoder(Box::new(Cursor::new(input_buffer.to_vec())))
+    .map_err(Error::from_reason)?;
+  let spec = source.spec();
+  let range = frame_range(start, end, range_unit, spec.sample_rate)?;
+  let output_data = re_sample_audio_source(source.as_mut(), sample_rate_output, range)
+    .map_err(Error::from_reason)?;
+  let buffer = match output_raw_format {
+    Some(raw) => encode_raw_output(&output_data, spec.channels, &raw)?,
+    None => samples_to_bytes(&output_data, &type_of_bin_data.unwrap_or(DataType::F32)),
+  };
+
+  Ok(ReSampledEncodedAudio {
+    buffer: buffer.into(),
+    sample_rate_input: spec.sample_rate,
+    channels: spec.channels as u32,
+  })
+}
+
+#[napi(object)]
+pub struct ArgsAudioBufferToFlac {
+  pub args_audio_to_re_sample: ArgsAudioToReSample,
+  pub input_buffer: Buffer,
+  pub type_of_bin_data: DataType,
+  /// From 0 (fastest) to 8 (smallest), 5 by default
+  pub compression_level: Option<u8>,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
+}
+
+/**
+ * Resamples raw samples of `type_of_bin_data` and returns them as a FLAC file
+ */
+#[napi]
+pub fn re_sample_buffer_to_flac(args: ArgsAudioBufferToFlac) -> Result<Buffer> {
+  let ArgsAudioBufferToFlac {
+    args_audio_to_re_sample,
+    input_buffer,
+    type_of_bin_data,
+    compression_level,
+    start,
+    end,
+    range_unit,
+  } = args;
+  let ArgsAudioToReSample {
+    channels,
+    sample_rate_input,
+    sample_rate_output,
+  } = args_audio_to_re_sample;
+  let mut read_buffer = Cursor::new(&input_buffer);
+  let data = match type_of_bin_data {
+    DataType::I16 => i16_buffer_to_vecs(&mut read_buffer, channels as usize),
+    DataType::F32 => f32_buffer_to_vecs(&mut read_buffer, channels as usize),
+  };
+
+  let range = frame_range(start, end, range_unit, sample_rate_input as u32)?;
+  let output_data =
+    re_sample_planar_range(data, sample_rate_input, sample_rate_output, channels, range)?;
+
+  let spec = AudioSpec {
+    sample_rate: sample_rate_output as u32,
+    channels: channels as usize,
+    bits_per_sample: flac_bits_per_sample(&type_of_bin_data),
+  };
+  let flac = encode_flac(
+    &output_data,
+    spec,
+    compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL),
+  )
+  .map_err(Error::from_reason)?;
+  Ok(flac.into())
+}
+
+#[napi(object)]
+pub struct ArgsRawAudioBuffer {
+  pub input_buffer: Buffer,
+  /// `<format>:<channels>:<rate>` of the input, like `s16le:2:44100`, or `auto` for an encoded file
+  pub input_format: String,
+  /// `<format>:<channels>:<rate>` of the output, with the channels of the input
+  pub output_format: String,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
+}
+
+/**
+ * Resamples raw samples described by format descriptors, like `s16le:2:44100` to `f32le:2:16000`.
+ * With an `input_format` of `auto` the input is any file found by `probe_audio`
+ */
+#[napi]
+pub fn re_sample_raw_buffer(args: ArgsRawAudioBuffer) -> Result<Buffer> {
+  let ArgsRawAudioBuffer {
+    input_buffer,
+    input_format,
+    output_format,
+    start,
+    end,
+    range_unit,
+  } = args;
+  let output_format: RawFormat = output_format.parse().map_err(Error::from_reason)?;
+  let (_, mut source) = buffer_source(&input_buffer, &input_format)?;
+  let spec = source.spec();
+  let range = frame_range(start, end, range_unit, spec.sample_rate)?;
+  let output_data = re_sample_audio_source(source.as_mut(), output_format.sample_rate, range)
+    .map_err(Error::from_reason)?;
+  Ok(encode_raw_output(&output_data, spec.channels, &output_format)?.into())
+}
+
+/**
+ * Decoder of an in-memory file found from its magic bytes when `input_format` is `auto`, of raw
+ * samples otherwise
+ */
+fn buffer_source<'a>(
+  input_buffer: &'a Buffer,
+  input_format: &str,
+) -> Result<(Option<InputFormat>, Box<dyn AudioSource + 'a>)> {
+  if is_auto_format(Some(input_format)) {
+    let format = InputFormat::from_magic(input_buffer).ok_or_else(|| {
+      Error::from_reason("Input buffer has no known container, its inputFormat must be given")
+    })?;
+    let source = format
+      .decoder(Box::new(Cursor::new(input_buffer.to_vec())))
+      .map_err(Error::from_reason)?;
+    Ok((Some(format), source))
+  } else {
+    let input_format: RawFormat = input_format.parse().map_err(Error::from_reason)?;
+    let source = input_format
+      .source(Cursor::new(&input_buffer[..]))
+      .map_err(Error::from_reason)?;
+    Ok((None, Box::new(source)))
+  }
+}
+
+#[napi(object)]
+pub struct ArgsContainerBuffer {
+  /// A whole file of a container found by `probe_audio`, or raw samples of `input_format`
+  pub input_buffer: Buffer,
+  /// `auto` (the default) or a raw format descriptor like `s16le:2:44100`
+  pub input_format: Option<String>,
+  pub sample_rate_output: u32,
+  pub output_container: OutputContainer,
+  /// Sample format of the output, as in `ArgsAudioFile`, `DataType.F32` by default
+  pub type_of_bin_data: Option<DataType>,
+  /// FLAC compression level, from 0 (fastest) to 8 (smallest), 5 by default
+  pub compression_level: Option<u8>,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
+}
+
+#[napi(object)]
+pub struct ReSampledContainer {
+  /// The output file, or interleaved samples of `type_of_bin_data` for `OutputContainer.Raw`
+  pub buffer: Buffer,
+  /// Sample rate of the input, found in its header
+  pub sample_rate_input: u32,
+  /// Channels of the input, kept in the output
+  pub channels: u32,
+}
+
+/**
+ * Decodes a file held in memory, resamples it and encodes it in `output_container` without any file
+ * on disk. A WAV output keeps the metadata chunks of a WAV input, like `re_sample_audio_file`
+ */
+#[napi]
+pub fn re_sample_container_buffer(args: ArgsContainerBuffer) -> Result<ReSampledContainer> {
+  let ArgsContainerBuffer {
+    input_buffer,
+    input_format,
+    sample_rate_output,
+    output_container,
+    type_of_bin_data,
+    compression_level,
+    start,
+    end,
+    range_unit,
+  } = args;
+  let type_of_bin_data = type_of_bin_data.unwrap_or(DataType::F32);
+  let (detected_format, mut source) =
+    buffer_source(&input_buffer, input_format.as_deref().unwrap_or("auto"))?;
+  let input_spec = source.spec();
+  let range = frame_range(start, end, range_unit, input_spec.sample_rate)?;
+  let wav_chunks = match (detected_format, &output_container) {
+    (
+      Some(InputFormat::Wav),
+      OutputContainer::Wav | OutputContainer::Rf64 | OutputContainer::Wave64,
+    ) => {
+      let mut chunks = wav_metadata(Cursor::new(&input_buffer[..])).map_err(Error::from_reason)?;
+      if range != FrameRange::default() {
+        cut_wav_metadata(&mut chunks, range.start);
+      }
+      rescale_wav_metadata(&mut chunks, input_spec.sample_rate, sample_rate_output);
+      chunks
+    }
+    _ => Vec::new(),
+  };
+  let spec = AudioSpec {
+    sample_rate: sample_rate_output,
+    channels: input_spec.channels,
+    bits_per_sample: flac_bits_per_sample(&type_of_bin_data),
+  };
+  let file = MemoryFile::default();
+  let mut writer = create_sink(
+    file.clone(),
+    &output_container,
+    spec,
+    &type_of_bin_data,
+    compression_level,
+    wav_chunks,
+  )
+  .map_err(Error::from_reason)?;
+  re_sample_source_into_sink(source.as_mut(), sample_rate_output, range, writer.as_mut())
+    .map_err(Error::from_reason)?;
+  let buffer = file.take();
+
+  Ok(ReSampledContainer {
+    buffer: buffer.into(),
+    sample_rate_input: input_spec.sample_rate,
+    channels: input_spec.channels as u32,
+  })
+}
+
+#[napi(object)]
+pub struct ProbedAudio {
+  /// `flac`, `ogg`, `mp3`, `aiff`, `wav`, `au`, `caf`, or `raw` when no container is recognized
+  pub container: String,
+  /// Like `pcm_s16le`, `flac`, `vorbis`, `mp3`, `opus` or `adpcm_ima_qt`, missing for raw input
+  pub codec: Option<String>,
+  pub sample_rate: Option<u32>,
+  pub channels: Option<u32>,
+  /// `s16le`, `mulaw`... for PCM, `s16` / `s24` for lossless codecs and `f32` for lossy ones
+  pub sample_format: Option<String>,
+  /// In seconds
+  pub duration: Option<f64>,
+  pub frames: Option<i64>,
+}
+
+/**
+ * Finds the container of a file or an in-memory file from its magic bytes, whatever its extension,
+ * and describes its stream. Input without a known container is reported as `raw`
+ */
+#[napi]
+pub fn probe_audio(input: Either<Buffer, String>) -> Result<ProbedAudio> {
+  let (format, media): (_, Box<dyn MediaSource>) = match input {
+    Either::A(buffer) => (
+      InputFormat::from_magic(&buffer),
+      Box::new(Cursor::new(buffer.to_vec())),
+    ),
+    Either::B(path) => (
+      InputFormat::sniff(&path).map_err(Error::from_reason)?,
+      Box::new(
+        File::open(&path)
+          .map_err(|err| Error::from_reason(format!("Can't open file {} : {}", path, err)))?,
+      ),
+    ),
+  };
+  let Some(format) = format else {
+    return Ok(ProbedAudio {
+      container: "raw".to_string(),
+      codec: None,
+      sample_rate: None,
+      channels: None,
+      sample_format: None,
+      duration: None,
+      frames: None,
+    });
+  };
+  let mut source = format.decoder(media).map_err(Error::from_reason)?;
+  let probe = probe_source(format, source.as_mut()).map_err(Error::from_reason)?;
+  Ok(ProbedAudio {
+    container: probe.format.container().to_string(),
+    duration: Some(probe.duration()),
+    codec: Some(probe.codec),
+    sample_rate: Some(probe.spec.sample_rate),
+    channels: Some(probe.spec.channels as u32),
+    sample_format: Some(probe.sample_format),
+    frames: Some(probe.nbr_frames as i64),
+  })
+}
+
+#[napi(object)]
+pub struct ArgsSamplePositions {
+  /// Frame indices at the input sample rate, like markers or region bounds
+  pub positions: Vec<i64>,
+  pub sample_rate_input: u32,
+  pub sample_rate_output: u32,
+}
+
+/**
+ * Rescales frame indices of the input to the frames of the same audio in the resampled output, the
+ * resampler delay being already compensated in every output of this module
+ */
+#[napi]
+pub fn rescale_sample_positions(args: ArgsSamplePositions) -> Result<Vec<i64>> {
+  let ArgsSamplePositions {
+    positions,
+    sample_rate_input,
+    sample_rate_output,
+  } = args;
+  if sample_rate_input == 0 || sample_rate_output == 0 {
+    return Err(Error::from_reason(format!(
+      "Invalid sample rates {} -> {}",
+      sample_rate_input, sample_rate_output
+    )));
+  }
+  positions
+    .into_iter()
+    .map(|position| {
+      u64::try_from(position)
+        .map(|position| rescale_position(position, sample_rate_input, sample_rate_output) as i64)
+        .map_err(|_| Error::from_reason(format!("Invalid sample position {}", position)))
+    })
+    .collect()
 }
 
 #[napi(object)]
 pub struct ArgsAudioInt16Buffer {
   pub args_audio_to_re_sample: ArgsAudioToReSample,
   pub input_int16_buffer: Buffer,
+  /// Start of the part of the input to resample, in seconds or in input frames with `range_unit`
+  pub start: Option<f64>,
+  /// End of the part of the input to resample, excluded, the end of the input by default
+  pub end: Option<f64>,
+  /// Unit of `start` and `end`, `Seconds` by default
+  pub range_unit: Option<RangeUnit>,
 }
 
 #[napi]
-pub fn re_sample_int_16_buffer(args: ArgsAudioInt16Buffer) -> Buffer {
+pub fn re_sample_int_16_buffer(args: ArgsAudioInt16Buffer) -> Result<Buffer> {
   let ArgsAudioInt16Buffer {
     args_audio_to_re_sample,
     input_int16_buffer,
+    start,
+    end,
+    range_unit,
   } = args;
 
   let ArgsAudioToReSample {
@@ -175,13 +1947,14 @@ pub fn re_sample_int_16_buffer(args: ArgsAudioInt16Buffer) -> Buffer {
     i16_data[0].len(),
   );
 
-  let output_data = re_sample_audio_buffer(
+  let range = frame_range(start, end, range_unit, sample_rate_input as u32)?;
+  let output_data = re_sample_planar_range(
     i16_data,
     sample_rate_input,
     sample_rate_output,
     channels,
-    channels,
-  );
+    range,
+  )?;
 
   let convert_i16_back_time = Instant::now();
 
@@ -200,7 +1973,31 @@ pub fn re_sample_int_16_buffer(args: ArgsAudioInt16Buffer) -> Buffer {
   let mut buffer: Vec<u8> = Vec::new();
   buffer.extend(i16_ouput.iter().flat_map(|&f| f.to_le_bytes()));
 
-  buffer.into()
+  Ok(buffer.into())
+}
+
+/**
+ * Resamples `range` of planar frames, the whole of them through `re_sample_audio_buffer` when no
+ * range is given
+ */
+fn re_sample_planar_range(
+  data: Vec<Vec<f32>>,
+  sample_rate_input: u16,
+  sample_rate_output: u16,
+  channels: u8,
+  range: FrameRange,
+) -> Result<Vec<f32>> {
+  if range == FrameRange::default() {
+    return Ok(re_sample_audio_buffer(
+      data,
+      sample_rate_input,
+      sample_rate_output,
+      channels,
+      channels,
+    ));
+  }
+  let mut source = PlanarSource::new(data, sample_rate_input as u32);
+  re_sample_audio_source(&mut source, sample_rate_output as u32, range).map_err(Error::from_reason)
 }
 
 /**
@@ -320,4 +2117,65 @@ mod tests {
 
     assert_eq!(result.len(), 10);
   }
+
+  #[test]
+  fn test_samples_to_bytes_clamps_and_rounds() {
+    let bytes = samples_to_bytes(&[1.5, -1.5, 0.5, 0.00002], &DataType::I16);
+    let samples: Vec<i16> = bytes
+      .chunks(2)
+      .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+      .collect();
+    assert_eq!(samples, [32767, -32767, 16384, 1]);
+    assert_eq!(
+      samples_to_bytes(&[0.25, -1.5], &DataType::F32),
+      [0.25f32.to_le_bytes(), (-1.5f32).to_le_bytes()].concat()
+    );
+  }
+
+  #[test]
+  fn test_commit_outputs_checks_every_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.raw");
+    std::fs::write(&input, b"input").unwrap();
+    let input = input.to_str().unwrap();
+    let paths: Vec<String> = (0..3)
+      .map(|index| segment_path(dir.path().join("part.raw").to_str().unwrap(), index))
+      .collect();
+    let output_files = |overwrite: bool| {
+      paths
+        .iter()
+        .map(|path| OutputFile::create(path, overwrite).unwrap())
+        .collect::<Vec<_>>()
+    };
+
+    // A later segment existing fails the whole output before any rename
+    std::fs::write(&paths[1], b"existing").unwrap();
+    let err = commit_outputs(
+      output_files(false),
+      input,
+      WritePolicy::ErrorIfExists,
+      OutputSync::None,
+    )
+    .unwrap_err();
+    assert!(matches!(err.status, FileErrorCode::OutputExists));
+    assert!(!Path::new(&paths[0]).exists());
+
+    // Only the segments missing or older than the input are replaced
+    let committed = commit_outputs(
+      output_files(true),
+      input,
+      WritePolicy::SkipIfUpToDate,
+      OutputSync::None,
+    );
+    assert!(matches!(committed, Ok(false)));
+    assert_eq!(std::fs::read(&paths[1]).unwrap(), b"existing");
+    assert!(paths.iter().all(|path| Path::new(path).exists()));
+    let committed = commit_outputs(
+      output_files(true),
+      input,
+      WritePolicy::SkipIfUpToDate,
+      OutputSync::None,
+    );
+    assert!(matches!(committed, Ok(true)));
+  }
 }
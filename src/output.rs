@@ -0,0 +1,251 @@
+use std::fs::File;
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use tempfile::NamedTempFile;
+
+/**
+ An output file written under a temporary name next to its final path and renamed over it once
+ complete, so readers of the path only ever see the previous file or the whole new one. The
+ temporary file is removed if it is dropped before `commit`. Without `overwrite`, `commit` fails
+ instead of replacing a file created at the path in the meantime.
+*/
+pub struct OutputFile {
+  temp: NamedTempFile,
+  path: PathBuf,
+  overwrite: bool,
+}
+
+impl OutputFile {
+  pub fn create(path: &str, overwrite: bool) -> Result<Self, String> {
+    let path = PathBuf::from(path);
+    let name = path
+      .file_name()
+      .ok_or_else(|| format!("Output path {} is not a file", path.display()))?;
+    // Hidden, so watchers of the directory skip it
+    let prefix = format!(".{}.", name.to_string_lossy());
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(".tmp");
+    // Created with the mode of File::create, masked by the umask, instead of 0600
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      builder.permissions(Permissions::from_mode(0o666));
+    }
+    let temp = builder
+      .tempfile_in(parent_directory(&path))
+      .map_err(|err| format!("Cannot create output file : {}", err))?;
+    debug!(
+      "Writing {} through {}",
+      path.display(),
+      temp.path().display()
+    );
+    Ok(OutputFile {
+      temp,
+      path,
+      overwrite,
+    })
+  }
+
+  /**
+   The final path of the file.
+  */
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /**
+   A buffered writer to the temporary file, which has to be flushed before `commit`.
+  */
+  pub fn writer(&self) -> Result<BufWriter<File>, String> {
+    let file = self
+      .temp
+      .as_file()
+      .try_clone()
+      .map_err(|err| format!("Cannot create output file : {}", err))?;
+    Ok(BufWriter::new(file))
+  }
+
+  /**
+   Renames the temporary file over the final path. `sync_file` flushes its content to the disk
+   before, so a crash cannot leave a renamed but empty file, and `sync_directory` flushes the rename
+   itself after.
+  */
+  pub fn commit(self, sync_file: bool, sync_directory: bool) -> Result<(), String> {
+    if sync_file {
+      self
+        .temp
+        .as_file()
+        .sync_all()
+        .map_err(|err| format!("Cannot sync output file : {}", err))?;
+    }
+    let OutputFile {
+      temp,
+      path,
+      overwrite,
+    } = self;
+    let persisted = if overwrite {
+      temp.persist(&path)
+    } else {
+      temp.persist_noclobber(&path)
+    };
+    persisted.map_err(|err| format!("Cannot move output file to {} : {}", path.display(), err))?;
+    if sync_directory {
+      sync_parent(&path)?;
+    }
+    Ok(())
+  }
+}
+
+/**
+ Path of one of the files of a split output: `template` with its `{name}` placeholders replaced by
+ `value`, or `_<value>` added before its extension when it has none.
+*/
+pub fn template_path(template: &str, name: &str, value: &str) -> String {
+  let placeholder = format!("{{{}}}", name);
+  if template.contains(&placeholder) {
+    return template.replace(&placeholder, value);
+  }
+  let path = Path::new(template);
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+  let file_name = match path.extension() {
+    Some(extension) => format!("{}_{}.{}", stem, value, extension.to_string_lossy()),
+    None => format!("{}_{}", stem, value),
+  };
+  path
+    .with_file_name(file_name)
+    .to_string_lossy()
+    .into_owned()
+}
+
+/**
+ Whether `output` exists and was modified after `input`, like the targets of make.
+*/
+pub fn is_up_to_date(output: &Path, input: &Path) -> bool {
+  let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+  match (modified(output), modified(input)) {
+    (Ok(output), Ok(input)) => output >= input,
+    _ => false,
+  }
+}
+
+/**
+ The directory of a file path, `.` for a bare file name.
+*/
+pub fn parent_directory(path: &Path) -> &Path {
+  match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new("."),
+  }
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<(), String> {
+  File::open(parent_directory(path))
+    .and_then(|directory| directory.sync_all())
+    .map_err(|err| format!("Cannot sync output directory : {}", err))
+}
+
+// Renames are not synced through a directory handle outside of unix
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<(), String> {
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  fn file_names(directory: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(directory)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+      .collect();
+    names.sort();
+    names
+  }
+
+  #[test]
+  fn test_output_file_replaces_the_path_on_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.raw");
+    std::fs::write(&path, b"previous").unwrap();
+
+    let output = OutputFile::create(path.to_str().unwrap(), true).unwrap();
+    let mut writer = output.writer().unwrap();
+    writer.write_all(b"resampled").unwrap();
+    writer.flush().unwrap();
+    // Until the commit, readers still see the previous file
+    assert_eq!(std::fs::read(&path).unwrap(), b"previous");
+    assert_eq!(file_names(dir.path()).len(), 2);
+
+    output.commit(true, true).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"resampled");
+    assert_eq!(file_names(dir.path()), ["output.raw"]);
+  }
+
+  #[test]
+  fn test_output_file_is_removed_without_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.raw");
+
+    let output = OutputFile::create(path.to_str().unwrap(), true).unwrap();
+    output.writer().unwrap().write_all(b"partial").unwrap();
+    drop(output);
+    assert!(file_names(dir.path()).is_empty());
+  }
+
+  #[test]
+  fn test_output_file_does_not_replace_files_created_meanwhile() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.raw");
+
+    let output = OutputFile::create(path.to_str().unwrap(), false).unwrap();
+    std::fs::write(&path, b"concurrent").unwrap();
+    assert!(output.commit(false, false).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"concurrent");
+    assert_eq!(file_names(dir.path()), ["output.raw"]);
+  }
+
+  #[test]
+  fn test_template_path() {
+    assert_eq!(
+      template_path("out/{segment}.wav", "segment", "001"),
+      "out/001.wav"
+    );
+    assert_eq!(
+      template_path("out/call.wav", "segment", "001"),
+      "out/call_001.wav"
+    );
+    assert_eq!(
+      template_path("call.tar.raw", "channel", "1"),
+      "call.tar_1.raw"
+    );
+    assert_eq!(template_path("out/call", "channel", "0"), "out/call_0");
+  }
+
+  #[test]
+  fn test_is_up_to_date() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.wav");
+    let output = dir.path().join("output.wav");
+    std::fs::write(&input, b"input").unwrap();
+    assert!(!is_up_to_date(&output, &input));
+
+    std::fs::write(&output, b"output").unwrap();
+    let input_time = std::fs::metadata(&input).unwrap().modified().unwrap();
+    let file = std::fs::File::options().write(true).open(&output).unwrap();
+    file
+      .set_modified(input_time + std::time::Duration::from_secs(1))
+      .unwrap();
+    assert!(is_up_to_date(&output, &input));
+    file
+      .set_modified(input_time - std::time::Duration::from_secs(1))
+      .unwrap();
+    assert!(!is_up_to_date(&output, &input));
+  }
+}
@@ -0,0 +1,90 @@
+use log::debug;
+
+use crate::codecs::{AudioSource, AudioSpec, InputFormat};
+
+/**
+ What the header, or a decoding pass when the header has no length, tells about an encoded input.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioProbe {
+  pub format: InputFormat,
+  pub spec: AudioSpec,
+  pub codec: String,
+  pub sample_format: String,
+  pub nbr_frames: u64,
+}
+
+impl AudioProbe {
+  pub fn duration(&self) -> f64 {
+    self.nbr_frames as f64 / self.spec.sample_rate as f64
+  }
+}
+
+/**
+ Describes the stream of a decoder, decoding it to the end when its header does not announce its
+ number of frames (Ogg Opus, MP3 without a Xing tag, WAV or AU running to the end of the file, ...).
+*/
+pub fn probe_source(
+  format: InputFormat,
+  source: &mut dyn AudioSource,
+) -> Result<AudioProbe, String> {
+  let nbr_frames = match source.nbr_frames() {
+    Some(nbr_frames) => nbr_frames,
+    None => {
+      debug!(
+        "{:?} header has no length, decoding it to count its frames",
+        format
+      );
+      let mut nbr_frames = 0;
+      while let Some(block) = source.read_block()? {
+        nbr_frames += block.first().map_or(0, Vec::len) as u64;
+      }
+      nbr_frames
+    }
+  };
+  Ok(AudioProbe {
+    format,
+    spec: source.spec(),
+    codec: source.codec(),
+    sample_format: source.sample_format(),
+    nbr_frames,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::au::AuHeader;
+  use crate::codecs::pcm::{PcmWriter, SampleFormat};
+  use crate::codecs::AudioSink;
+  use std::io::Cursor;
+
+  fn au_file(data_len_known: bool) -> Vec<u8> {
+    let header = AuHeader::new(8000, 2, SampleFormat::MuLaw).unwrap();
+    let mut writer =
+      PcmWriter::new(Cursor::new(Vec::new()), header, SampleFormat::MuLaw, 2).unwrap();
+    writer.write_samples(&vec![0.1; 8000 * 2]).unwrap();
+    writer.finish().unwrap();
+    let mut file = writer.into_inner().into_inner();
+    if !data_len_known {
+      file[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    }
+    file
+  }
+
+  #[test]
+  fn test_probe_source() {
+    for data_len_known in [true, false] {
+      let file = au_file(data_len_known);
+      let format = InputFormat::from_magic(&file).unwrap();
+      let mut source = format.decoder(Box::new(Cursor::new(file))).unwrap();
+      let probe = probe_source(format, source.as_mut()).unwrap();
+      assert_eq!(probe.format.container(), "au");
+      assert_eq!(probe.codec, "pcm_mulaw");
+      assert_eq!(probe.sample_format, "mulaw");
+      assert_eq!(probe.spec.channels, 2);
+      assert_eq!(probe.nbr_frames, 8000);
+      assert_eq!(probe.duration(), 1.0);
+    }
+  }
+}
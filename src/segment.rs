@@ -0,0 +1,284 @@
+use log::debug;
+
+use crate::codecs::AudioSink;
+
+/**
+ A piece of a split output, in output frames.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+  pub index: usize,
+  pub start: u64,
+  pub frames: u64,
+}
+
+/**
+ How an output is split: segments of `frames` frames, each one starting `overlap` frames before the
+ end of the previous one. With a `zero_crossing_window`, each cut moves to the closest frame, at
+ most that many frames away, where the sum of the channels changes sign.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentLayout {
+  pub frames: u64,
+  pub overlap: u64,
+  pub zero_crossing_window: u64,
+}
+
+impl SegmentLayout {
+  pub fn new(frames: u64, overlap: u64, zero_crossing_window: u64) -> Result<Self, String> {
+    if frames == 0 || overlap >= frames {
+      return Err(format!(
+        "Invalid segments of {} frames overlapping by {} frames",
+        frames, overlap
+      ));
+    }
+    // Cuts stay inside their segment and after the start of the next one
+    let zero_crossing_window = zero_crossing_window.min((frames - overlap - 1) / 2);
+    Ok(SegmentLayout {
+      frames,
+      overlap,
+      zero_crossing_window,
+    })
+  }
+}
+
+/**
+ Splits the interleaved samples written to it into segments, each one written to the sink returned
+ by `open` for it and finished at its cut. Segments are opened once they have a first frame, so the
+ output never ends with an empty one. Only the frames around the next cut are held in memory.
+*/
+pub struct SegmentSink<F: FnMut(&Segment) -> Result<Box<dyn AudioSink>, String>> {
+  open: F,
+  channels: usize,
+  layout: SegmentLayout,
+  current: Option<Box<dyn AudioSink>>,
+  segment_start: u64,
+  // Frame up to which the current segment is written
+  written: u64,
+  // Interleaved frames from `buffer_start` to the last frame received
+  buffer: Vec<f32>,
+  buffer_start: u64,
+  segments: Vec<Segment>,
+}
+
+impl<F: FnMut(&Segment) -> Result<Box<dyn AudioSink>, String>> SegmentSink<F> {
+  pub fn new(channels: usize, layout: SegmentLayout, open: F) -> Self {
+    SegmentSink {
+      open,
+      channels,
+      layout,
+      current: None,
+      segment_start: 0,
+      written: 0,
+      buffer: Vec::new(),
+      buffer_start: 0,
+      segments: Vec::new(),
+    }
+  }
+
+  /**
+   The segments written so far, all of them once the sink is finished.
+  */
+  pub fn segments(&self) -> &[Segment] {
+    &self.segments
+  }
+
+  fn received(&self) -> u64 {
+    self.buffer_start + (self.buffer.len() / self.channels) as u64
+  }
+
+  fn frames(&self, from: u64, to: u64) -> &[f32] {
+    let at = |frame: u64| (frame - self.buffer_start) as usize * self.channels;
+    &self.buffer[at(from)..at(to)]
+  }
+
+  // Writes the frames of the current segment up to `to`, opening it first if needed
+  fn write_until(&mut self, to: u64) -> Result<(), String> {
+    if to <= self.written {
+      return Ok(());
+    }
+    if self.current.is_none() {
+      let segment = Segment {
+        index: self.segments.len(),
+        start: self.segment_start,
+        frames: 0,
+      };
+      debug!("Opening segment {:?}", segment);
+      self.current = Some((self.open)(&segment)?);
+    }
+    let samples = self.frames(self.written, to).to_vec();
+    self.current.as_mut().unwrap().write_samples(&samples)?;
+    self.written = to;
+    Ok(())
+  }
+
+  fn close(&mut self, end: u64) -> Result<(), String> {
+    if let Some(mut sink) = self.current.take() {
+      sink.finish()?;
+      self.segments.push(Segment {
+        index: self.segments.len(),
+        start: self.segment_start,
+        frames: end - self.segment_start,
+      });
+    }
+    Ok(())
+  }
+
+  /**
+   The closest frame to `boundary`, in the window around it, where the sum of the channels
+   changes sign or is zero, `boundary` when there is none.
+  */
+  fn cut_near(&self, boundary: u64) -> u64 {
+    let window = self.layout.zero_crossing_window;
+    let level = |frame: u64| -> f32 { self.frames(frame, frame + 1).iter().sum() };
+    let is_crossing = |frame: u64| {
+      let (before, after) = (level(frame - 1), level(frame));
+      after == 0.0 || (before < 0.0) != (after < 0.0)
+    };
+    (0..=window)
+      .flat_map(|distance| [boundary - distance, boundary + distance])
+      .find(|&frame| frame > self.buffer_start && is_crossing(frame))
+      .unwrap_or(boundary)
+  }
+
+  // Cuts every segment that can be decided with the frames received
+  fn flush(&mut self) -> Result<(), String> {
+    let SegmentLayout {
+      frames,
+      overlap,
+      zero_crossing_window: window,
+    } = self.layout;
+    loop {
+      let boundary = self.segment_start + frames;
+      if self.received() <= boundary + window {
+        // The frames before the window of the next cut belong to the current segment
+        self.write_until(std::cmp::min(self.received(), boundary - window))?;
+        break;
+      }
+      let cut = if window > 0 {
+        self.cut_near(boundary)
+      } else {
+        boundary
+      };
+      self.write_until(cut)?;
+      self.close(cut)?;
+      self.segment_start = cut - overlap;
+      self.written = self.segment_start;
+    }
+    // Keeps the frames still needed by the next cut and the overlap of the next segment
+    let keep_from = std::cmp::min(
+      self.written,
+      (self.segment_start + frames).saturating_sub(window + overlap + 1),
+    )
+    .max(self.buffer_start);
+    let drained = (keep_from - self.buffer_start) as usize * self.channels;
+    self.buffer.drain(..drained);
+    self.buffer_start = keep_from;
+    Ok(())
+  }
+}
+
+impl<F: FnMut(&Segment) -> Result<Box<dyn AudioSink>, String>> AudioSink for SegmentSink<F> {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    self.buffer.extend_from_slice(samples);
+    self.flush()
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    let received = self.received();
+    self.write_until(received)?;
+    self.close(received)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  struct SharedSink(Rc<RefCell<Vec<f32>>>);
+
+  impl AudioSink for SharedSink {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+      self.0.borrow_mut().extend_from_slice(samples);
+      Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+      Ok(())
+    }
+  }
+
+  fn split(
+    samples: &[f32],
+    channels: usize,
+    layout: SegmentLayout,
+  ) -> (Vec<Segment>, Vec<Vec<f32>>) {
+    let outputs = Rc::new(RefCell::new(Vec::new()));
+    let opened = outputs.clone();
+    let mut sink = SegmentSink::new(channels, layout, move |_: &Segment| {
+      let output = Rc::new(RefCell::new(Vec::new()));
+      opened.borrow_mut().push(output.clone());
+      Ok(Box::new(SharedSink(output)) as Box<dyn AudioSink>)
+    });
+    for block in samples.chunks(channels * 37) {
+      sink.write_samples(block).unwrap();
+    }
+    sink.finish().unwrap();
+    let outputs = outputs
+      .borrow()
+      .iter()
+      .map(|output| output.borrow().clone())
+      .collect();
+    (sink.segments().to_vec(), outputs)
+  }
+
+  #[test]
+  fn test_segments_with_overlap() {
+    let samples: Vec<f32> = (0..1100).map(|i| i as f32).collect();
+    let (segments, outputs) = split(&samples, 2, SegmentLayout::new(200, 50, 0).unwrap());
+    let starts: Vec<u64> = segments.iter().map(|segment| segment.start).collect();
+    assert_eq!(starts, [0, 150, 300, 450]);
+    assert_eq!(segments[3].frames, 100);
+    // The last segment reaching the end of the output is not followed by its overlap alone
+    let (segments, _) = split(&samples[..1000], 2, SegmentLayout::new(200, 50, 0).unwrap());
+    assert_eq!(segments.len(), 3);
+    for (segment, output) in segments.iter().zip(outputs) {
+      let start = segment.start as usize * 2;
+      assert_eq!(output, samples[start..start + segment.frames as usize * 2]);
+    }
+  }
+
+  #[test]
+  fn test_segments_are_cut_at_zero_crossings() {
+    // Changes sign between frames 104 and 105, then between frames 209 and 210
+    let samples: Vec<f32> = (0..300)
+      .map(|i| match i {
+        0..=104 => 0.5,
+        105..=209 => -0.5,
+        _ => 0.5,
+      })
+      .collect();
+    let (segments, outputs) = split(&samples, 1, SegmentLayout::new(100, 0, 10).unwrap());
+    let starts: Vec<u64> = segments.iter().map(|segment| segment.start).collect();
+    assert_eq!(starts, [0, 105, 210]);
+    assert_eq!(outputs.concat(), samples);
+    // A cut without a crossing in its window stays on its boundary
+    let (segments, _) = split(&[0.5; 300], 1, SegmentLayout::new(100, 0, 10).unwrap());
+    assert_eq!(segments.len(), 3);
+    assert_eq!(segments[1].start, 100);
+  }
+
+  #[test]
+  fn test_invalid_segment_layouts() {
+    assert!(SegmentLayout::new(0, 0, 0).is_err());
+    assert!(SegmentLayout::new(100, 100, 0).is_err());
+    assert_eq!(
+      SegmentLayout::new(100, 20, 1000)
+        .unwrap()
+        .zero_crossing_window,
+      39
+    );
+  }
+}
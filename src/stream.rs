@@ -0,0 +1,684 @@
+use log::debug;
+use rubato::{FastFixedOut, PolynomialDegree, Resampler};
+
+use crate::codecs::{AudioSink, AudioSource, AudioSpec};
+use crate::helpers::interleave_frames;
+
+/**
+ Incremental counterpart of `re_sample_audio_buffer`.
+
+ Blocks of planar frames of any length can be pushed with `process`, the resampler is fed with full
+ chunks as soon as enough frames are pending, and `finish` flushes the remaining frames as a last
+ partial chunk. The resampler delay is skipped and the output is cut at
+ `nbr_input_frames * fs_out / fs_in`, so the concatenation of every returned block is the same as
+ what `re_sample_audio_buffer` returns for the whole input.
+
+ # Example
+
+ ```
+ let mut resampler = StreamResampler::new(44100, 16000, 2).unwrap();
+ let mut output = Vec::new();
+ for block in blocks {
+   interleave_frames(&resampler.process(&block).unwrap(), &mut output);
+ }
+ interleave_frames(&resampler.finish().unwrap(), &mut output);
+ ```
+*/
+pub struct StreamResampler {
+  resampler: FastFixedOut<f32>,
+  fs_in: u32,
+  fs_out: u32,
+  pending: Vec<Vec<f32>>,
+  ready: Vec<Vec<f32>>,
+  outbuffer: Vec<Vec<f32>>,
+  delay_to_skip: usize,
+  nbr_input_frames: usize,
+  nbr_output_frames: usize,
+}
+
+impl StreamResampler {
+  pub fn new(
+    input_sample_rate: u32,
+    output_sample_rate: u32,
+    channels: usize,
+  ) -> Result<Self, String> {
+    if channels == 0 {
+      return Err("Cannot resample a stream without channels".to_string());
+    }
+    if input_sample_rate == 0 || output_sample_rate == 0 {
+      return Err(format!(
+        "Invalid sample rates {} -> {}",
+        input_sample_rate, output_sample_rate
+      ));
+    }
+    let f_ratio = output_sample_rate as f64 / input_sample_rate as f64;
+    let resampler =
+      FastFixedOut::<f32>::new(f_ratio, 1.1, PolynomialDegree::Septic, 1024, channels)
+        .map_err(|err| err.to_string())?;
+    let delay_to_skip = resampler.output_delay();
+    let outbuffer = vec![vec![0.0f32; resampler.output_frames_max()]; channels];
+    debug!(
+      "StreamResampler ratio is {:?} with a delay of {:?} frames",
+      f_ratio, delay_to_skip
+    );
+
+    Ok(StreamResampler {
+      resampler,
+      fs_in: input_sample_rate,
+      fs_out: output_sample_rate,
+      pending: vec![Vec::new(); channels],
+      ready: vec![Vec::new(); channels],
+      outbuffer,
+      delay_to_skip,
+      nbr_input_frames: 0,
+      nbr_output_frames: 0,
+    })
+  }
+
+  pub fn channels(&self) -> usize {
+    self.pending.len()
+  }
+
+  /**
+   Pushes a block of planar frames and returns the output frames that are already final.
+   The block must have one vector per channel, all of the same length.
+  */
+  pub fn process(&mut self, block: &[Vec<f32>]) -> Result<Vec<Vec<f32>>, String> {
+    if block.len() != self.channels() {
+      return Err(format!(
+        "Block has {} channels but the resampler expects {}",
+        block.len(),
+        self.channels()
+      ));
+    }
+    for (pending, channel) in self.pending.iter_mut().zip(block.iter()) {
+      pending.extend_from_slice(channel);
+    }
+    self.nbr_input_frames += block[0].len();
+
+    while self.pending[0].len() >= self.resampler.input_frames_next() {
+      let (nbr_in, nbr_out) = self
+        .resampler
+        .process_into_buffer(&self.pending, &mut self.outbuffer, None)
+        .map_err(|err| err.to_string())?;
+      for chan in self.pending.iter_mut() {
+        chan.drain(..nbr_in);
+      }
+      self.keep_output(nbr_out);
+    }
+    Ok(self.release())
+  }
+
+  /**
+   Flushes the last partial chunk and returns the remaining output frames.
+  */
+  pub fn finish(&mut self) -> Result<Vec<Vec<f32>>, String> {
+    if !self.pending[0].is_empty() {
+      let (_nbr_in, nbr_out) = self
+        .resampler
+        .process_partial_into_buffer(Some(&self.pending), &mut self.outbuffer, None)
+        .map_err(|err| err.to_string())?;
+      for chan in self.pending.iter_mut() {
+        chan.clear();
+      }
+      self.keep_output(nbr_out);
+    }
+    debug!(
+      "StreamResampler finished with {:?} input frames and {:?} output frames",
+      self.nbr_input_frames,
+      self.expected_output_frames()
+    );
+    Ok(self.release())
+  }
+
+  fn expected_output_frames(&self) -> usize {
+    (self.nbr_input_frames as f64 * self.fs_out as f64 / self.fs_in as f64) as usize
+  }
+
+  // Moves the fresh output after the resampler delay into `ready`
+  fn keep_output(&mut self, nbr_out: usize) {
+    let skipped = std::cmp::min(self.delay_to_skip, nbr_out);
+    self.delay_to_skip -= skipped;
+    for (ready, out) in self.ready.iter_mut().zip(self.outbuffer.iter()) {
+      ready.extend_from_slice(&out[skipped..nbr_out]);
+    }
+  }
+
+  // Hands out the ready frames that fit below the expected output length
+  fn release(&mut self) -> Vec<Vec<f32>> {
+    let allowed = self
+      .expected_output_frames()
+      .saturating_sub(self.nbr_output_frames);
+    let nbr_frames = std::cmp::min(allowed, self.ready[0].len());
+    self.nbr_output_frames += nbr_frames;
+    self
+      .ready
+      .iter_mut()
+      .map(|ready| ready.drain(..nbr_frames).collect())
+      .collect()
+  }
+}
+
+/**
+ Position in the output of `StreamResampler` (and `re_sample_audio_buffer`) of an input frame, for
+ markers, cue points or loops given in input frames.
+
+ The resampler output lags its input by `output_delay()` frames, which are skipped before any frame
+ is handed out, so an input frame keeps its time in the output: its position is scaled by
+ `fs_out / fs_in` and rounded to the nearest output frame.
+*/
+pub fn rescale_position(position: u64, input_sample_rate: u32, output_sample_rate: u32) -> u64 {
+  let fs_in = input_sample_rate as u128;
+  ((position as u128 * output_sample_rate as u128 + fs_in / 2) / fs_in) as u64
+}
+
+/**
+ Input frames read before the start of a range and after its end, so the resampler sees the same
+ neighbourhood as when the whole input is resampled. The septic interpolation only reaches 4 frames
+ around each output frame.
+*/
+const CONTEXT_FRAMES: u64 = 64;
+
+// Frames of the blocks of a `PlanarSource`
+const PLANAR_BLOCK_FRAMES: usize = 4096;
+
+/**
+ Part of an input to resample, in input frames, from `start` included to `end` excluded, `None`
+ running to the end of the stream. The default range is the whole stream.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameRange {
+  pub start: u64,
+  pub end: Option<u64>,
+}
+
+impl FrameRange {
+  pub fn new(start: u64, end: Option<u64>) -> Result<Self, String> {
+    match end {
+      Some(end) if end <= start => Err(format!(
+        "The end of the range ({}) must be after its start ({})",
+        end, start
+      )),
+      _ => Ok(FrameRange { start, end }),
+    }
+  }
+
+  /**
+   The frames of the range in the resampled stream, at `rescale_position` of its bounds.
+  */
+  pub fn rescale(&self, input_sample_rate: u32, output_sample_rate: u32) -> FrameRange {
+    FrameRange {
+      start: rescale_position(self.start, input_sample_rate, output_sample_rate),
+      end: self
+        .end
+        .map(|end| rescale_position(end, input_sample_rate, output_sample_rate)),
+    }
+  }
+}
+
+/**
+ Bounds, inside a block of `len` frames starting at frame `position`, of the frames of `range`.
+*/
+fn block_part(position: u64, len: u64, range: FrameRange) -> (usize, usize) {
+  let end = position + len;
+  let from = range.start.clamp(position, end);
+  let to = range
+    .end
+    .map_or(end, |range_end| range_end.clamp(from, end));
+  ((from - position) as usize, (to - position) as usize)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+  if b == 0 {
+    a
+  } else {
+    gcd(b, a % b)
+  }
+}
+
+/**
+ Drains an `AudioSource` block by block through a `StreamResampler` and returns the interleaved
+ resampled frames of `range`.
+*/
+pub fn re_sample_audio_source<S: AudioSource + ?Sized>(
+  source: &mut S,
+  output_sample_rate: u32,
+  range: FrameRange,
+) -> Result<Vec<f32>, String> {
+  let spec = source.spec();
+  debug!(
+    "Resampling source of {:?} Hz {:?} channel(s) {:?} bits to {:?} Hz",
+    spec.sample_rate, spec.channels, spec.bits_per_sample, output_sample_rate
+  );
+  let mut output = Vec::new();
+  re_sample_source_into_sink(source, output_sample_rate, range, &mut output)?;
+  Ok(output)
+}
+
+/**
+ Streams `range` of an `AudioSource` through a `StreamResampler` into an `AudioSink` and finishes
+ it. Each block is written as soon as it is resampled, so the memory used does not depend on the
+ length of the input. Returns the number of output frames.
+
+ A range is resampled from a few frames before its start, aligned on a multiple of
+ `fs_in / gcd(fs_in, fs_out)` so its output frames fall on the ones of a whole-stream resample, to a
+ few frames after its end, then cut at `FrameRange::rescale`. Its output is the same, up to float
+ rounding, as the same frames cut from the resampling of the whole stream. Sources that can seek
+ jump to the start of the context, the other ones are decoded up to it.
+*/
+pub fn re_sample_source_into_sink<S: AudioSource + ?Sized>(
+  source: &mut S,
+  output_sample_rate: u32,
+  range: FrameRange,
+  sink: &mut dyn AudioSink,
+) -> Result<u64, String> {
+  let spec = source.spec();
+  let fs_in = spec.sample_rate as u64;
+  let alignment = fs_in / gcd(fs_in, output_sample_rate as u64);
+  let context_start = range.start.saturating_sub(CONTEXT_FRAMES) / alignment * alignment;
+  let input_range = FrameRange {
+    start: context_start,
+    end: range.end.map(|end| end + CONTEXT_FRAMES),
+  };
+  let output_range = range.rescale(spec.sample_rate, output_sample_rate);
+  if range != FrameRange::default() {
+    debug!(
+      "Resampling input frames {:?} from frame {} to output frames {:?}",
+      range, context_start, output_range
+    );
+  }
+
+  // Input frame of the next block of the source
+  let mut position = if context_start > 0 && source.seek_frame(context_start)? {
+    context_start
+  } else {
+    0
+  };
+  // Output frame of the whole-stream resample of the next resampled frame
+  let mut output_position = rescale_position(context_start, spec.sample_rate, output_sample_rate);
+  let mut resampler = StreamResampler::new(spec.sample_rate, output_sample_rate, spec.channels)?;
+  let mut samples = Vec::new();
+  let mut nbr_output_frames = 0;
+  // Writes the part of the resampled frames that falls in the output range
+  let mut write = |frames: Vec<Vec<f32>>, samples: &mut Vec<f32>| {
+    let len = frames[0].len() as u64;
+    let (from, to) = block_part(output_position, len, output_range);
+    output_position += len;
+    if from == to {
+      return Ok(());
+    }
+    nbr_output_frames += (to - from) as u64;
+    samples.clear();
+    if from == 0 && to == len as usize {
+      interleave_frames(&frames, samples);
+    } else {
+      let kept: Vec<Vec<f32>> = frames
+        .iter()
+        .map(|channel| channel[from..to].to_vec())
+        .collect();
+      interleave_frames(&kept, samples);
+    }
+    sink.write_samples(samples)
+  };
+  while let Some(block) = source.read_block()? {
+    let len = block[0].len() as u64;
+    let (from, to) = block_part(position, len, input_range);
+    position += len;
+    if from == 0 && to == len as usize {
+      write(resampler.process(&block)?, &mut samples)?;
+    } else if from < to {
+      let kept: Vec<Vec<f32>> = block
+        .iter()
+        .map(|channel| channel[from..to].to_vec())
+        .collect();
+      write(resampler.process(&kept)?, &mut samples)?;
+    }
+    if input_range.end.is_some_and(|end| position >= end) {
+      break;
+    }
+  }
+  write(resampler.finish()?, &mut samples)?;
+  sink.finish()?;
+  Ok(nbr_output_frames)
+}
+
+/**
+ An `AudioSource` handing out the blocks of another one resampled to `output_sample_rate`, for the
+ outputs reading several inputs side by side. Its frames are the ones of `re_sample_audio_source`.
+*/
+pub struct ResampledSource {
+  source: Box<dyn AudioSource>,
+  resampler: Option<StreamResampler>,
+  spec: AudioSpec,
+}
+
+impl ResampledSource {
+  pub fn new(source: Box<dyn AudioSource>, output_sample_rate: u32) -> Result<Self, String> {
+    let input_spec = source.spec();
+    let resampler = StreamResampler::new(
+      input_spec.sample_rate,
+      output_sample_rate,
+      input_spec.channels,
+    )?;
+    Ok(ResampledSource {
+      source,
+      resampler: Some(resampler),
+      spec: AudioSpec {
+        sample_rate: output_sample_rate,
+        ..input_spec
+      },
+    })
+  }
+}
+
+impl AudioSource for ResampledSource {
+  fn spec(&self) -> AudioSpec {
+    self.spec
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    while let Some(resampler) = self.resampler.as_mut() {
+      let frames = match self.source.read_block()? {
+        Some(block) => resampler.process(&block)?,
+        None => {
+          let frames = resampler.finish()?;
+          self.resampler = None;
+          frames
+        }
+      };
+      if !frames[0].is_empty() {
+        return Ok(Some(frames));
+      }
+    }
+    Ok(None)
+  }
+
+  fn codec(&self) -> String {
+    self.source.codec()
+  }
+
+  fn sample_format(&self) -> String {
+    self.source.sample_format()
+  }
+
+  fn nbr_frames(&self) -> Option<u64> {
+    let input_sample_rate = self.source.spec().sample_rate as u64;
+    self
+      .source
+      .nbr_frames()
+      .map(|frames| frames * self.spec.sample_rate as u64 / input_sample_rate)
+  }
+}
+
+/**
+ An `AudioSource` over planar frames already in memory, one vector per channel, handed out in blocks
+ and seeking like the uncompressed inputs.
+*/
+pub struct PlanarSource {
+  frames: Vec<Vec<f32>>,
+  sample_rate: u32,
+  position: usize,
+}
+
+impl PlanarSource {
+  pub fn new(frames: Vec<Vec<f32>>, sample_rate: u32) -> Self {
+    PlanarSource {
+      frames,
+      sample_rate,
+      position: 0,
+    }
+  }
+
+  fn len(&self) -> usize {
+    self.frames.first().map_or(0, Vec::len)
+  }
+}
+
+impl AudioSource for PlanarSource {
+  fn spec(&self) -> AudioSpec {
+    AudioSpec {
+      sample_rate: self.sample_rate,
+      channels: self.frames.len(),
+      bits_per_sample: 32,
+    }
+  }
+
+  fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+    let len = self.len();
+    if self.position >= len {
+      return Ok(None);
+    }
+    let end = std::cmp::min(self.position + PLANAR_BLOCK_FRAMES, len);
+    let block = self
+      .frames
+      .iter()
+      .map(|channel| channel[self.position..end].to_vec())
+      .collect();
+    self.position = end;
+    Ok(Some(block))
+  }
+
+  fn codec(&self) -> String {
+    "pcm_f32le".to_string()
+  }
+
+  fn sample_format(&self) -> String {
+    "f32le".to_string()
+  }
+
+  fn nbr_frames(&self) -> Option<u64> {
+    Some(self.len() as u64)
+  }
+
+  fn seek_frame(&mut self, frame: u64) -> Result<bool, String> {
+    self.position = std::cmp::min(frame, self.len() as u64) as usize;
+    Ok(true)
+  }
+}
+
+/**
+ Collects the interleaved samples in memory.
+*/
+impl AudioSink for Vec<f32> {
+  fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+    self.extend_from_slice(samples);
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), String> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::codecs::raw::RawFormat;
+  use crate::re_sample_audio_buffer;
+
+  fn sine(frames: usize, channels: usize) -> Vec<Vec<f32>> {
+    (0..channels)
+      .map(|chan| {
+        (0..frames)
+          .map(|i| ((i as f32 * 0.01) + chan as f32).sin() * 0.5)
+          .collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_rescale_position_follows_the_resampled_audio() {
+    assert_eq!(rescale_position(44100, 44100, 16000), 16000);
+    assert_eq!(rescale_position(3, 48000, 16000), 1);
+    assert_eq!(rescale_position(0, 8000, 48000), 0);
+
+    // A click keeps its time once the output delay is skipped
+    for (fs_in, fs_out, click) in [(44100, 16000, 12345), (16000, 48000, 5000)] {
+      let mut input = vec![vec![0.0f32; 20000]];
+      input[0][click] = 1.0;
+      let output = re_sample_audio_buffer(input, fs_in, fs_out, 1, 1);
+      let peak = (0..output.len())
+        .max_by(|&a, &b| output[a].abs().total_cmp(&output[b].abs()))
+        .unwrap() as i64;
+      let expected = rescale_position(click as u64, fs_in as u32, fs_out as u32) as i64;
+      assert!((peak - expected).abs() <= 1, "{} != {}", peak, expected);
+    }
+  }
+
+  #[test]
+  fn test_stream_resampler_matches_buffer_resampling() {
+    let input = sine(20000, 2);
+    let expected = re_sample_audio_buffer(input.clone(), 44100, 16000, 2, 2);
+
+    let mut resampler = StreamResampler::new(44100, 16000, 2).unwrap();
+    let mut output = Vec::new();
+    for start in (0..20000).step_by(333) {
+      let end = std::cmp::min(start + 333, 20000);
+      let block: Vec<Vec<f32>> = input.iter().map(|c| c[start..end].to_vec()).collect();
+      interleave_frames(&resampler.process(&block).unwrap(), &mut output);
+    }
+    interleave_frames(&resampler.finish().unwrap(), &mut output);
+
+    assert_eq!(output, expected);
+  }
+
+  struct BlockSource(Vec<Vec<Vec<f32>>>);
+
+  impl AudioSource for BlockSource {
+    fn spec(&self) -> crate::codecs::AudioSpec {
+      crate::codecs::AudioSpec {
+        sample_rate: 44100,
+        channels: 2,
+        bits_per_sample: 32,
+      }
+    }
+
+    fn read_block(&mut self) -> Result<Option<Vec<Vec<f32>>>, String> {
+      Ok((!self.0.is_empty()).then(|| self.0.remove(0)))
+    }
+
+    fn codec(&self) -> String {
+      "pcm_f32le".to_string()
+    }
+
+    fn sample_format(&self) -> String {
+      "f32le".to_string()
+    }
+  }
+
+  #[derive(Default)]
+  struct BlockSink {
+    samples: Vec<f32>,
+    largest_block: usize,
+    finished: bool,
+  }
+
+  impl AudioSink for BlockSink {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+      self.largest_block = std::cmp::max(self.largest_block, samples.len());
+      self.samples.extend_from_slice(samples);
+      Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+      self.finished = true;
+      Ok(())
+    }
+  }
+
+  fn blocks(input: &[Vec<f32>]) -> Vec<Vec<Vec<f32>>> {
+    let len = input[0].len();
+    (0..len)
+      .step_by(4096)
+      .map(|start| {
+        let end = std::cmp::min(start + 4096, len);
+        input.iter().map(|c| c[start..end].to_vec()).collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_resampled_source_matches_buffer_resampling() {
+    let input = sine(30000, 2);
+    let expected = re_sample_audio_buffer(input.clone(), 44100, 16000, 2, 2);
+    let mut source = ResampledSource::new(Box::new(BlockSource(blocks(&input))), 16000).unwrap();
+    assert_eq!(source.spec().sample_rate, 16000);
+    let mut output = Vec::new();
+    while let Some(block) = source.read_block().unwrap() {
+      assert!(!block[0].is_empty());
+      interleave_frames(&block, &mut output);
+    }
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_source_is_streamed_into_sink() {
+    let input = sine(50000, 2);
+    let expected = re_sample_audio_buffer(input.clone(), 44100, 16000, 2, 2);
+    let mut sink = BlockSink::default();
+    let nbr_frames = re_sample_source_into_sink(
+      &mut BlockSource(blocks(&input)),
+      16000,
+      FrameRange::default(),
+      &mut sink,
+    )
+    .unwrap();
+    assert_eq!(sink.samples, expected);
+    assert_eq!(nbr_frames as usize * 2, expected.len());
+    assert!(sink.finished);
+    // Written block by block, never the whole output at once
+    assert!(sink.largest_block < 2 * 4096);
+  }
+
+  #[test]
+  fn test_range_matches_the_whole_resample() {
+    let input = sine(50000, 2);
+    let whole = re_sample_audio_buffer(input.clone(), 44100, 16000, 2, 2);
+    let mut interleaved = Vec::new();
+    interleave_frames(&input, &mut interleaved);
+    let raw: RawFormat = "f32le:2:44100".parse().unwrap();
+    let bytes = raw.encode(&interleaved);
+
+    for (start, end) in [
+      (0, Some(1000)),
+      (12345, Some(30001)),
+      (20000, None),
+      (49990, Some(60000)),
+    ] {
+      let range = FrameRange::new(start, end).unwrap();
+      let output_range = range.rescale(44100, 16000);
+      let whole_end = whole.len() / 2;
+      let expected = &whole[std::cmp::min(output_range.start as usize, whole_end) * 2
+        ..std::cmp::min(output_range.end.unwrap_or(u64::MAX) as usize, whole_end) * 2];
+
+      // Decoded up to the range, and seeking straight to it
+      let mut decoded = Vec::new();
+      re_sample_source_into_sink(&mut BlockSource(blocks(&input)), 16000, range, &mut decoded)
+        .unwrap();
+      let mut seeked = Vec::new();
+      let mut source = raw.source(std::io::Cursor::new(&bytes[..])).unwrap();
+      let nbr_frames = re_sample_source_into_sink(&mut source, 16000, range, &mut seeked).unwrap();
+      assert_eq!(nbr_frames as usize * 2, expected.len());
+      let planar =
+        re_sample_audio_source(&mut PlanarSource::new(input.clone(), 44100), 16000, range).unwrap();
+      for output in [decoded, seeked, planar] {
+        assert_eq!(output.len(), expected.len(), "{:?}", range);
+        for (sample, expected) in output.iter().zip(expected) {
+          assert!((sample - expected).abs() < 1e-5, "{:?}", range);
+        }
+      }
+    }
+    assert!(FrameRange::new(10, Some(10)).is_err());
+  }
+
+  #[test]
+  fn test_stream_resampler_rejects_wrong_channel_count() {
+    let mut resampler = StreamResampler::new(48000, 16000, 2).unwrap();
+    assert!(resampler.process(&sine(10, 1)).is_err());
+  }
+
+  #[test]
+  fn test_stream_resampler_needs_channels() {
+    assert!(StreamResampler::new(48000, 16000, 0).is_err());
+  }
+}